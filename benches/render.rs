@@ -0,0 +1,29 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use dustrown::{RemoteContent, Renderer, Theme};
+
+const SAMPLE: &str = include_str!("../tests/fixtures/markdown/basic.md");
+
+fn render_cold(c: &mut Criterion) {
+    c.bench_function("render_markdown (fresh renderer)", |b| {
+        b.iter(|| {
+            Renderer::new().render_markdown(
+                black_box(SAMPLE),
+                &Theme::light(),
+                RemoteContent::Allow,
+            )
+        })
+    });
+}
+
+fn render_cached(c: &mut Criterion) {
+    let renderer = Renderer::new();
+    c.bench_function("render_markdown (cached renderer)", |b| {
+        b.iter(|| {
+            renderer.render_markdown(black_box(SAMPLE), &Theme::light(), RemoteContent::Allow)
+        })
+    });
+}
+
+criterion_group!(benches, render_cold, render_cached);
+criterion_main!(benches);
@@ -0,0 +1,601 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    config,
+    core::{self, AppState, Document, DocumentSource, LoadOptions, Renderer},
+    encoding::{self, TextEncoding},
+    theme::Theme,
+};
+
+const VIEWER_USAGE: &str = "\
+Usage: dustrown [OPTIONS] [PATH...]
+       dustrown render [INPUT] [-o OUTPUT] [--theme THEME] [--base-url URL]
+
+Open Markdown files in the viewer, each in its own tab.
+
+Arguments:
+  PATH                  Markdown file, or a folder to open the Markdown files in;
+                        '-' reads stdin. Append #HEADING to jump to a heading
+
+Options:
+      --theme THEME     Theme to start with, by name
+      --line N          Jump to the section holding line N of PATH; needs
+                        exactly one PATH, and it must be a file
+  -V, --version         Print the version
+  -h, --help            Print this help
+
+See 'dustrown render --help' for rendering to HTML.";
+
+const RENDER_USAGE: &str = "\
+Usage: dustrown render [INPUT] [-o OUTPUT] [--theme THEME] [--base-url URL]
+
+Render Markdown to a standalone HTML document, identical to what the viewer shows.
+
+Arguments:
+  INPUT                 Markdown file to read; omit or use '-' for stdin
+
+Options:
+  -o, --output OUTPUT   Write HTML to OUTPUT instead of stdout
+      --theme THEME     Theme by name, built-in or user theme, as in the viewer;
+                        defaults to the configured theme, or Light
+      --base-url URL    Resolve relative images and links against URL instead of
+                        wherever the HTML is served from
+  -h, --help            Print this help";
+
+#[derive(Debug, PartialEq, Eq)]
+struct RenderArgs {
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    /// Theme name, matched like the viewer's `--theme`.
+    theme: Option<String>,
+    base_url: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum RenderCommand {
+    Render(Box<RenderArgs>),
+    Help,
+}
+
+/// What the viewer opens at startup, from its command line.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ViewerArgs {
+    pub targets: Vec<OpenTarget>,
+    /// Theme name, matched like `theme` in `config.toml`.
+    pub theme: Option<String>,
+}
+
+/// A file, folder or stdin to open, and where to scroll to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OpenTarget {
+    /// `None` reads stdin.
+    pub path: Option<PathBuf>,
+    /// Heading id from `PATH#HEADING`.
+    pub fragment: Option<String>,
+    /// Source line from `--line`; wins over `fragment`.
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ViewerCommand {
+    Open(ViewerArgs),
+    Help,
+    Version,
+}
+
+fn parse_render_args(args: impl IntoIterator<Item = String>) -> Result<RenderCommand, String> {
+    let mut input = None;
+    let mut input_seen = false;
+    let mut output = None;
+    let mut theme = None;
+    let mut base_url = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(RenderCommand::Help),
+            "-o" | "--output" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for {arg}"))?;
+                output = Some(PathBuf::from(value));
+            }
+            "--theme" => {
+                let value = args.next().ok_or("missing value for --theme")?;
+                theme = Some(value);
+            }
+            "--base-url" => {
+                let value = args.next().ok_or("missing value for --base-url")?;
+                base_url = Some(value);
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option '{flag}'"));
+            }
+            path => {
+                if input_seen {
+                    return Err("only one INPUT may be given".to_string());
+                }
+                input_seen = true;
+                input = (path != "-").then(|| PathBuf::from(path));
+            }
+        }
+    }
+
+    Ok(RenderCommand::Render(Box::new(RenderArgs {
+        input,
+        output,
+        theme,
+        base_url,
+    })))
+}
+
+fn parse_viewer_args(args: impl IntoIterator<Item = String>) -> Result<ViewerCommand, String> {
+    let mut viewer = ViewerArgs::default();
+    let mut line = None;
+    let mut options_done = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if !options_done {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(ViewerCommand::Help),
+                "-V" | "--version" => return Ok(ViewerCommand::Version),
+                "--" => {
+                    options_done = true;
+                    continue;
+                }
+                "--theme" => {
+                    let value = args.next().ok_or("missing value for --theme")?;
+                    viewer.theme = Some(value);
+                    continue;
+                }
+                "--line" => {
+                    let value = args.next().ok_or("missing value for --line")?;
+                    let number = value.parse().ok().filter(|&number| number > 0);
+                    line = Some(number.ok_or_else(|| format!("invalid line number '{value}'"))?);
+                    continue;
+                }
+                flag if flag.starts_with('-') && flag != "-" && !flag.starts_with("-#") => {
+                    return Err(format!("unknown option '{flag}'"));
+                }
+                _ => {}
+            }
+        }
+        let mut target = parse_target(&arg);
+        if target.path.is_none() && viewer.targets.iter().any(|known| known.path.is_none()) {
+            return Err("'-' (stdin) may only be given once".to_string());
+        }
+        target.line = line.take();
+        viewer.targets.push(target);
+    }
+    if line.is_some() {
+        return Err("--line must come before the PATH it applies to".to_string());
+    }
+    if viewer.targets.iter().any(|target| target.line.is_some()) {
+        let file = match viewer.targets.as_slice() {
+            [target] => target.path.as_ref().filter(|path| !path.is_dir()),
+            _ => None,
+        };
+        if file.is_none() {
+            return Err("--line needs exactly one PATH, and it must be a file".to_string());
+        }
+    }
+
+    Ok(ViewerCommand::Open(viewer))
+}
+
+/// Splits `PATH#HEADING`, unless a file is really called that.
+fn parse_target(arg: &str) -> OpenTarget {
+    let (path, fragment) = match arg.rsplit_once('#') {
+        Some((path, fragment))
+            if !path.is_empty() && !fragment.is_empty() && !Path::new(arg).exists() =>
+        {
+            (path, Some(fragment.to_string()))
+        }
+        _ => (arg, None),
+    };
+    OpenTarget {
+        path: (path != "-").then(|| PathBuf::from(path)),
+        fragment,
+        line: None,
+    }
+}
+
+/// Reads the viewer's command line. Help, the version and usage errors are
+/// printed here; `Err` holds the exit code to leave with.
+pub fn viewer_args(args: impl IntoIterator<Item = String>) -> Result<ViewerArgs, ExitCode> {
+    match parse_viewer_args(args) {
+        Ok(ViewerCommand::Open(args)) => Ok(args),
+        Ok(ViewerCommand::Help) => {
+            println!("{VIEWER_USAGE}");
+            Err(ExitCode::SUCCESS)
+        }
+        Ok(ViewerCommand::Version) => {
+            println!("dustrown {}", env!("CARGO_PKG_VERSION"));
+            Err(ExitCode::SUCCESS)
+        }
+        Err(message) => {
+            eprintln!("dustrown: {message}\n\n{VIEWER_USAGE}");
+            Err(ExitCode::from(2))
+        }
+    }
+}
+
+/// The document read from stdin, when `-` is among the targets. Stdin
+/// cannot be read again later, so it is rendered right away.
+pub fn stdin_document(
+    targets: &[OpenTarget],
+    renderer: &Renderer,
+    theme: &Theme,
+) -> Option<Document> {
+    let target = targets.iter().find(|target| target.path.is_none())?;
+    match read_input(None) {
+        Ok((markdown, encoding)) => {
+            let mut document =
+                Document::from_source(DocumentSource::Stdin, markdown, renderer, theme);
+            document.encoding = encoding;
+            document.fragment = target.fragment.clone();
+            Some(document)
+        }
+        Err(err) => {
+            eprintln!("dustrown: could not read stdin: {err}");
+            None
+        }
+    }
+}
+
+/// The files for the targets given on the command line, in order, with where
+/// to scroll to, for [`AppState::start_load`]. A folder adds its files with
+/// one of `extensions`, sorted by name.
+pub fn target_files(targets: &[OpenTarget], extensions: &[String]) -> Vec<(PathBuf, LoadOptions)> {
+    let mut files = Vec::new();
+    for target in targets {
+        let Some(path) = &target.path else {
+            continue;
+        };
+        if path.is_dir() {
+            let found = files_in_folder(path, extensions);
+            if found.is_empty() {
+                eprintln!("dustrown: no Markdown files in {}", path.display());
+            }
+            files.extend(found.into_iter().map(|file| (file, LoadOptions::default())));
+            continue;
+        }
+        let options = LoadOptions {
+            line: target.line,
+            fragment: target.fragment.clone(),
+            ..LoadOptions::default()
+        };
+        files.push((path.clone(), options));
+    }
+    files
+}
+
+fn files_in_folder(dir: &Path, extensions: &[String]) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|found| {
+                    extensions
+                        .iter()
+                        .any(|extension| found.eq_ignore_ascii_case(extension.as_str()))
+                })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// The page the viewer would show for `markdown`, with the same remote
+/// content policy. Relative images and links stay relative, so they resolve
+/// against wherever the page is published, or against `base_url` when given.
+pub fn render_standalone(
+    renderer: &Renderer,
+    markdown: &str,
+    theme: &Theme,
+    base_url: Option<&str>,
+) -> String {
+    let body = renderer.render_markdown(markdown, theme, renderer.remote_content);
+    core::render_document(&body, theme, base_url)
+}
+
+/// Reads `input`, or stdin when `None`, decoded like the files the viewer
+/// opens.
+fn read_input(input: Option<&PathBuf>) -> io::Result<(String, TextEncoding)> {
+    let bytes = match input {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            bytes
+        }
+    };
+    Ok(encoding::decode(&bytes, None))
+}
+
+fn write_output(output: Option<&PathBuf>, html: &str) -> io::Result<()> {
+    match output {
+        Some(path) => fs::write(path, html),
+        None => io::stdout().write_all(html.as_bytes()),
+    }
+}
+
+/// Entry point for `dustrown render ...`; `args` excludes the subcommand itself.
+pub fn run_render(args: impl IntoIterator<Item = String>) -> ExitCode {
+    let args = match parse_render_args(args) {
+        Ok(RenderCommand::Render(args)) => args,
+        Ok(RenderCommand::Help) => {
+            println!("{RENDER_USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("dustrown render: {message}\n\n{RENDER_USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let markdown = match read_input(args.input.as_ref()) {
+        Ok((markdown, _)) => markdown,
+        Err(err) => {
+            let source = args
+                .input
+                .as_ref()
+                .map_or_else(|| "stdin".to_string(), |path| path.display().to_string());
+            eprintln!("dustrown render: could not read {source}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (config, mut errors) = config::load_config();
+    let (renderer, renderer_errors) = Renderer::with_config(&config);
+    errors.extend(renderer_errors);
+    // The same themes as the viewer: built-ins plus user theme files, with
+    // the configured fonts and width.
+    let mut state = AppState::new(Theme::light());
+    errors.extend(state.load_themes(&config, &renderer));
+    for error in errors {
+        eprintln!("dustrown render: skipped {error}");
+    }
+    if let Some(name) = &args.theme
+        && !state.select_theme_by_name(name)
+    {
+        let names: Vec<&str> = state
+            .themes
+            .iter()
+            .map(|theme| theme.name.as_str())
+            .collect();
+        eprintln!(
+            "dustrown render: unknown theme '{name}'; available themes: {}\n\n{RENDER_USAGE}",
+            names.join(", ")
+        );
+        return ExitCode::from(2);
+    }
+    let html = render_standalone(&renderer, &markdown, &state.theme, args.base_url.as_deref());
+    if let Err(err) = write_output(args.output.as_ref(), &html) {
+        eprintln!("dustrown render: could not write output: {err}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn parse(args: &[&str]) -> Result<RenderCommand, String> {
+        parse_render_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_render_args_reads_input_output_and_theme() {
+        let command = parse(&[
+            "input.md",
+            "-o",
+            "out.html",
+            "--theme",
+            "dark",
+            "--base-url",
+            "https://example.com/docs/",
+        ]);
+
+        assert_eq!(
+            command,
+            Ok(RenderCommand::Render(Box::new(RenderArgs {
+                input: Some(PathBuf::from("input.md")),
+                output: Some(PathBuf::from("out.html")),
+                theme: Some("dark".to_string()),
+                base_url: Some("https://example.com/docs/".to_string()),
+            })))
+        );
+    }
+
+    #[test]
+    fn parse_render_args_defaults_to_stdin_stdout_and_configured_theme() {
+        let expected = Ok(RenderCommand::Render(Box::new(RenderArgs {
+            input: None,
+            output: None,
+            theme: None,
+            base_url: None,
+        })));
+
+        assert_eq!(parse(&[]), expected);
+        assert_eq!(parse(&["-"]), expected);
+    }
+
+    #[test]
+    fn parse_render_args_rejects_invalid_usage() {
+        assert!(parse(&["a.md", "b.md"]).is_err());
+        assert!(parse(&["--theme"]).is_err());
+        assert!(parse(&["--output"]).is_err());
+        assert!(parse(&["--base-url"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert_eq!(parse(&["--help"]), Ok(RenderCommand::Help));
+    }
+
+    fn parse_viewer(args: &[&str]) -> Result<ViewerCommand, String> {
+        parse_viewer_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_viewer_args_reads_paths_stdin_theme_and_jumps() {
+        let command = parse_viewer(&[
+            "--theme",
+            "Dark",
+            "a.md",
+            "docs",
+            "-",
+            "guide.md#setup",
+            "--",
+            "--odd.md",
+        ]);
+
+        let target = |path: Option<&str>, fragment: Option<&str>, line| OpenTarget {
+            path: path.map(PathBuf::from),
+            fragment: fragment.map(str::to_string),
+            line,
+        };
+        assert_eq!(
+            command,
+            Ok(ViewerCommand::Open(ViewerArgs {
+                targets: vec![
+                    target(Some("a.md"), None, None),
+                    target(Some("docs"), None, None),
+                    target(None, None, None),
+                    target(Some("guide.md"), Some("setup"), None),
+                    target(Some("--odd.md"), None, None),
+                ],
+                theme: Some("Dark".to_string()),
+            }))
+        );
+        assert_eq!(
+            parse_viewer(&[]),
+            Ok(ViewerCommand::Open(ViewerArgs::default()))
+        );
+        assert_eq!(
+            parse_viewer(&["--line", "40", "a.md"]),
+            Ok(ViewerCommand::Open(ViewerArgs {
+                targets: vec![target(Some("a.md"), None, Some(40))],
+                theme: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_viewer_args_rejects_invalid_usage() {
+        assert_eq!(parse_viewer(&["-V"]), Ok(ViewerCommand::Version));
+        assert_eq!(parse_viewer(&["a.md", "--help"]), Ok(ViewerCommand::Help));
+        assert_eq!(
+            parse_viewer(&["--verbose"]),
+            Err("unknown option '--verbose'".to_string())
+        );
+        assert_eq!(
+            parse_viewer(&["-", "-"]),
+            Err("'-' (stdin) may only be given once".to_string())
+        );
+        assert_eq!(
+            parse_viewer(&["--line", "0", "a.md"]),
+            Err("invalid line number '0'".to_string())
+        );
+        assert_eq!(
+            parse_viewer(&["a.md", "--line", "3"]),
+            Err("--line must come before the PATH it applies to".to_string())
+        );
+        for args in [
+            &["--line", "3", "a.md", "b.md"][..],
+            &["a.md", "--line", "3", "b.md"],
+            &["--line", "3", "-"],
+            &["--line", "3", "src"],
+        ] {
+            assert_eq!(
+                parse_viewer(args),
+                Err("--line needs exactly one PATH, and it must be a file".to_string())
+            );
+        }
+        assert!(parse_viewer(&["--theme"]).is_err());
+    }
+
+    #[test]
+    fn target_files_expand_folders_and_jump_to_line_sections() {
+        let dir = TempDir::new("cli");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.md"), "# B").unwrap();
+        fs::write(dir.join("a.MD"), "# Intro\n\ntext\n\n## Usage\n\nmore\n").unwrap();
+        fs::write(dir.join("c.png"), "not markdown").unwrap();
+        let targets = [
+            OpenTarget {
+                path: Some(dir.to_path_buf()),
+                fragment: None,
+                line: None,
+            },
+            OpenTarget {
+                path: Some(dir.join("a.MD")),
+                fragment: None,
+                line: Some(6),
+            },
+            OpenTarget {
+                path: Some(dir.join("b.md")),
+                fragment: Some("b".to_string()),
+                line: None,
+            },
+        ];
+
+        let renderer = Renderer::new();
+        let theme = Theme::light();
+        let documents: Vec<Document> = target_files(&targets, &["md".to_string()])
+            .into_iter()
+            .map(|(path, options)| {
+                let mut state = AppState::new(theme.clone());
+                let load = state.start_load(&path, options);
+                load.run(&renderer, &theme).unwrap()
+            })
+            .collect();
+
+        let titles: Vec<&str> = documents.iter().map(|d| d.title.as_str()).collect();
+        assert_eq!(titles, ["a.MD", "b.md", "a.MD", "b.md"]);
+        assert_eq!(documents[0].fragment, None);
+        assert_eq!(documents[2].fragment.as_deref(), Some("usage"));
+        assert_eq!(documents[3].fragment.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn render_standalone_matches_viewer_document() {
+        let renderer = Renderer::new();
+        let html = render_standalone(&renderer, "# Title", &Theme::dark(), None);
+
+        assert!(html.starts_with("<!doctype html>"));
+        assert!(html.contains("background: #0d1117"));
+        assert!(html.contains("<article class=\"markdown-body\"><h1 id=\"title\">Title</h1>"));
+        assert!(!html.contains("<base"));
+    }
+
+    #[test]
+    fn render_standalone_blocks_remote_images_and_keeps_relative_ones_relative() {
+        let markdown = "![a](https://example.com/a.png) ![b](img/b.png)";
+        let renderer = Renderer::new();
+        let html = render_standalone(&renderer, markdown, &Theme::light(), None);
+
+        assert!(!html.contains("https://example.com/a.png"));
+        assert!(html.contains("src=\"img/b.png\""));
+        assert!(!html.contains("<base"));
+        assert!(!html.contains("file://"));
+
+        let html = render_standalone(
+            &renderer,
+            markdown,
+            &Theme::light(),
+            Some("https://example.com/docs/"),
+        );
+        assert!(html.contains("<base href=\"https://example.com/docs/\" />"));
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::Options;
+use serde::Deserialize;
+
+use crate::{
+    core::{self, RemoteContent},
+    keymap::{self, Keymap},
+    theme::{self, Theme},
+};
+
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (980, 760);
+/// Bounds for the window size, in logical pixels.
+const WINDOW_EDGE_RANGE: std::ops::RangeInclusive<u32> = 200..=16384;
+const DEFAULT_FILE_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd", "txt"];
+const DEFAULT_MAX_FILE_SIZE_MB: u64 = 10;
+/// Bounds for the size limit, in megabytes.
+const MAX_FILE_SIZE_MB_RANGE: std::ops::RangeInclusive<u64> = 1..=4096;
+const BINDABLE_COMMANDS: &str = "open, close, reopen-closed, recent:N, quit, find, find-next, \
+     find-previous, theme, theme:N, encoding:N, outline, next-tab, previous-tab, tab:N, back, \
+     forward, allow-remote, cancel-load, scroll-down, scroll-up, half-page-down, half-page-up, \
+     top, bottom, next-heading, previous-heading";
+
+/// User settings from `config.toml` in the config directory (see
+/// [`load_config`]). Entries the file leaves out or gets wrong keep their
+/// defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Theme to start with; `None` follows the system light/dark setting.
+    pub theme: Option<String>,
+    /// Fonts and content width applied on top of every theme.
+    pub body_font: Option<String>,
+    pub code_font: Option<String>,
+    pub max_width: Option<u32>,
+    pub window_width: u32,
+    pub window_height: u32,
+    pub extensions: MarkdownExtensions,
+    /// Extensions, without the dot, offered by the open dialog.
+    pub file_extensions: Vec<String>,
+    /// Files larger than this many megabytes are only rendered once
+    /// confirmed.
+    pub max_file_size_mb: u64,
+    /// Whether newly opened documents load remote images.
+    pub remote_content: RemoteContent,
+    pub keymap: Keymap,
+    /// Reopen the files, theme and scroll positions of the last session on
+    /// startup.
+    pub restore_session: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: None,
+            body_font: None,
+            code_font: None,
+            max_width: None,
+            window_width: DEFAULT_WINDOW_SIZE.0,
+            window_height: DEFAULT_WINDOW_SIZE.1,
+            extensions: MarkdownExtensions::default(),
+            file_extensions: DEFAULT_FILE_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            max_file_size_mb: DEFAULT_MAX_FILE_SIZE_MB,
+            remote_content: RemoteContent::Block,
+            keymap: Keymap::default(),
+            restore_session: true,
+        }
+    }
+}
+
+impl Config {
+    /// Applies the configured fonts and content width to `theme`.
+    pub fn apply_to_theme(&self, theme: &mut Theme) {
+        if let Some(font) = &self.body_font {
+            theme.body_font = font.clone();
+        }
+        if let Some(font) = &self.code_font {
+            theme.code_font = Some(font.clone());
+        }
+        if let Some(max_width) = self.max_width {
+            theme.max_width = max_width;
+        }
+    }
+}
+
+/// Markdown syntax beyond CommonMark that the renderer recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkdownExtensions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub task_lists: bool,
+    pub math: bool,
+    /// GitHub `> [!NOTE]` style alerts.
+    pub alerts: bool,
+    /// YAML (`---`) and TOML (`+++`) front matter.
+    pub front_matter: bool,
+    pub definition_lists: bool,
+    pub smart_punctuation: bool,
+}
+
+impl Default for MarkdownExtensions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            task_lists: true,
+            math: true,
+            alerts: true,
+            front_matter: true,
+            definition_lists: false,
+            smart_punctuation: false,
+        }
+    }
+}
+
+impl MarkdownExtensions {
+    pub fn options(self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.task_lists);
+        options.set(Options::ENABLE_MATH, self.math);
+        options.set(Options::ENABLE_GFM, self.alerts);
+        options.set(
+            Options::ENABLE_YAML_STYLE_METADATA_BLOCKS,
+            self.front_matter,
+        );
+        options.set(
+            Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS,
+            self.front_matter,
+        );
+        options.set(Options::ENABLE_DEFINITION_LIST, self.definition_lists);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options
+    }
+}
+
+/// A configuration, theme or syntax file that could not be (fully) used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+/// On-disk form of [`Config`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    theme: Option<String>,
+    max_width: Option<u32>,
+    remote_content: Option<String>,
+    vim_navigation: Option<bool>,
+    restore_session: Option<bool>,
+    #[serde(default)]
+    fonts: FontsFile,
+    #[serde(default)]
+    window: WindowFile,
+    #[serde(default)]
+    markdown: MarkdownFile,
+    #[serde(default)]
+    files: FilesFile,
+    #[serde(default)]
+    keybindings: BTreeMap<String, KeySequences>,
+}
+
+/// A command's key sequences: one string, or a list (empty to unbind).
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum KeySequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FontsFile {
+    body: Option<String>,
+    code: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WindowFile {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MarkdownFile {
+    tables: Option<bool>,
+    footnotes: Option<bool>,
+    strikethrough: Option<bool>,
+    task_lists: Option<bool>,
+    math: Option<bool>,
+    alerts: Option<bool>,
+    front_matter: Option<bool>,
+    definition_lists: Option<bool>,
+    smart_punctuation: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilesFile {
+    extensions: Option<Vec<String>>,
+    max_size_mb: Option<u64>,
+}
+
+/// `config.toml` in the config directory.
+pub fn config_path() -> Option<PathBuf> {
+    core::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Reads the user's config file; a missing file gives the defaults.
+pub fn load_config() -> (Config, Vec<ConfigError>) {
+    match config_path() {
+        Some(path) => load_config_from(&path),
+        None => (Config::default(), Vec::new()),
+    }
+}
+
+fn load_config_from(path: &Path) -> (Config, Vec<ConfigError>) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return (Config::default(), Vec::new());
+        }
+        Err(err) => {
+            let error = ConfigError {
+                path: path.to_path_buf(),
+                message: err.to_string(),
+            };
+            return (Config::default(), vec![error]);
+        }
+    };
+    let (config, problems) = parse_config(&text);
+    let errors = problems
+        .into_iter()
+        .map(|message| ConfigError {
+            path: path.to_path_buf(),
+            message,
+        })
+        .collect();
+    (config, errors)
+}
+
+/// Parses a config file. A file that is not valid TOML, or has keys of the
+/// wrong type, is ignored as a whole; other invalid entries are skipped one
+/// by one. Returns a message for every problem found.
+fn parse_config(text: &str) -> (Config, Vec<String>) {
+    let mut config = Config::default();
+    let file: ConfigFile = match toml::from_str(text) {
+        Ok(file) => file,
+        Err(err) => {
+            let message = match err.span() {
+                Some(span) => format!("line {}: {}", line_number(text, span.start), err.message()),
+                None => err.message().to_string(),
+            };
+            return (config, vec![message]);
+        }
+    };
+    let mut problems = Vec::new();
+
+    if let Some(name) = file.theme {
+        let name = name.trim();
+        if name.is_empty() {
+            problems.push("theme: must not be empty".to_string());
+        } else if !name.eq_ignore_ascii_case("system") {
+            config.theme = Some(name.to_string());
+        }
+    }
+    for (key, font, target) in [
+        ("body", file.fonts.body, &mut config.body_font),
+        ("code", file.fonts.code, &mut config.code_font),
+    ] {
+        let Some(font) = font else {
+            continue;
+        };
+        if theme::is_font_family(&font) {
+            *target = Some(font);
+        } else {
+            problems.push(format!(
+                "fonts.{key}: '{font}' is not a CSS font-family list"
+            ));
+        }
+    }
+    if let Some(max_width) = file.max_width {
+        match theme::check_max_width(max_width) {
+            Ok(()) => config.max_width = Some(max_width),
+            Err(message) => problems.push(format!("max_width: {message}")),
+        }
+    }
+    for (key, size, target) in [
+        ("width", file.window.width, &mut config.window_width),
+        ("height", file.window.height, &mut config.window_height),
+    ] {
+        let Some(size) = size else {
+            continue;
+        };
+        if WINDOW_EDGE_RANGE.contains(&size) {
+            *target = size;
+        } else {
+            problems.push(format!(
+                "window.{key}: {size} is outside {}..={}",
+                WINDOW_EDGE_RANGE.start(),
+                WINDOW_EDGE_RANGE.end()
+            ));
+        }
+    }
+
+    let markdown = file.markdown;
+    let extensions = &mut config.extensions;
+    for (enabled, target) in [
+        (markdown.tables, &mut extensions.tables),
+        (markdown.footnotes, &mut extensions.footnotes),
+        (markdown.strikethrough, &mut extensions.strikethrough),
+        (markdown.task_lists, &mut extensions.task_lists),
+        (markdown.math, &mut extensions.math),
+        (markdown.alerts, &mut extensions.alerts),
+        (markdown.front_matter, &mut extensions.front_matter),
+        (markdown.definition_lists, &mut extensions.definition_lists),
+        (
+            markdown.smart_punctuation,
+            &mut extensions.smart_punctuation,
+        ),
+    ] {
+        if let Some(enabled) = enabled {
+            *target = enabled;
+        }
+    }
+
+    if let Some(extensions) = file.files.extensions {
+        let extensions: Vec<String> = extensions
+            .iter()
+            .map(|extension| {
+                extension
+                    .trim_start_matches("*.")
+                    .trim_start_matches('.')
+                    .to_string()
+            })
+            .collect();
+        if extensions.is_empty() {
+            problems.push("files.extensions: must list at least one extension".to_string());
+        } else if let Some(bad) = extensions.iter().find(|extension| {
+            extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric())
+        }) {
+            problems.push(format!("files.extensions: '{bad}' is not a file extension"));
+        } else {
+            config.file_extensions = extensions;
+        }
+    }
+    if let Some(size) = file.files.max_size_mb {
+        if MAX_FILE_SIZE_MB_RANGE.contains(&size) {
+            config.max_file_size_mb = size;
+        } else {
+            problems.push(format!(
+                "files.max_size_mb: {size} is outside {}..={}",
+                MAX_FILE_SIZE_MB_RANGE.start(),
+                MAX_FILE_SIZE_MB_RANGE.end()
+            ));
+        }
+    }
+
+    if let Some(policy) = file.remote_content {
+        match policy.to_ascii_lowercase().as_str() {
+            "block" => config.remote_content = RemoteContent::Block,
+            "allow" => config.remote_content = RemoteContent::Allow,
+            _ => problems.push(format!(
+                "remote_content: unknown policy '{policy}', expected block or allow"
+            )),
+        }
+    }
+
+    if file.vim_navigation == Some(false) {
+        config.keymap = Keymap::without_vim_navigation();
+    }
+    for (command, sequences) in file.keybindings {
+        if keymap::parse_app_event(&command).is_none() {
+            problems.push(format!(
+                "keybindings.{command}: unknown command, expected one of {BINDABLE_COMMANDS}"
+            ));
+            continue;
+        }
+        let sequences = match sequences {
+            KeySequences::One(sequence) => vec![sequence],
+            KeySequences::Many(sequences) => sequences,
+        };
+        match sequences
+            .iter()
+            .map(|sequence| keymap::parse_sequence(sequence))
+            .collect()
+        {
+            Ok(sequences) => config.keymap.rebind(&command, sequences),
+            Err(message) => problems.push(format!("keybindings.{command}: {message}")),
+        }
+    }
+    for conflict in config.keymap.conflicts() {
+        problems.push(format!("keybindings: {conflict}"));
+    }
+    if let Some(restore) = file.restore_session {
+        config.restore_session = restore;
+    }
+
+    (config, problems)
+}
+
+fn line_number(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::AppEvent;
+
+    #[test]
+    fn parse_config_reads_every_section() {
+        let (config, problems) = parse_config(
+            "theme = \"Dark\"\nmax_width = 720\nremote_content = \"allow\"\nvim_navigation = false\n\
+             restore_session = false\n\
+             [fonts]\ncode = \"'Fira Code', monospace\"\n\
+             [window]\nwidth = 1200\n\
+             [markdown]\nmath = false\nsmart_punctuation = true\n\
+             [files]\nextensions = [\".md\", \"rst\"]\nmax_size_mb = 50\n\
+             [keybindings]\nquit = \"Ctrl+Shift+Q\"\ntheme = [\"Ctrl+K Ctrl+T\", \"Ctrl+D\"]\n\
+             find-next = []\n",
+        );
+
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(config.theme.as_deref(), Some("Dark"));
+        assert_eq!(config.max_width, Some(720));
+        assert_eq!(config.remote_content, RemoteContent::Allow);
+        assert!(!config.restore_session);
+        assert_eq!(config.code_font.as_deref(), Some("'Fira Code', monospace"));
+        assert_eq!((config.window_width, config.window_height), (1200, 760));
+        assert!(!config.extensions.math);
+        assert!(config.extensions.smart_punctuation);
+        assert!(config.extensions.options().contains(Options::ENABLE_TABLES));
+        assert!(!config.extensions.options().contains(Options::ENABLE_MATH));
+        assert_eq!(config.file_extensions, ["md", "rst"]);
+        assert_eq!(config.max_file_size_mb, 50);
+        let shortcut = |event| config.keymap.shortcut(&event).map(ToString::to_string);
+        assert_eq!(shortcut(AppEvent::Quit).as_deref(), Some("Ctrl+Shift+Q"));
+        assert_eq!(shortcut(AppEvent::ToggleTheme).as_deref(), Some("Ctrl+D"));
+        assert_eq!(shortcut(AppEvent::Open).as_deref(), Some("Ctrl+O"));
+        assert_eq!(shortcut(AppEvent::FindNext), None);
+        assert!(!config
+            .keymap
+            .bindings()
+            .iter()
+            .any(|binding| binding.command == "scroll-down"));
+        assert!(config
+            .keymap
+            .bindings()
+            .iter()
+            .any(|binding| binding.command == "theme" && binding.keys.len() == 2));
+    }
+
+    #[test]
+    fn parse_config_skips_invalid_entries_with_a_message_each() {
+        let (config, problems) = parse_config(
+            "theme = \"system\"\nmax_width = 5\nremote_content = \"sometimes\"\n\
+             [fonts]\nbody = \"x; } body { color: red\"\n\
+             [window]\nheight = 50\n\
+             [files]\nextensions = [\"md\", \"*\"]\nmax_size_mb = 0\n\
+             [keybindings]\nopen = \"Hyper+O\"\nprint = \"Ctrl+P\"\nclose = \"Ctrl+Q\"\n",
+        );
+
+        assert_eq!(
+            problems,
+            [
+                "fonts.body: 'x; } body { color: red' is not a CSS font-family list",
+                "max_width: 5 is outside 320..=4096",
+                "window.height: 50 is outside 200..=16384",
+                "files.extensions: '*' is not a file extension",
+                "files.max_size_mb: 0 is outside 1..=4096",
+                "remote_content: unknown policy 'sometimes', expected block or allow",
+                "keybindings.open: unknown modifier 'Hyper' in 'Hyper+O'",
+                "keybindings.print: unknown command, expected one of open, close, \
+                 reopen-closed, recent:N, quit, find, find-next, find-previous, theme, \
+                 theme:N, encoding:N, outline, next-tab, previous-tab, tab:N, back, forward, allow-remote, \
+                 cancel-load, scroll-down, scroll-up, half-page-down, half-page-up, top, bottom, next-heading, previous-heading",
+                "keybindings: Ctrl+Q (quit) shadows Ctrl+Q (close)",
+            ]
+        );
+        let defaults = Config::default();
+        assert_eq!(config.theme, None);
+        assert_eq!(config.max_width, None);
+        assert_eq!(config.window_height, defaults.window_height);
+        assert_eq!(config.file_extensions, defaults.file_extensions);
+        assert_eq!(
+            config.keymap.shortcut(&AppEvent::Open),
+            defaults.keymap.shortcut(&AppEvent::Open)
+        );
+    }
+
+    #[test]
+    fn parse_config_reports_syntax_errors_with_their_line() {
+        let (config, problems) = parse_config("theme = \"Dark\"\n[window]\nwidht = 900\n");
+
+        assert_eq!(config, Config::default());
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("line 3: unknown field `widht`"),
+            "{}",
+            problems[0]
+        );
+    }
+
+    #[test]
+    fn load_config_from_missing_file_gives_defaults() {
+        let path = std::env::temp_dir().join("dustrown-no-such-config.toml");
+        assert_eq!(load_config_from(&path), (Config::default(), Vec::new()));
+    }
+}
@@ -1,326 +1,3568 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use ammonia::Builder as HtmlSanitizer;
+use ammonia::{Builder as HtmlSanitizer, UrlRelative};
 use dark_light::Mode;
-use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+use pulldown_cmark::{
+    html, BlockQuoteKind, CodeBlockKind, CowStr, Event, MetadataBlockKind, Options, Parser, Tag,
+    TagEnd,
+};
 use syntect::{
     highlighting::{Theme as SyntectTheme, ThemeSet},
     html::highlighted_html_for_string,
-    parsing::SyntaxSet,
+    parsing::{SyntaxDefinition, SyntaxSet},
+};
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::{
+    config::{self, Config, ConfigError},
+    encoding::{self, TextEncoding},
+    math::latex_to_mathml,
+    theme::{self, Theme},
 };
 
 pub const APP_TITLE: &str = "Dustrown";
+/// Title of a document read from standard input.
+pub const STDIN_TITLE: &str = "(stdin)";
+
+/// Quiet period after the last change before a watched file is reloaded.
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+/// How often frontends check a [`ReloadWatch`] for due reloads.
+pub const RELOAD_TICK: Duration = Duration::from_millis(250);
+
+/// How many files File → Open Recent lists.
+const MAX_RECENT_FILES: usize = 10;
+
+/// Extensions of files that links open in the viewer instead of the webview.
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd"];
+
+/// Schemes handed to the system browser; links with any other scheme are
+/// blocked.
+const EXTERNAL_SCHEMES: &[&str] = &["http", "https"];
 
-#[derive(Copy, Clone)]
-pub enum Theme {
-    Light,
-    Dark,
+/// MathML elements produced by [`latex_to_mathml`] and kept by the sanitizer.
+const MATHML_TAGS: &[&str] = &[
+    "math",
+    "semantics",
+    "annotation",
+    "mrow",
+    "mi",
+    "mn",
+    "mo",
+    "mtext",
+    "mspace",
+    "msub",
+    "msup",
+    "msubsup",
+    "munder",
+    "mover",
+    "munderover",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "mtable",
+    "mtr",
+    "mtd",
+    "merror",
+];
+
+/// Where the Markdown of a document comes from. Only files can be read
+/// again, so live reload, the recent files and the session leave the others
+/// out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentSource {
+    File(PathBuf),
+    /// Read once from standard input.
+    Stdin,
+    /// Text handed to the viewer directly, such as a drop of plain text,
+    /// shown under the given name. Only the Linux frontend accepts dropped
+    /// text.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Memory(String),
 }
 
-impl Theme {
-    pub fn toggled(self) -> Self {
+impl DocumentSource {
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            Self::Stdin | Self::Memory(_) => None,
+        }
+    }
+
+    /// Tab title when the front matter does not name the document.
+    pub fn name(&self) -> String {
         match self {
-            Self::Light => Self::Dark,
-            Self::Dark => Self::Light,
+            Self::File(path) => filename_or_path(path),
+            Self::Stdin => STDIN_TITLE.to_string(),
+            Self::Memory(name) => name.clone(),
         }
     }
 }
 
-pub struct AppState {
-    pub theme: Theme,
+/// An open document, shown as one tab. A file that failed to open keeps its
+/// tab with an error page, so `source_markdown` is `None` for it.
+pub struct Document {
+    pub title: String,
+    pub source: DocumentSource,
     pub source_markdown: Option<String>,
-    pub rendered_html: Option<String>,
+    pub rendered_html: String,
+    pub outline: Vec<Heading>,
+    /// Heading id to scroll to when the document is shown.
+    pub fragment: Option<String>,
+    pub history: History,
+    pub remote_content: RemoteContent,
+    /// Remote images left out of `rendered_html` by the remote content policy.
+    pub blocked_remote: usize,
+    /// Vertical scroll offset, in CSS pixels, to restore when the page is
+    /// next shown.
+    pub scroll_position: Option<u32>,
+    pub encoding: TextEncoding,
+    /// Id of the newest render requested for this tab and not yet applied,
+    /// see [`AppState::finish_render`].
+    pending_render: Option<u64>,
+    /// The newest load started to replace this document, by a link or the
+    /// back/forward buttons, and not yet shown.
+    navigation: Option<PendingNavigation>,
+    /// Encoding picked to read the file again with, until a render that
+    /// reads it finishes, see [`AppState::reopen_with_encoding`].
+    reencoding: Option<&'static encoding_rs::Encoding>,
 }
 
-pub fn detect_theme() -> Theme {
-    match dark_light::detect() {
-        Mode::Dark => Theme::Dark,
-        _ => Theme::Light,
+impl Document {
+    /// Reads and renders the file at `path`. Folders and binary files, say
+    /// an image dropped on the window, get an error page instead.
+    pub fn load(path: &Path, renderer: &Renderer, theme: &Theme) -> Self {
+        if path.is_dir() {
+            return Self::open_error(path, "This is a folder, not a text file.");
+        }
+        let bytes = match read_unless_binary(path) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => {
+                return Self::open_error(
+                    path,
+                    "This does not look like a text file, so it is not shown.",
+                );
+            }
+            Err(err) => return Self::open_error(path, &err.to_string()),
+        };
+        let (markdown, encoding) = encoding::decode(&bytes, None);
+        let mut document = Self::from_markdown(path, markdown, renderer, theme);
+        document.encoding = encoding;
+        document
     }
-}
 
-pub fn render_markdown(markdown: &str, theme: Theme) -> String {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_TASKLISTS);
-    options.insert(Options::ENABLE_FOOTNOTES);
+    pub fn from_markdown(
+        path: &Path,
+        markdown: String,
+        renderer: &Renderer,
+        theme: &Theme,
+    ) -> Self {
+        Self::from_source(
+            DocumentSource::File(path.to_path_buf()),
+            markdown,
+            renderer,
+            theme,
+        )
+    }
 
-    let parser = Parser::new_ext(markdown, options);
-    let syntax_set = SyntaxSet::load_defaults_newlines();
-    let themes = ThemeSet::load_defaults();
-    let theme_name = match theme {
-        Theme::Light => "InspiredGitHub",
-        Theme::Dark => "base16-ocean.dark",
-    };
-    let syntect_theme = themes
-        .themes
-        .get(theme_name)
-        .or_else(|| themes.themes.values().next())
-        .expect("syntect theme available");
+    pub fn from_source(
+        source: DocumentSource,
+        markdown: String,
+        renderer: &Renderer,
+        theme: &Theme,
+    ) -> Self {
+        let mut document = Self::unrendered(source, renderer.remote_content);
+        document.set_markdown(markdown, renderer, theme);
+        document
+    }
+
+    fn unrendered(source: DocumentSource, remote_content: RemoteContent) -> Self {
+        Self {
+            title: source.name(),
+            source,
+            source_markdown: None,
+            rendered_html: String::new(),
+            outline: Vec::new(),
+            fragment: None,
+            history: History::default(),
+            remote_content,
+            blocked_remote: 0,
+            scroll_position: None,
+            encoding: TextEncoding::default(),
+            pending_render: None,
+            navigation: None,
+            reencoding: None,
+        }
+    }
+
+    pub fn open_error(path: &Path, error_text: &str) -> Self {
+        let escaped = html_escape::encode_text(error_text);
+        Self {
+            title: filename_or_path(path),
+            source: DocumentSource::File(path.to_path_buf()),
+            source_markdown: None,
+            rendered_html: format!("<h2>Could not open file</h2><p>{escaped}</p>"),
+            outline: Vec::new(),
+            fragment: None,
+            history: History::default(),
+            remote_content: RemoteContent::Block,
+            blocked_remote: 0,
+            scroll_position: None,
+            encoding: TextEncoding::default(),
+            pending_render: None,
+            navigation: None,
+            reencoding: None,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.source_markdown.is_some()
+    }
+
+    /// Whether a render of this tab is running on a worker thread;
+    /// `rendered_html` is out of date until it finishes.
+    pub fn is_rendering(&self) -> bool {
+        self.pending_render.is_some()
+    }
+
+    /// The file shown, or `None` for text that did not come from a file.
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source.path()
+    }
+
+    /// Where the tab is headed: the file a navigation is still loading, or
+    /// else the document's own file.
+    fn location(&self) -> Option<PathBuf> {
+        match &self.navigation {
+            Some(navigation) => Some(navigation.path.clone()),
+            None => self.source_path().map(Path::to_path_buf),
+        }
+    }
+
+    /// The heading of the section that source line `line` belongs to: the
+    /// last heading at or before it.
+    pub fn heading_at_line(&self, line: usize) -> Option<&Heading> {
+        fn find(headings: &[Heading], line: usize) -> Option<&Heading> {
+            let heading = headings.iter().take_while(|h| h.line <= line).last()?;
+            find(&heading.children, line).or(Some(heading))
+        }
+        find(&self.outline, line)
+    }
+
+    /// Renders `markdown` on the calling thread, superseding any render of
+    /// this tab still running.
+    pub fn set_markdown(&mut self, markdown: String, renderer: &Renderer, theme: &Theme) {
+        let rendered = renderer.render(&markdown, theme, self.remote_content);
+        self.source_markdown = Some(markdown);
+        self.pending_render = None;
+        self.apply_render(rendered);
+    }
 
-    let transformed = inject_highlighted_code_blocks(parser, &syntax_set, syntect_theme);
-    let mut rendered = String::new();
-    html::push_html(&mut rendered, transformed.into_iter());
+    fn apply_render(&mut self, rendered: RenderedMarkdown) {
+        let front_matter_title = rendered.front_matter.as_ref().and_then(FrontMatter::title);
+        self.title = match front_matter_title {
+            Some(title) => title.to_string(),
+            None => self.source.name(),
+        };
+        self.rendered_html = rendered.html;
+        self.outline = rendered.outline;
+        self.blocked_remote = rendered.blocked_remote;
+    }
 
-    sanitize_rendered_html(&rendered)
+    /// Banner text about blocked remote images, or `None` when nothing was
+    /// blocked.
+    pub fn blocked_remote_message(&self) -> Option<String> {
+        match self.blocked_remote {
+            0 => None,
+            1 => Some("1 remote image was blocked to protect your privacy.".to_string()),
+            count => Some(format!(
+                "{count} remote images were blocked to protect your privacy."
+            )),
+        }
+    }
 }
 
-pub fn default_body() -> String {
-    "<p class=\"empty\">Use File -&gt; Open... or Ctrl+O to load a Markdown file.</p>".to_string()
+/// How to show a file once it is loaded, see [`AppState::start_load`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Source line whose section to scroll to; wins over `fragment`.
+    pub line: Option<usize>,
+    /// Heading id to scroll to.
+    pub fragment: Option<String>,
+    pub scroll_position: Option<u32>,
+    /// Switch to the tab once it opens.
+    pub select: bool,
+    /// Move the file to the front of the recent files.
+    pub remember: bool,
 }
 
-pub fn render_document(body: &str, theme: Theme) -> String {
-    let (bg, fg, muted, border, code_bg, quote, link) = match theme {
-        Theme::Light => (
-            "#ffffff", "#1f2328", "#57606a", "#d0d7de", "#f6f8fa", "#656d76", "#0969da",
-        ),
-        Theme::Dark => (
-            "#0d1117", "#e6edf3", "#9198a1", "#30363d", "#161b22", "#8b949e", "#4493f8",
-        ),
-    };
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            line: None,
+            fragment: None,
+            scroll_position: None,
+            select: true,
+            remember: true,
+        }
+    }
+}
 
-    format!(
-        r#"<!doctype html>
-<html lang="en">
-  <head>
-    <meta charset="utf-8" />
-    <meta name="viewport" content="width=device-width, initial-scale=1" />
-    <style>
-      html, body {{
-        margin: 0;
-        padding: 0;
-        background: {bg};
-        color: {fg};
-        font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", "Noto Sans", Helvetica, Arial, sans-serif;
-      }}
-      .markdown-body {{
-        box-sizing: border-box;
-        max-width: 980px;
-        margin: 0 auto;
-        padding: 32px;
-        line-height: 1.6;
-        word-wrap: break-word;
-      }}
-      @media (max-width: 767px) {{ .markdown-body {{ padding: 18px; }} }}
-      .empty {{ color: {muted}; }}
-      .markdown-body h1, .markdown-body h2, .markdown-body h3,
-      .markdown-body h4, .markdown-body h5, .markdown-body h6 {{
-        margin-top: 24px;
-        margin-bottom: 16px;
-        font-weight: 600;
-        line-height: 1.25;
-        border-bottom: 1px solid transparent;
-      }}
-      .markdown-body h1, .markdown-body h2 {{
-        padding-bottom: 0.3em;
-        border-bottom-color: {border};
-      }}
-      .markdown-body p, .markdown-body ul, .markdown-body ol,
-      .markdown-body table, .markdown-body pre, .markdown-body blockquote {{
-        margin-top: 0;
-        margin-bottom: 16px;
-      }}
-      .markdown-body a {{ color: {link}; text-decoration: none; }}
-      .markdown-body a:hover {{ text-decoration: underline; }}
-      .markdown-body code, .markdown-body tt {{
-        padding: 0.2em 0.4em;
-        margin: 0;
-        font-size: 85%;
-        border-radius: 6px;
-        background: {code_bg};
-      }}
-      .markdown-body pre {{
-        padding: 16px;
-        overflow: auto;
-        border-radius: 8px;
-        background: {code_bg};
-      }}
-      .markdown-body pre code {{ padding: 0; background: transparent; border-radius: 0; }}
-      .markdown-body blockquote {{
-        padding: 0 1em;
-        color: {quote};
-        border-left: 0.25em solid {border};
-      }}
-      .markdown-body table {{
-        display: block;
-        width: max-content;
-        max-width: 100%;
-        overflow: auto;
-        border-collapse: collapse;
-      }}
-      .markdown-body table th, .markdown-body table td {{
-        padding: 6px 13px;
-        border: 1px solid {border};
-      }}
-      .markdown-body hr {{
-        height: 0.25em;
-        margin: 24px 0;
-        background: {border};
-        border: 0;
-      }}
-      .markdown-body img {{ max-width: 100%; height: auto; }}
-    </style>
-  </head>
-  <body>
-    <article class="markdown-body">{body}</article>
-  </body>
-</html>"#
-    )
+/// A load replacing the document of a tab, see [`AppState::follow_link`].
+/// The tab's history moves when the load starts, so pressing back twice
+/// quickly goes back two steps.
+#[derive(Debug)]
+struct PendingNavigation {
+    id: u64,
+    path: PathBuf,
+    /// The history from before the navigations still running, put back when
+    /// they fail or are cancelled.
+    restore: History,
 }
 
-pub fn filename_or_path(path: &Path) -> String {
-    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-        name.to_string()
-    } else {
-        path.to_string_lossy().into_owned()
+/// A file being read and rendered for a new tab, see
+/// [`AppState::start_load`], or to replace the document of a tab, see
+/// [`AppState::follow_link`]. Cancelling cannot interrupt the renderer, so a
+/// load cancelled midway still finishes and its result is dropped.
+#[derive(Debug, Clone)]
+pub struct PendingLoad {
+    pub id: u64,
+    pub path: PathBuf,
+    options: LoadOptions,
+    navigation: bool,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PendingLoad {
+    /// Reads and renders the file; meant for a worker thread. `None` when
+    /// the load was cancelled before it started.
+    pub fn run(&self, renderer: &Renderer, theme: &Theme) -> Option<Document> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut document = Document::load(&self.path, renderer, theme);
+        document.fragment = match self.options.line {
+            Some(line) => document
+                .heading_at_line(line)
+                .map(|heading| heading.id.clone()),
+            None => self.options.fragment.clone(),
+        };
+        document.scroll_position = self.options.scroll_position;
+        Some(document)
     }
 }
 
-fn sanitize_rendered_html(dirty_html: &str) -> String {
-    let mut sanitizer = HtmlSanitizer::default();
-    sanitizer.add_tag_attributes("a", &["href", "title"]);
-    sanitizer.add_tag_attributes("img", &["src", "alt", "title"]);
-    sanitizer.add_tag_attributes("code", &["class"]);
-    sanitizer.add_tag_attributes("pre", &["class", "style"]);
-    sanitizer.add_tag_attributes("span", &["class", "style"]);
+/// Why a tab is rendered again, which decides how the frontend shows the
+/// result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderReason {
+    /// The theme changed, so the whole page is rebuilt.
+    Theme,
+    /// The Markdown or the remote content policy changed; only the body is
+    /// replaced, keeping the scroll position.
+    Content,
+}
 
-    sanitizer.clean(dirty_html).to_string()
+/// A render of one tab to run off the UI thread, see
+/// [`AppState::render_jobs`]. The frontend runs it on a worker and passes
+/// the result to [`AppState::finish_render`].
+pub struct RenderJob {
+    id: u64,
+    pub reason: RenderReason,
+    input: RenderInput,
+    theme: Theme,
+    remote_content: RemoteContent,
 }
 
-fn inject_highlighted_code_blocks<'a>(
-    parser: Parser<'a>,
-    syntax_set: &SyntaxSet,
-    syntect_theme: &SyntectTheme,
-) -> Vec<Event<'a>> {
-    let mut output = Vec::new();
-    let mut iter = parser.into_iter();
+enum RenderInput {
+    Markdown(String),
+    /// Read the file again, decoded as the given encoding.
+    File(PathBuf, &'static encoding_rs::Encoding),
+}
 
-    while let Some(event) = iter.next() {
-        match event {
-            Event::Start(Tag::CodeBlock(kind)) => {
-                let language = match kind {
-                    CodeBlockKind::Fenced(info) => info
-                        .split_whitespace()
-                        .next()
-                        .map(|s| s.trim_start_matches("language-").to_string()),
-                    CodeBlockKind::Indented => None,
+impl RenderJob {
+    pub fn run(self, renderer: &Renderer) -> FinishedRender {
+        let (markdown, reread) = match self.input {
+            RenderInput::Markdown(markdown) => (markdown, None),
+            RenderInput::File(path, encoding) => {
+                let Ok(bytes) = fs::read(&path) else {
+                    return FinishedRender {
+                        id: self.id,
+                        reason: self.reason,
+                        rendered: None,
+                        reread: None,
+                    };
                 };
-
-                let mut code = String::new();
-                for code_event in iter.by_ref() {
-                    match code_event {
-                        Event::End(TagEnd::CodeBlock) => break,
-                        Event::Text(text) | Event::Code(text) | Event::Html(text) => {
-                            code.push_str(&text)
-                        }
-                        Event::SoftBreak | Event::HardBreak => code.push('\n'),
-                        _ => {}
-                    }
-                }
-
-                let highlighted =
-                    highlight_code_block(&code, language.as_deref(), syntax_set, syntect_theme);
-                output.push(Event::Html(CowStr::from(highlighted)));
+                let (markdown, encoding) = encoding::decode(&bytes, Some(encoding));
+                (markdown, Some(encoding))
             }
-            other => output.push(other),
+        };
+        FinishedRender {
+            id: self.id,
+            reason: self.reason,
+            rendered: Some(renderer.render(&markdown, &self.theme, self.remote_content)),
+            reread: reread.map(|encoding| (markdown, encoding)),
         }
     }
+}
 
-    output
+pub struct FinishedRender {
+    id: u64,
+    pub reason: RenderReason,
+    /// `None` when the file to read again could not be read.
+    rendered: Option<RenderedMarkdown>,
+    /// The Markdown read again for the render, with its encoding.
+    reread: Option<(String, TextEncoding)>,
 }
 
-fn highlight_code_block(
-    code: &str,
-    language: Option<&str>,
-    syntax_set: &SyntaxSet,
-    syntect_theme: &SyntectTheme,
-) -> String {
-    let syntax = language
-        .and_then(|lang| syntax_set.find_syntax_by_token(lang))
-        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+/// Back/forward stacks of a tab, filled by following links between documents.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct History {
+    back: Vec<PathBuf>,
+    forward: Vec<PathBuf>,
+}
 
-    highlighted_html_for_string(code, syntax_set, syntax, syntect_theme).unwrap_or_else(|_| {
-        let escaped = html_escape::encode_text(code);
-        format!("<pre><code>{escaped}</code></pre>")
-    })
+impl History {
+    /// Records leaving `from` for a new document; drops the forward stack.
+    pub fn visit(&mut self, from: PathBuf) {
+        self.back.push(from);
+        self.forward.clear();
+    }
+
+    pub fn back(&mut self, current: PathBuf) -> Option<PathBuf> {
+        let target = self.back.pop()?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    pub fn forward(&mut self, current: PathBuf) -> Option<PathBuf> {
+        let target = self.forward.pop()?;
+        self.back.push(current);
+        Some(target)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub struct AppState {
+    pub theme: Theme,
+    /// Themes offered in the View menu: the built-ins plus user themes.
+    pub themes: Vec<Theme>,
+    pub documents: Vec<Document>,
+    pub active: usize,
+    /// Ask before handing a link to the system browser.
+    pub confirm_external_links: bool,
+    /// Recently opened files, newest first.
+    pub recent: Vec<PathBuf>,
+    /// Files of closed tabs, the last closed at the end.
+    closed: Vec<PathBuf>,
+    /// Files larger than this many megabytes are only rendered once
+    /// confirmed, see [`large_text_file`].
+    pub max_file_size_mb: u64,
+    /// Files being read for new tabs, oldest first.
+    loads: Vec<PendingLoad>,
+    /// Results of loads that wait for an older load to open first.
+    finished_loads: Vec<(u64, Option<Document>)>,
+    next_load_id: u64,
+    next_render_id: u64,
+}
 
-    #[test]
-    fn toggled_switches_between_themes() {
-        assert!(matches!(Theme::Light.toggled(), Theme::Dark));
-        assert!(matches!(Theme::Dark.toggled(), Theme::Light));
+impl AppState {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+            themes: vec![Theme::light(), Theme::dark()],
+            documents: Vec::new(),
+            active: 0,
+            confirm_external_links: true,
+            recent: Vec::new(),
+            closed: Vec::new(),
+            max_file_size_mb: Config::default().max_file_size_mb,
+            loads: Vec::new(),
+            finished_loads: Vec::new(),
+            next_load_id: 0,
+            next_render_id: 0,
+        }
     }
 
-    #[test]
-    fn render_markdown_sanitizes_unsafe_html() {
-        let rendered = render_markdown(
-            r#"<script>alert('xss')</script><a href="javascript:alert(1)">click</a>"#,
-            Theme::Light,
-        );
+    /// Requests a render of tab `index`, superseding any still running for
+    /// it. `None` for a tab that failed to open.
+    fn queue_render(&mut self, index: usize, reason: RenderReason) -> Option<RenderJob> {
+        let document = self.documents.get_mut(index)?;
+        let markdown = document.source_markdown.clone()?;
+        let input = match (document.reencoding, document.source_path()) {
+            (Some(encoding), Some(path)) => RenderInput::File(path.to_path_buf(), encoding),
+            _ => RenderInput::Markdown(markdown),
+        };
+        self.next_render_id += 1;
+        document.pending_render = Some(self.next_render_id);
+        Some(RenderJob {
+            id: self.next_render_id,
+            reason,
+            input,
+            theme: self.theme.clone(),
+            remote_content: document.remote_content,
+        })
+    }
 
-        assert!(!rendered.contains("<script"));
-        assert!(!rendered.contains("javascript:"));
+    /// Renders of every loaded tab with the current theme, the active tab
+    /// first; for after a theme switch.
+    pub fn render_jobs(&mut self) -> Vec<RenderJob> {
+        let mut order: Vec<usize> = (0..self.documents.len()).collect();
+        order.sort_by_key(|&index| index != self.active);
+        order
+            .into_iter()
+            .filter_map(|index| self.queue_render(index, RenderReason::Theme))
+            .collect()
     }
 
-    #[test]
-    fn render_markdown_renders_fenced_code_blocks() {
-        let rendered = render_markdown("```rust\nfn main() {}\n```", Theme::Dark);
+    /// Shows a finished render in its tab and returns the tab index. `None`
+    /// when the tab was closed or a newer render of it was requested since,
+    /// so the result is stale, or when the file to read again could not be
+    /// read, leaving the tab as it was.
+    pub fn finish_render(&mut self, render: FinishedRender) -> Option<usize> {
+        let index = self
+            .documents
+            .iter()
+            .position(|document| document.pending_render == Some(render.id))?;
+        let document = &mut self.documents[index];
+        document.pending_render = None;
+        document.reencoding = None;
+        let rendered = render.rendered?;
+        if let Some((markdown, encoding)) = render.reread {
+            document.source_markdown = Some(markdown);
+            document.encoding = encoding;
+        }
+        document.apply_render(rendered);
+        Some(index)
+    }
 
-        assert!(rendered.contains("<pre"));
-        assert!(rendered.contains("main"));
+    /// Loads remote images for the active tab only; other tabs, and this
+    /// one once reopened, keep blocking them.
+    pub fn allow_remote_content(&mut self) -> Option<RenderJob> {
+        self.active_document_mut()?.remote_content = RemoteContent::Allow;
+        self.queue_render(self.active, RenderReason::Content)
     }
 
-    #[test]
-    fn render_document_applies_theme_palette() {
-        let light = render_document("<p>Hello</p>", Theme::Light);
-        let dark = render_document("<p>Hello</p>", Theme::Dark);
+    /// Reads the file of the active tab again on a worker, decoded as
+    /// `encoding` from now on, live reloads included. `None` when it shows
+    /// no file; a file that cannot be read leaves the tab as it was.
+    pub fn reopen_with_encoding(
+        &mut self,
+        encoding: &'static encoding_rs::Encoding,
+    ) -> Option<RenderJob> {
+        let document = self.active_document_mut().filter(|d| d.is_loaded())?;
+        document.source_path()?;
+        document.reencoding = Some(encoding);
+        self.queue_render(self.active, RenderReason::Content)
+    }
 
-        assert!(light.contains("background: #ffffff"));
-        assert!(dark.contains("background: #0d1117"));
-        assert!(light.contains("<article class=\"markdown-body\"><p>Hello</p></article>"));
-        assert!(dark.contains("<article class=\"markdown-body\"><p>Hello</p></article>"));
+    /// Opens Markdown that does not come from a file in a new tab, blank
+    /// until the returned render of it finishes. Only the Linux frontend
+    /// opens dropped text.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn open_text(
+        &mut self,
+        source: DocumentSource,
+        markdown: String,
+        remote_content: RemoteContent,
+    ) -> (usize, Option<RenderJob>) {
+        let mut document = Document::unrendered(source, remote_content);
+        document.source_markdown = Some(markdown);
+        let index = self.open_document(document);
+        (index, self.queue_render(index, RenderReason::Theme))
     }
 
-    #[test]
-    fn filename_or_path_prefers_filename_when_present() {
-        let file = Path::new("/tmp/sample.md");
-        let root = Path::new("/");
+    /// Registers a file to read and render off the UI thread. The frontend
+    /// runs the returned load on a worker and passes its result to
+    /// [`AppState::finish_load`].
+    pub fn start_load(&mut self, path: &Path, options: LoadOptions) -> PendingLoad {
+        self.next_load_id += 1;
+        let load = PendingLoad {
+            id: self.next_load_id,
+            path: path.to_path_buf(),
+            options,
+            navigation: false,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        self.loads.push(load.clone());
+        load
+    }
 
-        assert_eq!(filename_or_path(file), "sample.md");
-        assert_eq!(filename_or_path(root), "/");
+    /// Starts loading a linked document to show in the active tab, like
+    /// following a link in a browser: the document on screen goes onto the
+    /// tab's back stack, even when a navigation started before is still
+    /// loading. Without a tab it opens in a new one.
+    pub fn follow_link(&mut self, link: MarkdownLink) -> PendingLoad {
+        let options = LoadOptions {
+            fragment: link.fragment,
+            ..LoadOptions::default()
+        };
+        let Some(document) = self.documents.get_mut(self.active) else {
+            return self.start_load(&link.path, options);
+        };
+        let history = match &document.navigation {
+            Some(navigation) => navigation.restore.clone(),
+            None => document.history.clone(),
+        };
+        document.history = history.clone();
+        if let Some(from) = document.source_path() {
+            document.history.visit(from.to_path_buf());
+        }
+        self.start_navigation(&link.path, options, history)
     }
 
-    #[test]
-    fn default_body_contains_open_instruction() {
-        let body = default_body();
-        assert!(body.contains("Ctrl+O"));
-        assert!(body.contains("Open"));
+    /// Starts loading the document before the active one in its tab's
+    /// history. `None` when there is nowhere to go back to.
+    pub fn go_back(&mut self) -> Option<PendingLoad> {
+        let document = self.documents.get_mut(self.active)?;
+        let history = document.history.clone();
+        let from = document.location()?;
+        let target = document.history.back(from)?;
+        Some(self.start_navigation(&target, LoadOptions::default(), history))
     }
 
-    #[test]
-    fn fixture_markdown_samples_render_expected_html_snippets() {
-        let basic = include_str!("../tests/fixtures/markdown/basic.md");
-        let table_tasklist = include_str!("../tests/fixtures/markdown/table-tasklist.md");
-        let unsafe_markdown = include_str!("../tests/fixtures/markdown/unsafe.md");
+    pub fn go_forward(&mut self) -> Option<PendingLoad> {
+        let document = self.documents.get_mut(self.active)?;
+        let history = document.history.clone();
+        let from = document.location()?;
+        let target = document.history.forward(from)?;
+        Some(self.start_navigation(&target, LoadOptions::default(), history))
+    }
+
+    /// Registers a load to replace the active document, whose history the
+    /// caller already moved from `history`. It supersedes a load still
+    /// running for the same tab, whose result is then dropped.
+    fn start_navigation(
+        &mut self,
+        path: &Path,
+        options: LoadOptions,
+        history: History,
+    ) -> PendingLoad {
+        self.next_load_id += 1;
+        let load = PendingLoad {
+            id: self.next_load_id,
+            path: path.to_path_buf(),
+            options,
+            navigation: true,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let superseded = self.documents[self.active].navigation.take();
+        if let Some(position) = superseded
+            .as_ref()
+            .and_then(|superseded| self.loads.iter().position(|load| load.id == superseded.id))
+        {
+            self.loads
+                .remove(position)
+                .cancelled
+                .store(true, Ordering::Relaxed);
+        }
+        self.documents[self.active].navigation = Some(PendingNavigation {
+            id: load.id,
+            path: load.path.clone(),
+            restore: superseded.map_or(history, |superseded| superseded.restore),
+        });
+        self.loads.push(load.clone());
+        load
+    }
+
+    /// Takes the document of load `id`, `None` when the worker gave up, and
+    /// opens the finished loads in tabs in the order they were started, so
+    /// tabs keep the order of the files asked for. Returns the indices of
+    /// the tabs opened: none when the load was cancelled or an older one is
+    /// still running.
+    ///
+    /// A load started by [`AppState::follow_link`] or the back/forward
+    /// buttons shows right away in the tab it was started from, unless a
+    /// newer one for that tab superseded it or the tab was closed.
+    pub fn finish_load(&mut self, id: u64, document: Option<Document>) -> Vec<usize> {
+        let Some(position) = self.loads.iter().position(|load| load.id == id) else {
+            return Vec::new();
+        };
+        if self.loads[position].navigation {
+            self.loads.remove(position);
+            return self.finish_navigation(id, document).into_iter().collect();
+        }
+        self.finished_loads.push((id, document));
+        let mut opened = Vec::new();
+        while let Some(next) = self.loads.iter().position(|load| !load.navigation)
+            && let Some(position) = self
+                .finished_loads
+                .iter()
+                .position(|(finished, _)| *finished == self.loads[next].id)
+        {
+            let load = self.loads.remove(next);
+            let (_, document) = self.finished_loads.remove(position);
+            if let Some(document) = document {
+                opened.push(self.open_loaded(document, &load.options));
+            }
+        }
+        opened
+    }
+
+    fn finish_navigation(&mut self, id: u64, document: Option<Document>) -> Option<usize> {
+        let index = self.documents.iter().position(|document| {
+            (document.navigation.as_ref()).is_some_and(|navigation| navigation.id == id)
+        })?;
+        let current = &mut self.documents[index];
+        let navigation = current.navigation.take()?;
+        let Some(mut document) = document else {
+            current.history = navigation.restore;
+            return None;
+        };
+        document.history = std::mem::take(&mut current.history);
+        *current = document;
+        self.remember(index);
+        Some(index)
+    }
+
+    fn open_loaded(&mut self, document: Document, options: &LoadOptions) -> usize {
+        let index = self.place_document(document);
+        if options.select {
+            self.active = index;
+        }
+        if options.remember {
+            self.remember(index);
+        }
+        index
+    }
+
+    /// Cancels every load in progress; returns whether there were any.
+    pub fn cancel_loads(&mut self) -> bool {
+        for load in &self.loads {
+            load.cancelled.store(true, Ordering::Relaxed);
+        }
+        let cancelled = !self.loads.is_empty();
+        self.loads.clear();
+        self.finished_loads.clear();
+        for document in &mut self.documents {
+            if let Some(navigation) = document.navigation.take() {
+                document.history = navigation.restore;
+            }
+        }
+        cancelled
+    }
+
+    /// Text for the loading indicator, or `None` when nothing is loading.
+    pub fn loading_message(&self) -> Option<String> {
+        match self.loads.as_slice() {
+            [] => None,
+            [load] => Some(format!("Loading {}…", filename_or_path(&load.path))),
+            loads => Some(format!("Loading {} files…", loads.len())),
+        }
+    }
+
+    pub fn active_document(&self) -> Option<&Document> {
+        self.documents.get(self.active)
+    }
+
+    pub fn active_document_mut(&mut self) -> Option<&mut Document> {
+        self.documents.get_mut(self.active)
+    }
+
+    /// Shows `document` in a new tab, or in place of the tab already showing
+    /// the same file, and makes it active. Returns the tab index.
+    pub fn open_document(&mut self, document: Document) -> usize {
+        let index = self.place_document(document);
+        self.active = index;
+        self.remember(index);
+        index
+    }
+
+    fn place_document(&mut self, document: Document) -> usize {
+        let existing = document.source_path().and_then(|path| {
+            self.documents
+                .iter()
+                .position(|open| open.source_path() == Some(path))
+        });
+        match existing {
+            Some(index) => {
+                self.documents[index] = document;
+                index
+            }
+            None => {
+                self.documents.push(document);
+                self.documents.len() - 1
+            }
+        }
+    }
+
+    /// Closes the active tab; the tab after it (or the new last tab) becomes
+    /// active.
+    pub fn close_active(&mut self) -> Option<Document> {
+        if self.active >= self.documents.len() {
+            return None;
+        }
+        let closed = self.documents.remove(self.active);
+        self.active = self.active.min(self.documents.len().saturating_sub(1));
+        if let Some(path) = closed.source_path() {
+            self.closed.push(path.to_path_buf());
+        }
+        Some(closed)
+    }
+
+    /// Takes the file of the most recently closed tab that still exists,
+    /// for reopening it.
+    pub fn take_closed(&mut self) -> Option<PathBuf> {
+        while let Some(path) = self.closed.pop() {
+            if path.exists() {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    /// The recent files that still exist, newest first. File → Open Recent
+    /// and the `recent:N` command use this order.
+    pub fn recent_files(&self) -> Vec<PathBuf> {
+        self.recent
+            .iter()
+            .filter(|path| path.exists())
+            .cloned()
+            .collect()
+    }
+
+    /// Moves the file shown in tab `index` to the front of the recent
+    /// files, or drops it from them when it could not be opened.
+    fn remember(&mut self, index: usize) {
+        let Some(document) = self.documents.get(index) else {
+            return;
+        };
+        let Some(path) = document.source_path().map(Path::to_path_buf) else {
+            return;
+        };
+        self.recent.retain(|recent| *recent != path);
+        if document.is_loaded() {
+            self.recent.insert(0, path);
+            self.recent.truncate(MAX_RECENT_FILES);
+        }
+    }
+
+    pub fn select(&mut self, index: usize) -> bool {
+        let valid = index < self.documents.len();
+        if valid {
+            self.active = index;
+        }
+        valid
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.documents.is_empty() {
+            self.active = (self.active + 1) % self.documents.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.documents.is_empty() {
+            self.active = (self.active + self.documents.len() - 1) % self.documents.len();
+        }
+    }
+
+    /// Replaces the theme list with the built-ins plus the user themes from
+    /// the config directory, with the configured fonts and width applied.
+    /// Switches to the configured theme, or else keeps the current theme by
+    /// name. Returns the theme files that could not be loaded.
+    pub fn load_themes(&mut self, config: &Config, renderer: &Renderer) -> Vec<ConfigError> {
+        let dir = config_dir().map(|dir| dir.join("themes"));
+        let (mut themes, mut errors) =
+            theme::load_themes(dir.as_deref(), |name| renderer.has_syntax_theme(name));
+        for theme in &mut themes {
+            config.apply_to_theme(theme);
+        }
+        self.themes = themes;
+
+        let configured = config
+            .theme
+            .as_ref()
+            .is_some_and(|name| self.select_theme_by_name(name));
+        if !configured {
+            if let Some(name) = &config.theme {
+                errors.push(ConfigError {
+                    path: config::config_path().unwrap_or_default(),
+                    message: format!("theme: unknown theme '{name}'"),
+                });
+            }
+            if let Some(index) = self.theme_index() {
+                self.set_theme(index);
+            }
+        }
+        errors
+    }
+
+    /// Switches to the theme called `name`, ignoring case. Returns whether
+    /// there is such a theme.
+    pub fn select_theme_by_name(&mut self, name: &str) -> bool {
+        let index = self
+            .themes
+            .iter()
+            .position(|theme| theme.name.eq_ignore_ascii_case(name));
+        index.is_some_and(|index| self.set_theme(index))
+    }
+
+    /// Index in `themes` of the current theme.
+    pub fn theme_index(&self) -> Option<usize> {
+        self.themes
+            .iter()
+            .position(|theme| theme.name == self.theme.name)
+    }
+
+    /// Switches to theme `index`. Open tabs keep their old rendering until
+    /// the frontend runs [`AppState::render_jobs`].
+    pub fn set_theme(&mut self, index: usize) -> bool {
+        let Some(theme) = self.themes.get(index) else {
+            return false;
+        };
+        self.theme = theme.clone();
+        true
+    }
+
+    /// Switches between the Light and Dark themes (or the user themes that
+    /// replace them), depending on whether the current theme is dark.
+    pub fn toggle_theme(&mut self) {
+        let target = if self.theme.dark {
+            Theme::light()
+        } else {
+            Theme::dark()
+        };
+        match self
+            .themes
+            .iter()
+            .position(|theme| theme.name == target.name)
+        {
+            Some(index) => {
+                self.set_theme(index);
+            }
+            None => {
+                self.themes.push(target);
+                self.set_theme(self.themes.len() - 1);
+            }
+        }
+    }
+
+    pub fn window_title(&self) -> String {
+        match self.active_document() {
+            Some(document) if document.is_loaded() => format!("{APP_TITLE} - {}", document.title),
+            _ => APP_TITLE.to_string(),
+        }
+    }
+
+    /// Paths of successfully loaded documents, each listed once.
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for document in self.documents.iter().filter(|d| d.is_loaded()) {
+            if let Some(path) = document.source_path()
+                && !paths.iter().any(|known| known == path)
+            {
+                paths.push(path.to_path_buf());
+            }
+        }
+        paths
+    }
+
+    /// Takes the new `bytes` of `path` into every loaded tab showing it and
+    /// returns the renders to run for them.
+    pub fn reload_path(&mut self, path: &Path, bytes: &[u8]) -> Vec<RenderJob> {
+        let mut reloaded = Vec::new();
+        for (index, document) in self.documents.iter_mut().enumerate() {
+            if document.is_loaded() && document.source_path() == Some(path) {
+                let chosen = document.reencoding.take().or(document.encoding.chosen());
+                let (markdown, encoding) = encoding::decode(bytes, chosen);
+                document.source_markdown = Some(markdown);
+                document.encoding = encoding;
+                reloaded.push(index);
+            }
+        }
+        reloaded
+            .into_iter()
+            .filter_map(|index| self.queue_render(index, RenderReason::Content))
+            .collect()
+    }
+}
+
+/// Tracks a file on disk and debounces change notifications into reloads.
+///
+/// Changes can be reported by a native watcher through [`ReloadWatch::notify`]
+/// or discovered by comparing modification times in [`ReloadWatch::poll`].
+pub struct ReloadWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    pending_since: Option<Instant>,
+}
+
+impl ReloadWatch {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            modified: modified_time(path),
+            pending_since: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn notify(&mut self, now: Instant) {
+        self.pending_since = Some(now);
+    }
+
+    pub fn poll(&mut self, now: Instant) {
+        let modified = modified_time(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            self.notify(now);
+        }
+    }
+
+    /// Returns `true` once no change has been reported for [`RELOAD_DEBOUNCE`].
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        match self.pending_since {
+            Some(since) if now.duration_since(since) >= RELOAD_DEBOUNCE => {
+                self.pending_since = None;
+                self.modified = modified_time(&self.path);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Reads the file at `path`, or returns `None` when its start looks binary,
+/// so that a large binary is not read in full.
+fn read_unless_binary(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path)?;
+    let mut bytes = Vec::new();
+    (&mut file)
+        .take(encoding::SNIFF_LEN as u64)
+        .read_to_end(&mut bytes)?;
+    if encoding::looks_binary(&bytes) {
+        return Ok(None);
+    }
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// Size in bytes of the file at `path` when it is over `limit_mb` megabytes,
+/// so the frontends ask before rendering it. `None` for files within the
+/// limit and for binary files, which are refused without being read.
+pub fn large_text_file(path: &Path, limit_mb: u64) -> Option<u64> {
+    let size = fs::metadata(path).ok().filter(|meta| meta.is_file())?.len();
+    if size <= limit_mb.saturating_mul(1024 * 1024) {
+        return None;
+    }
+    let mut start = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(encoding::SNIFF_LEN as u64)
+        .read_to_end(&mut start)
+        .ok()?;
+    (!encoding::looks_binary(&start)).then_some(size)
+}
+
+/// Question asked before rendering a file found by [`large_text_file`].
+pub fn large_file_prompt(path: &Path, size: u64, limit_mb: u64) -> String {
+    format!(
+        "{} is {:.1} MB, more than the {limit_mb} MB set by files.max_size_mb. \
+         Rendering it may take a while.",
+        filename_or_path(path),
+        size as f64 / (1024.0 * 1024.0)
+    )
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Position within the matches of an in-document search, for the find bar's
+/// "3 of 12" status. The webview does the matching and wraps around at the
+/// ends, so stepping here wraps the same way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FindStatus {
+    pub total: u32,
+    pub current: u32,
+}
+
+impl FindStatus {
+    pub fn with_total(total: u32) -> Self {
+        Self {
+            total,
+            current: u32::from(total > 0),
+        }
+    }
+
+    pub fn next(&mut self) {
+        if self.total > 0 {
+            self.current = self.current % self.total + 1;
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if self.total > 0 {
+            self.current = (self.current + self.total - 2) % self.total + 1;
+        }
+    }
+
+    pub fn label(&self) -> String {
+        if self.total == 0 {
+            "No matches".to_string()
+        } else {
+            format!("{} of {}", self.current, self.total)
+        }
+    }
+}
+
+pub fn detect_theme() -> Theme {
+    match dark_light::detect() {
+        Mode::Dark => Theme::dark(),
+        _ => Theme::light(),
+    }
+}
+
+/// Per-user configuration directory: `%APPDATA%\dustrown` on Windows,
+/// `$XDG_CONFIG_HOME/dustrown` or `~/.config/dustrown` elsewhere.
+pub fn config_dir() -> Option<PathBuf> {
+    user_dir("APPDATA", "XDG_CONFIG_HOME", ".config")
+}
+
+/// Per-user cache directory: `%LOCALAPPDATA%\dustrown` on Windows,
+/// `$XDG_CACHE_HOME/dustrown` or `~/.cache/dustrown` elsewhere.
+pub fn cache_dir() -> Option<PathBuf> {
+    user_dir("LOCALAPPDATA", "XDG_CACHE_HOME", ".cache")
+}
+
+/// Per-user state directory, for the session: `%LOCALAPPDATA%\dustrown` on
+/// Windows, `$XDG_STATE_HOME/dustrown` or `~/.local/state/dustrown`
+/// elsewhere.
+pub fn state_dir() -> Option<PathBuf> {
+    user_dir("LOCALAPPDATA", "XDG_STATE_HOME", ".local/state")
+}
+
+fn user_dir(windows_var: &str, xdg_var: &str, home_subdir: &str) -> Option<PathBuf> {
+    let non_empty = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(target_os = "windows") {
+        PathBuf::from(non_empty(windows_var)?)
+    } else {
+        non_empty(xdg_var)
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(home_subdir)))?
+    };
+    Some(base.join("dustrown"))
+}
+
+/// A heading in the document outline, nested under the closest preceding
+/// heading of a lower level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    pub title: String,
+    pub id: String,
+    /// Line of the heading in the Markdown source, counted from 1.
+    pub line: usize,
+    pub children: Vec<Heading>,
+}
+
+/// Whether rendered documents may load images from the network. Blocking
+/// avoids leaking that (and when) a document was opened.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RemoteContent {
+    Block,
+    Allow,
+}
+
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub outline: Vec<Heading>,
+    pub blocked_remote: usize,
+    pub front_matter: Option<FrontMatter>,
+}
+
+/// A value from a document's front matter. Scalars (numbers, dates,
+/// booleans) are kept as their text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataValue {
+    Text(String),
+    List(Vec<MetadataValue>),
+    Map(BTreeMap<String, MetadataValue>),
+}
+
+impl MetadataValue {
+    fn from_yaml(value: Yaml) -> Option<Self> {
+        Some(match value {
+            Yaml::Null | Yaml::BadValue | Yaml::Alias(_) => return None,
+            Yaml::Boolean(value) => Self::Text(value.to_string()),
+            Yaml::Integer(value) => Self::Text(value.to_string()),
+            Yaml::Real(value) | Yaml::String(value) => Self::Text(value),
+            Yaml::Array(values) => {
+                Self::List(values.into_iter().filter_map(Self::from_yaml).collect())
+            }
+            Yaml::Hash(hash) => Self::Map(
+                hash.into_iter()
+                    .filter_map(|(key, value)| match Self::from_yaml(key)? {
+                        Self::Text(key) => Some((key, Self::from_yaml(value)?)),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    fn from_toml(value: toml::Value) -> Self {
+        use toml::Value;
+        match value {
+            Value::String(value) => Self::Text(value),
+            Value::Integer(value) => Self::Text(value.to_string()),
+            Value::Float(value) => Self::Text(value.to_string()),
+            Value::Boolean(value) => Self::Text(value.to_string()),
+            Value::Datetime(value) => Self::Text(value.to_string()),
+            Value::Array(values) => Self::List(values.into_iter().map(Self::from_toml).collect()),
+            Value::Table(table) => Self::Map(
+                table
+                    .into_iter()
+                    .map(|(key, value)| (key, Self::from_toml(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Text items of a list, or the comma-separated parts of a text value.
+    fn items(&self) -> Vec<&str> {
+        match self {
+            Self::Text(text) => text.split(',').map(str::trim).collect(),
+            Self::List(values) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Self::Text(text) => Some(text.trim()),
+                    _ => None,
+                })
+                .collect(),
+            Self::Map(_) => Vec::new(),
+        }
+        .into_iter()
+        .filter(|item| !item.is_empty())
+        .collect()
+    }
+}
+
+/// YAML (`---`) or TOML (`+++`) metadata at the top of a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    pub fields: BTreeMap<String, MetadataValue>,
+}
+
+impl FrontMatter {
+    /// Parses a metadata block; `None` unless it is a valid key/value map.
+    fn parse(kind: MetadataBlockKind, text: &str) -> Option<Self> {
+        let value = match kind {
+            MetadataBlockKind::YamlStyle => {
+                let mut documents = YamlLoader::load_from_str(text).ok()?;
+                MetadataValue::from_yaml(documents.drain(..).next()?)
+            }
+            MetadataBlockKind::PlusesStyle => Some(MetadataValue::from_toml(toml::Value::Table(
+                toml::from_str(text).ok()?,
+            ))),
+        };
+        match value {
+            Some(MetadataValue::Map(fields)) => Some(Self { fields }),
+            _ => None,
+        }
+    }
+
+    fn text(&self, key: &str) -> Option<&str> {
+        match self.fields.get(key)? {
+            MetadataValue::Text(text) => Some(text.trim()).filter(|text| !text.is_empty()),
+            _ => None,
+        }
+    }
+
+    fn items(&self, keys: &[&str]) -> Vec<&str> {
+        keys.iter()
+            .find_map(|key| self.fields.get(*key))
+            .map_or_else(Vec::new, MetadataValue::items)
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.text("title")
+    }
+
+    pub fn authors(&self) -> Vec<&str> {
+        self.items(&["author", "authors"])
+    }
+
+    pub fn date(&self) -> Option<&str> {
+        self.text("date")
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
+        self.items(&["tags", "keywords"])
+    }
+
+    /// Header card shown above the document body, or `None` when there is
+    /// nothing to show in it.
+    fn card_html(&self) -> Option<String> {
+        let mut byline = String::new();
+        let authors = self.authors();
+        if !authors.is_empty() {
+            byline.push_str(&format!(
+                "<span class=\"front-matter-author\">{}</span>",
+                html_escape::encode_text(&authors.join(", "))
+            ));
+        }
+        if let Some(date) = self.date() {
+            byline.push_str(&format!(
+                "<span class=\"front-matter-date\">{}</span>",
+                html_escape::encode_text(date)
+            ));
+        }
+        let tags: String = self
+            .tags()
+            .iter()
+            .map(|tag| format!("<li>{}</li>", html_escape::encode_text(tag)))
+            .collect();
+        if self.title().is_none() && byline.is_empty() && tags.is_empty() {
+            return None;
+        }
+
+        let mut card = String::from("<header class=\"front-matter\">");
+        if let Some(title) = self.title() {
+            card.push_str(&format!(
+                "<div class=\"front-matter-title\">{}</div>",
+                html_escape::encode_text(title)
+            ));
+        }
+        if !byline.is_empty() {
+            card.push_str(&format!(
+                "<div class=\"front-matter-byline\">{byline}</div>"
+            ));
+        }
+        if !tags.is_empty() {
+            card.push_str(&format!("<ul class=\"front-matter-tags\">{tags}</ul>"));
+        }
+        card.push_str("</header>");
+        Some(card)
+    }
+}
+
+pub struct Renderer {
+    options: Options,
+    syntax_set: SyntaxSet,
+    themes: ThemeSet,
+    /// Remote content policy of newly opened documents.
+    pub remote_content: RemoteContent,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::with_highlighting(
+            SyntaxSet::load_defaults_newlines(),
+            ThemeSet::load_defaults(),
+            &Config::default(),
+        )
+    }
+
+    /// Renderer with the configured Markdown extensions and remote content
+    /// policy that also knows the user's `.sublime-syntax` files in
+    /// `syntaxes/` and `.tmTheme` files in `themes/` of the config
+    /// directory; highlight themes are named after their file. Returns the
+    /// files that could not be loaded.
+    pub fn with_config(config: &Config) -> (Self, Vec<ConfigError>) {
+        let dir = config_dir();
+        let (syntax_set, mut errors) = load_syntax_set(
+            dir.as_ref().map(|dir| dir.join("syntaxes")).as_deref(),
+            cache_dir().as_deref(),
+        );
+        let (themes, theme_errors) =
+            load_highlight_themes(dir.as_ref().map(|dir| dir.join("themes")).as_deref());
+        errors.extend(theme_errors);
+        (Self::with_highlighting(syntax_set, themes, config), errors)
+    }
+
+    fn with_highlighting(syntax_set: SyntaxSet, themes: ThemeSet, config: &Config) -> Self {
+        Self {
+            options: config.extensions.options(),
+            syntax_set,
+            themes,
+            remote_content: config.remote_content,
+        }
+    }
+
+    pub fn has_syntax_theme(&self, name: &str) -> bool {
+        self.themes.themes.contains_key(name)
+    }
+
+    pub fn render_markdown(&self, markdown: &str, theme: &Theme, remote: RemoteContent) -> String {
+        self.render(markdown, theme, remote).html
+    }
+
+    pub fn render(&self, markdown: &str, theme: &Theme, remote: RemoteContent) -> RenderedMarkdown {
+        let mut heading_lines = Vec::new();
+        let mut line = (0, 1);
+        let parser = Parser::new_ext(markdown, self.options)
+            .into_offset_iter()
+            .map(|(event, range)| {
+                if matches!(event, Event::Start(Tag::Heading { .. })) {
+                    let (counted, lines) = &mut line;
+                    *lines += markdown[*counted..range.start].matches('\n').count();
+                    *counted = range.start;
+                    heading_lines.push(*lines);
+                }
+                event
+            });
+        let syntect_theme = self
+            .themes
+            .themes
+            .get(&theme.syntax_theme)
+            .or_else(|| self.themes.themes.values().next())
+            .expect("syntect theme available");
+
+        let highlighted = inject_highlighted_code_blocks(parser, &self.syntax_set, syntect_theme);
+        let (body, front_matter) = extract_front_matter(highlighted);
+        let (transformed, headings) = assign_heading_ids(body, &heading_lines);
+        let mut rendered = String::new();
+        html::push_html(
+            &mut rendered,
+            inject_alerts(inject_math(transformed)).into_iter(),
+        );
+
+        let (mut html, blocked_remote) = sanitize_rendered_html(&rendered, remote);
+        // Built from escaped text only, so it is added after sanitizing to
+        // keep its classes.
+        if let Some(card) = front_matter.as_ref().and_then(FrontMatter::card_html) {
+            html.insert_str(0, &card);
+        }
+        RenderedMarkdown {
+            html,
+            outline: build_outline(headings),
+            blocked_remote,
+            front_matter,
+        }
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Files in `dir` with the given extension, sorted by path.
+fn files_with_extension(dir: Option<&Path>, extension: &str) -> Vec<PathBuf> {
+    let Some(entries) = dir.and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|found| found.eq_ignore_ascii_case(extension))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The default syntaxes plus the `.sublime-syntax` files in `dir`. Building
+/// the set is slow, so it is dumped to `cache` and reused while the files
+/// are unchanged; a set with broken files is rebuilt every time so they keep
+/// being reported.
+fn load_syntax_set(dir: Option<&Path>, cache: Option<&Path>) -> (SyntaxSet, Vec<ConfigError>) {
+    let files = files_with_extension(dir, "sublime-syntax");
+    if files.is_empty() {
+        return (SyntaxSet::load_defaults_newlines(), Vec::new());
+    }
+
+    let fingerprint = syntax_fingerprint(&files);
+    let dump_path = cache.map(|cache| cache.join("syntaxes.packdump"));
+    let fingerprint_path = cache.map(|cache| cache.join("syntaxes.fingerprint"));
+    if let (Some(dump_path), Some(fingerprint_path)) = (&dump_path, &fingerprint_path)
+        && fs::read_to_string(fingerprint_path).is_ok_and(|cached| cached == fingerprint)
+        && let Ok(syntax_set) = syntect::dumps::from_dump_file(dump_path)
+    {
+        return (syntax_set, Vec::new());
+    }
+
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    let mut errors = Vec::new();
+    for path in files {
+        let fallback_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        let loaded = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| {
+                SyntaxDefinition::load_from_str(&text, true, fallback_name.as_deref())
+                    .map_err(|err| err.to_string())
+            });
+        match loaded {
+            Ok(syntax) => builder.add(syntax),
+            Err(message) => errors.push(ConfigError { path, message }),
+        }
+    }
+    let syntax_set = builder.build();
+
+    if errors.is_empty()
+        && let (Some(cache), Some(dump_path), Some(fingerprint_path)) =
+            (cache, &dump_path, &fingerprint_path)
+    {
+        // Written last, so an interrupted dump is never picked up.
+        let _ = fs::create_dir_all(cache)
+            .map_err(|err| err.to_string())
+            .and_then(|()| {
+                syntect::dumps::dump_to_file(&syntax_set, dump_path).map_err(|err| err.to_string())
+            })
+            .and_then(|()| {
+                fs::write(fingerprint_path, &fingerprint).map_err(|err| err.to_string())
+            });
+    }
+    (syntax_set, errors)
+}
+
+/// Identifies a set of syntax files by name, size and modification time.
+fn syntax_fingerprint(files: &[PathBuf]) -> String {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    for path in files {
+        path.hash(&mut hasher);
+        if let Ok(metadata) = fs::metadata(path) {
+            metadata.len().hash(&mut hasher);
+            metadata.modified().ok().hash(&mut hasher);
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// The default highlight themes plus the `.tmTheme` files in `dir`.
+fn load_highlight_themes(dir: Option<&Path>) -> (ThemeSet, Vec<ConfigError>) {
+    let mut themes = ThemeSet::load_defaults();
+    let mut errors = Vec::new();
+    for path in files_with_extension(dir, "tmTheme") {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match ThemeSet::get_theme(&path) {
+            Ok(theme) => {
+                themes.themes.insert(name, theme);
+            }
+            Err(err) => errors.push(ConfigError {
+                path,
+                message: err.to_string(),
+            }),
+        }
+    }
+    (themes, errors)
+}
+
+pub fn default_body() -> String {
+    "<p class=\"empty\">Use File -&gt; Open... or Ctrl+O to load a Markdown file.</p>".to_string()
+}
+
+/// A link from the shown document to another local Markdown file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownLink {
+    pub path: PathBuf,
+    pub fragment: Option<String>,
+}
+
+/// Where a link clicked in the document leads. The webview itself only ever
+/// navigates within the shown document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// An anchor in the shown document.
+    SameDocument,
+    /// Another local Markdown file, opened in the viewer.
+    Markdown(MarkdownLink),
+    /// A web page, opened in the system browser.
+    External(String),
+    /// Any other scheme or local file type.
+    Blocked(String),
+}
+
+/// Classifies a navigation to `url` started from the page at `current_url`.
+pub fn classify_link(url: &str, current_url: Option<&str>) -> LinkTarget {
+    fn without_fragment(url: &str) -> &str {
+        url.split_once('#').map_or(url, |(url, _)| url)
+    }
+
+    if let Some(current_url) = current_url
+        && url.contains('#')
+        && without_fragment(url) == without_fragment(current_url)
+    {
+        return LinkTarget::SameDocument;
+    }
+    if let Some(link) = markdown_link_from_file_url(url) {
+        return LinkTarget::Markdown(link);
+    }
+    let external = url.split_once(':').is_some_and(|(scheme, _)| {
+        EXTERNAL_SCHEMES
+            .iter()
+            .any(|known| scheme.eq_ignore_ascii_case(known))
+    });
+    if external {
+        LinkTarget::External(url.to_string())
+    } else {
+        LinkTarget::Blocked(url.to_string())
+    }
+}
+
+pub fn is_markdown_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            MARKDOWN_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Resolves a `file://` URL the webview navigates to into a Markdown link,
+/// or `None` when it points at anything other than a Markdown file.
+pub fn markdown_link_from_file_url(url: &str) -> Option<MarkdownLink> {
+    let rest = url.strip_prefix("file://")?;
+    // Skip a host part (`file://localhost/...`); local paths start at '/'.
+    let url_path = &rest[rest.find('/')?..];
+    markdown_link_from_url_path(url_path)
+}
+
+/// Like [`markdown_link_from_file_url`], for the path (plus optional query
+/// and fragment) of a URL whose host is already known to be local.
+pub fn markdown_link_from_url_path(url_path: &str) -> Option<MarkdownLink> {
+    let (url_path, fragment) = match url_path.split_once('#') {
+        Some((url_path, fragment)) => (url_path, Some(fragment)),
+        None => (url_path, None),
+    };
+    let url_path = url_path.split_once('?').map_or(url_path, |(path, _)| path);
+    let path = path_from_url_path(url_path);
+    is_markdown_path(&path).then(|| MarkdownLink {
+        path,
+        fragment: fragment
+            .map(percent_decode)
+            .filter(|fragment| !fragment.is_empty()),
+    })
+}
+
+/// Decodes a URL path into a filesystem path; `/C:/docs` becomes `C:/docs`.
+pub fn path_from_url_path(url_path: &str) -> PathBuf {
+    let path = percent_decode(url_path);
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => PathBuf::from(&path[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encoded URL path of the directory containing `path`, with a
+/// trailing slash so relative references resolve inside it.
+pub fn document_directory_url_path(path: &Path) -> Option<String> {
+    let absolute = std::path::absolute(path).ok()?;
+    let directory = absolute.parent()?.to_string_lossy().replace('\\', "/");
+
+    let mut encoded = String::with_capacity(directory.len() + 2);
+    if !directory.starts_with('/') {
+        encoded.push('/');
+    }
+    for byte in directory.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    if !encoded.ends_with('/') {
+        encoded.push('/');
+    }
+    Some(encoded)
+}
+
+/// `file://` URI of the directory containing `path`, used as the base for
+/// relative images and links in the rendered document.
+pub fn document_base_uri(path: &Path) -> Option<String> {
+    document_directory_url_path(path).map(|url_path| format!("file://{url_path}"))
+}
+
+pub fn render_document(body: &str, theme: &Theme, base_uri: Option<&str>) -> String {
+    let theme::Palette {
+        background: bg,
+        text: fg,
+        muted,
+        border,
+        code_background: code_bg,
+        quote,
+        link,
+        note,
+        tip,
+        important,
+        warning,
+        caution,
+    } = &theme.colors;
+    let body_font = &theme.body_font;
+    let max_width = theme.max_width;
+    let code_font = theme.code_font.as_ref().map_or_else(String::new, |font| {
+        format!("\n      .markdown-body code, .markdown-body pre {{ font-family: {font}; }}")
+    });
+    let base = base_uri.map_or_else(String::new, |uri| {
+        format!(
+            "\n    <base href=\"{}\" />",
+            html_escape::encode_double_quoted_attribute(uri)
+        )
+    });
+
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+    <meta http-equiv="Content-Security-Policy" content="script-src 'none'; object-src 'none'" />{base}
+    <style>
+      html, body {{
+        margin: 0;
+        padding: 0;
+        background: {bg};
+        color: {fg};
+        font-family: {body_font};
+      }}
+      .markdown-body {{
+        box-sizing: border-box;
+        max-width: {max_width}px;
+        margin: 0 auto;
+        padding: 32px;
+        line-height: 1.6;
+        word-wrap: break-word;
+      }}
+      @media (max-width: 767px) {{ .markdown-body {{ padding: 18px; }} }}
+      .empty {{ color: {muted}; }}
+      .markdown-body h1, .markdown-body h2, .markdown-body h3,
+      .markdown-body h4, .markdown-body h5, .markdown-body h6 {{
+        margin-top: 24px;
+        margin-bottom: 16px;
+        font-weight: 600;
+        line-height: 1.25;
+        border-bottom: 1px solid transparent;
+      }}
+      .markdown-body h1, .markdown-body h2 {{
+        padding-bottom: 0.3em;
+        border-bottom-color: {border};
+      }}
+      .markdown-body p, .markdown-body ul, .markdown-body ol,
+      .markdown-body table, .markdown-body pre, .markdown-body blockquote {{
+        margin-top: 0;
+        margin-bottom: 16px;
+      }}
+      .markdown-body a {{ color: {link}; text-decoration: none; }}
+      .markdown-body a:hover {{ text-decoration: underline; }}
+      .markdown-body code, .markdown-body tt {{
+        padding: 0.2em 0.4em;
+        margin: 0;
+        font-size: 85%;
+        border-radius: 6px;
+        background: {code_bg};
+      }}
+      .markdown-body pre {{
+        padding: 16px;
+        overflow: auto;
+        border-radius: 8px;
+        background: {code_bg};
+      }}
+      .markdown-body pre code {{ padding: 0; background: transparent; border-radius: 0; }}{code_font}
+      .markdown-body blockquote {{
+        padding: 0 1em;
+        color: {quote};
+        border-left: 0.25em solid {border};
+      }}
+      .markdown-body .markdown-alert {{
+        margin-bottom: 16px;
+        padding: 8px 16px;
+        border-left: 0.25em solid var(--alert-color);
+      }}
+      .markdown-body .markdown-alert > :last-child {{ margin-bottom: 0; }}
+      .markdown-body .markdown-alert-title {{
+        margin-bottom: 4px;
+        color: var(--alert-color);
+        font-weight: 500;
+      }}
+      .markdown-body .markdown-alert-title::before {{ margin-right: 8px; }}
+      .markdown-alert-note {{ --alert-color: {note}; }}
+      .markdown-alert-note .markdown-alert-title::before {{ content: "\24D8"; }}
+      .markdown-alert-tip {{ --alert-color: {tip}; }}
+      .markdown-alert-tip .markdown-alert-title::before {{ content: "\2726"; }}
+      .markdown-alert-important {{ --alert-color: {important}; }}
+      .markdown-alert-important .markdown-alert-title::before {{ content: "\2757\FE0E"; }}
+      .markdown-alert-warning {{ --alert-color: {warning}; }}
+      .markdown-alert-warning .markdown-alert-title::before {{ content: "\26A0\FE0E"; }}
+      .markdown-alert-caution {{ --alert-color: {caution}; }}
+      .markdown-alert-caution .markdown-alert-title::before {{ content: "\2298"; }}
+      .markdown-body table {{
+        display: block;
+        width: max-content;
+        max-width: 100%;
+        overflow: auto;
+        border-collapse: collapse;
+      }}
+      .markdown-body table th, .markdown-body table td {{
+        padding: 6px 13px;
+        border: 1px solid {border};
+      }}
+      .markdown-body hr {{
+        height: 0.25em;
+        margin: 24px 0;
+        background: {border};
+        border: 0;
+      }}
+      .markdown-body img {{ max-width: 100%; height: auto; }}
+      .markdown-body .front-matter {{
+        margin: 0 0 24px;
+        padding: 16px 20px;
+        background: {code_bg};
+        border: 1px solid {border};
+        border-radius: 6px;
+      }}
+      .markdown-body .front-matter-title {{ font-size: 1.6em; font-weight: 600; }}
+      .markdown-body .front-matter-byline {{ margin-top: 4px; color: {muted}; }}
+      .markdown-body .front-matter-byline span + span::before {{ content: "·"; margin: 0 8px; }}
+      .markdown-body .front-matter-tags {{
+        display: flex;
+        flex-wrap: wrap;
+        gap: 6px;
+        margin: 12px 0 0;
+        padding: 0;
+        list-style: none;
+      }}
+      .markdown-body .front-matter-tags li {{
+        margin: 0;
+        padding: 0 10px;
+        border: 1px solid {border};
+        border-radius: 2em;
+        color: {link};
+        font-size: 0.85em;
+      }}
+      .markdown-body math[display="block"] {{ margin: 16px 0; overflow-x: auto; }}
+    </style>
+  </head>
+  <body>
+    <article class="markdown-body">{body}</article>
+  </body>
+</html>"#
+    )
+}
+
+/// Builds a script that swaps the rendered article in place, so a reload keeps
+/// the current scroll position instead of loading a fresh page.
+pub fn replace_body_script(body: &str) -> String {
+    replace_inner_html_script("article.markdown-body", body)
+}
+
+pub fn replace_inner_html_script(selector: &str, html: &str) -> String {
+    format!(
+        "(function() {{ const element = document.querySelector({}); \
+         if (element) {{ element.innerHTML = {}; }} }})();",
+        js_string_literal(selector),
+        js_string_literal(html)
+    )
+}
+
+pub fn js_string_literal(text: &str) -> String {
+    let mut literal = String::with_capacity(text.len() + 2);
+    literal.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\u{2028}' => literal.push_str("\\u2028"),
+            '\u{2029}' => literal.push_str("\\u2029"),
+            '<' => literal.push_str("\\u003c"),
+            c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+pub fn filename_or_path(path: &Path) -> String {
+    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+        name.to_string()
+    } else {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// Sanitizes rendered HTML; with [`RemoteContent::Block`] remote image
+/// sources are dropped, and their number returned. Local image sources
+/// outside the document's folder are always dropped, see
+/// [`is_within_document_folder`].
+fn sanitize_rendered_html(dirty_html: &str, remote: RemoteContent) -> (String, usize) {
+    let mut sanitizer = HtmlSanitizer::default();
+    let blocked = Arc::new(AtomicUsize::new(0));
+    {
+        let blocked = blocked.clone();
+        sanitizer.attribute_filter(move |element, attribute, value| {
+            if attribute == "style" {
+                return is_plain_style(value).then(|| value.into());
+            }
+            if element != "img" || attribute != "src" {
+                return Some(value.into());
+            }
+            if !is_remote_url(value) {
+                return is_within_document_folder(value).then(|| value.into());
+            }
+            if remote == RemoteContent::Block {
+                blocked.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            Some(value.into())
+        });
+    }
+    // Relative URLs resolve against the document's directory via the page base.
+    sanitizer.url_relative(UrlRelative::PassThrough);
+    sanitizer.add_tag_attributes("a", &["href", "title"]);
+    sanitizer.add_tag_attributes("img", &["src", "alt", "title"]);
+    sanitizer.add_tag_attributes("code", &["class"]);
+    for heading in ["h1", "h2", "h3", "h4", "h5", "h6"] {
+        sanitizer.add_tag_attributes(heading, &["id"]);
+    }
+    sanitizer.add_tag_attributes("pre", &["class", "style"]);
+    sanitizer.add_tag_attributes("span", &["class", "style"]);
+    sanitizer.add_allowed_classes(
+        "div",
+        &[
+            "markdown-alert",
+            "markdown-alert-note",
+            "markdown-alert-tip",
+            "markdown-alert-important",
+            "markdown-alert-warning",
+            "markdown-alert-caution",
+        ],
+    );
+    sanitizer.add_allowed_classes("p", &["markdown-alert-title"]);
+    sanitizer.add_tags(MATHML_TAGS);
+    sanitizer.add_tag_attributes("math", &["display"]);
+    sanitizer.add_tag_attributes("annotation", &["encoding"]);
+    sanitizer.add_tag_attributes("mi", &["mathvariant"]);
+    sanitizer.add_tag_attributes("mn", &["mathvariant"]);
+    sanitizer.add_tag_attributes("mo", &["stretchy"]);
+    sanitizer.add_tag_attributes("mover", &["accent"]);
+    sanitizer.add_tag_attributes("munder", &["accentunder"]);
+    sanitizer.add_tag_attributes("mfrac", &["linethickness"]);
+    sanitizer.add_tag_attributes("mspace", &["width"]);
+    sanitizer.add_tag_attributes("mtable", &["columnalign"]);
+
+    let html = sanitizer.clean(dirty_html).to_string();
+    (html, blocked.load(Ordering::Relaxed))
+}
+
+/// True for URLs fetched over the network: any scheme other than `file:` and
+/// `data:`, and scheme-relative `//host/...` URLs, where either slash may
+/// be a backslash as browsers read them alike (`\\host\share` is a UNC
+/// path). Windows drive paths such as `C:\img\a.png` are local.
+fn is_remote_url(url: &str) -> bool {
+    let url = url.trim().replace('\\', "/");
+    if url.starts_with("//") {
+        return true;
+    }
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return false;
+    };
+    if scheme.len() == 1 && (rest.starts_with('\\') || rest.starts_with('/')) {
+        return false;
+    }
+    let is_scheme = scheme
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    is_scheme && !scheme.eq_ignore_ascii_case("file") && !scheme.eq_ignore_ascii_case("data")
+}
+
+/// True for an inline style of the kind syntect writes on highlighted code:
+/// only colors and font styles, with plain values. Anything else, say a
+/// `background: url(...)` in raw HTML, could fetch a remote resource past
+/// the remote content policy, so the attribute is dropped.
+fn is_plain_style(style: &str) -> bool {
+    style
+        .split(';')
+        .filter(|declaration| !declaration.trim().is_empty())
+        .all(|declaration| {
+            let Some((property, value)) = declaration.split_once(':') else {
+                return false;
+            };
+            let property = property.trim().to_ascii_lowercase();
+            matches!(
+                property.as_str(),
+                "color" | "background-color" | "font-weight" | "font-style" | "text-decoration"
+            ) && value
+                .trim()
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | ' ' | '-' | '.'))
+        })
+}
+
+/// True for a local URL that stays inside the folder of the document it is
+/// written in: a relative path whose `..` segments never climb above it, or
+/// a `data:` URL. Absolute paths, drive paths and `file:` URLs are not. Both
+/// frontends show only the images this allows; on Windows the asset
+/// protocol also checks the file itself, see
+/// `windows_shared::asset_within`.
+fn is_within_document_folder(url: &str) -> bool {
+    // Browsers read `\` as `/` and `%2e` as `.` in paths.
+    let url = url.trim().replace('\\', "/");
+    if url.starts_with('/') {
+        return false;
+    }
+    if let Some((scheme, _)) = url.split_once(':')
+        && !scheme.contains(['/', '?', '#'])
+    {
+        return scheme.eq_ignore_ascii_case("data");
+    }
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let mut depth = 0usize;
+    for segment in path.split('/') {
+        match segment.to_ascii_lowercase().replace("%2e", ".").as_str() {
+            "" | "." => {}
+            ".." => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            _ => depth += 1,
+        }
+    }
+    true
+}
+
+/// Takes the metadata block out of the body. A block that does not parse as a
+/// key/value map is shown as preformatted text instead.
+fn extract_front_matter(events: Vec<Event<'_>>) -> (Vec<Event<'_>>, Option<FrontMatter>) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut front_matter = None;
+    let mut iter = events.into_iter();
+
+    while let Some(event) = iter.next() {
+        let Event::Start(Tag::MetadataBlock(kind)) = event else {
+            output.push(event);
+            continue;
+        };
+        let mut text = String::new();
+        for event in iter.by_ref() {
+            match event {
+                Event::End(TagEnd::MetadataBlock(_)) => break,
+                Event::Text(chunk) => text.push_str(&chunk),
+                _ => {}
+            }
+        }
+        match FrontMatter::parse(kind, &text) {
+            Some(parsed) => front_matter = Some(parsed),
+            None => output.push(Event::Html(CowStr::from(format!(
+                "<pre><code>{}</code></pre>",
+                html_escape::encode_text(&text)
+            )))),
+        }
+    }
+
+    (output, front_matter)
+}
+
+/// Turns `> [!NOTE]`-style blockquotes into titled alert containers, styled
+/// per kind by [`render_document`].
+fn inject_alerts(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::BlockQuote(Some(kind))) => {
+                let (class, title) = match kind {
+                    BlockQuoteKind::Note => ("note", "Note"),
+                    BlockQuoteKind::Tip => ("tip", "Tip"),
+                    BlockQuoteKind::Important => ("important", "Important"),
+                    BlockQuoteKind::Warning => ("warning", "Warning"),
+                    BlockQuoteKind::Caution => ("caution", "Caution"),
+                };
+                Event::Html(CowStr::from(format!(
+                    "<div class=\"markdown-alert markdown-alert-{class}\">\
+                     <p class=\"markdown-alert-title\">{title}</p>\n"
+                )))
+            }
+            Event::End(TagEnd::BlockQuote(Some(_))) => Event::Html(CowStr::from("</div>\n")),
+            other => other,
+        })
+        .collect()
+}
+
+/// Replaces `$...$` and `$$...$$` spans with MathML rendered in Rust.
+fn inject_math(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::InlineMath(tex) => Event::InlineHtml(latex_to_mathml(&tex, false).into()),
+            Event::DisplayMath(tex) => Event::InlineHtml(latex_to_mathml(&tex, true).into()),
+            other => other,
+        })
+        .collect()
+}
+
+fn inject_highlighted_code_blocks<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    syntax_set: &SyntaxSet,
+    syntect_theme: &SyntectTheme,
+) -> Vec<Event<'a>> {
+    let mut output = Vec::new();
+    let mut iter = events;
+
+    while let Some(event) = iter.next() {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .map(|s| s.trim_start_matches("language-").to_string()),
+                    CodeBlockKind::Indented => None,
+                };
+
+                let mut code = String::new();
+                for code_event in iter.by_ref() {
+                    match code_event {
+                        Event::End(TagEnd::CodeBlock) => break,
+                        Event::Text(text) | Event::Code(text) | Event::Html(text) => {
+                            code.push_str(&text)
+                        }
+                        Event::SoftBreak | Event::HardBreak => code.push('\n'),
+                        _ => {}
+                    }
+                }
+
+                let highlighted =
+                    highlight_code_block(&code, language.as_deref(), syntax_set, syntect_theme);
+                output.push(Event::Html(CowStr::from(highlighted)));
+            }
+            other => output.push(other),
+        }
+    }
+
+    output
+}
+
+/// Heading slug in the style of GitHub's: lowercase, punctuation dropped,
+/// spaces turned into hyphens.
+fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|ch| match ch {
+            ' ' => Some('-'),
+            '-' | '_' => Some(ch),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A slug no other heading has taken; headings without a single letter or
+/// digit, such as `# !!!`, become `section`, where GitHub would leave the
+/// id empty.
+fn unique_slug(text: &str, seen: &mut HashSet<String>) -> String {
+    let mut base = slugify(text);
+    if base.is_empty() {
+        base = "section".to_string();
+    }
+    let mut slug = base.clone();
+    let mut suffix = 0;
+    while !seen.insert(slug.clone()) {
+        suffix += 1;
+        slug = format!("{base}-{suffix}");
+    }
+    slug
+}
+
+/// Gives every heading a unique id. `lines` holds the source line of each
+/// heading, in order.
+fn assign_heading_ids<'a>(
+    events: Vec<Event<'a>>,
+    lines: &[usize],
+) -> (Vec<Event<'a>>, Vec<Heading>) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut headings = Vec::new();
+    let mut seen = HashSet::new();
+    let mut open_heading: Option<(usize, String)> = None;
+
+    for event in events {
+        match &event {
+            Event::Start(Tag::Heading { .. }) => open_heading = Some((output.len(), String::new())),
+            Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                if let Some((_, title)) = open_heading.as_mut() {
+                    title.push_str(text);
+                }
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                if let Some((start, title)) = open_heading.take() {
+                    let id = unique_slug(&title, &mut seen);
+                    if let Event::Start(Tag::Heading { id: heading_id, .. }) = &mut output[start] {
+                        *heading_id = Some(CowStr::from(id.clone()));
+                    }
+                    headings.push(Heading {
+                        level: *level as u8,
+                        title: title.trim().to_string(),
+                        id,
+                        line: lines.get(headings.len()).copied().unwrap_or(1),
+                        children: Vec::new(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        output.push(event);
+    }
+
+    (output, headings)
+}
+
+fn build_outline(headings: Vec<Heading>) -> Vec<Heading> {
+    fn insert(tree: &mut Vec<Heading>, heading: Heading) {
+        match tree.last_mut() {
+            Some(last) if last.level < heading.level => insert(&mut last.children, heading),
+            _ => tree.push(heading),
+        }
+    }
+
+    let mut outline = Vec::new();
+    for heading in headings {
+        insert(&mut outline, heading);
+    }
+    outline
+}
+
+/// Builds a script that scrolls the heading with `id` into view.
+pub fn scroll_to_heading_script(id: &str) -> String {
+    format!(
+        "(function() {{ const heading = document.getElementById({}); \
+         if (heading) {{ heading.scrollIntoView(); }} }})();",
+        js_string_literal(id)
+    )
+}
+
+/// Builds a script that scrolls the page `offset` CSS pixels down from the
+/// top.
+pub fn scroll_to_offset_script(offset: u32) -> String {
+    format!("window.scrollTo(0, {offset});")
+}
+
+/// Keyboard scrolling through the shown document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Navigation {
+    LineDown,
+    LineUp,
+    HalfPageDown,
+    HalfPageUp,
+    Top,
+    Bottom,
+    NextHeading,
+    PreviousHeading,
+}
+
+/// Scrolls the page for `navigation`. Heading jumps keep clear of a sticky
+/// `.topbar`, which only the Windows page has.
+pub fn navigation_script(navigation: Navigation) -> String {
+    let scroll = |by: &str| format!("window.scrollBy(0, {by});");
+    match navigation {
+        Navigation::LineDown => scroll("40"),
+        Navigation::LineUp => scroll("-40"),
+        Navigation::HalfPageDown => scroll("window.innerHeight / 2"),
+        Navigation::HalfPageUp => scroll("-window.innerHeight / 2"),
+        Navigation::Top => "window.scrollTo(0, 0);".to_string(),
+        Navigation::Bottom => "window.scrollTo(0, document.documentElement.scrollHeight);".to_string(),
+        Navigation::NextHeading | Navigation::PreviousHeading => format!(
+            "(function() {{ \
+             const offset = (document.querySelector('.topbar')?.offsetHeight ?? 0) + 1; \
+             const article = document.querySelector('.markdown-body'); \
+             const headings = article ? [...article.querySelectorAll('h1, h2, h3, h4, h5, h6')] : []; \
+             const tops = headings.map((heading) => heading.getBoundingClientRect().top - offset); \
+             let target = null; \
+             for (const top of tops) {{ {} }} \
+             if (target !== null) {{ window.scrollBy(0, target); }} }})();",
+            if navigation == Navigation::NextHeading {
+                "if (top > 1) { target = top; break; }"
+            } else {
+                "if (top < -1) { target = top; }"
+            }
+        ),
+    }
+}
+
+fn highlight_code_block(
+    code: &str,
+    language: Option<&str>,
+    syntax_set: &SyntaxSet,
+    syntect_theme: &SyntectTheme,
+) -> String {
+    let syntax = language
+        .and_then(|lang| syntax_set.find_syntax_by_token(lang))
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+    highlighted_html_for_string(code, syntax_set, syntax, syntect_theme).unwrap_or_else(|_| {
+        let escaped = html_escape::encode_text(code);
+        format!("<pre><code>{escaped}</code></pre>")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn toggle_theme_switches_between_light_and_dark_themes() {
+        let mut state = AppState::new(Theme::light());
+        let mut sepia = Theme::light();
+        sepia.name = "Sepia".to_string();
+        let mut dark = Theme::dark();
+        dark.max_width = 700;
+        state.themes = vec![Theme::light(), dark.clone(), sepia];
+
+        assert!(state.set_theme(2));
+        assert_eq!(state.theme_index(), Some(2));
+        state.toggle_theme();
+        assert_eq!(state.theme, dark);
+        state.toggle_theme();
+        assert_eq!(state.theme, Theme::light());
+        assert!(!state.set_theme(3));
+    }
+
+    #[test]
+    fn load_themes_applies_config_overrides_and_starting_theme() {
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        let config = Config {
+            theme: Some("dark".to_string()),
+            code_font: Some("monospace".to_string()),
+            max_width: Some(640),
+            ..Config::default()
+        };
+
+        assert!(state.load_themes(&config, &renderer).is_empty());
+        assert_eq!(state.theme.name, "Dark");
+        assert_eq!(state.theme.code_font.as_deref(), Some("monospace"));
+        assert!(state.themes.iter().all(|theme| theme.max_width == 640));
+
+        let config = Config {
+            theme: Some("Sepia".to_string()),
+            ..Config::default()
+        };
+        let errors = state.load_themes(&config, &renderer);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.ends_with("theme: unknown theme 'Sepia'"));
+        assert_eq!(state.theme.name, "Dark");
+    }
+
+    #[test]
+    fn renderer_follows_configured_extensions_and_remote_policy() {
+        let config = Config {
+            extensions: config::MarkdownExtensions {
+                tables: false,
+                smart_punctuation: true,
+                ..Default::default()
+            },
+            remote_content: RemoteContent::Allow,
+            ..Config::default()
+        };
+        let renderer = Renderer::with_highlighting(
+            SyntaxSet::load_defaults_newlines(),
+            ThemeSet::load_defaults(),
+            &config,
+        );
+        let document = Document::from_markdown(
+            Path::new("/tmp/a.md"),
+            "\"quoted\"\n\n| a |\n|---|\n| b |\n\n![a](https://example.com/a.png)".to_string(),
+            &renderer,
+            &Theme::light(),
+        );
+
+        assert!(document.rendered_html.contains("\u{201c}quoted\u{201d}"));
+        assert!(!document.rendered_html.contains("<table>"));
+        assert_eq!(document.remote_content, RemoteContent::Allow);
+        assert_eq!(document.blocked_remote, 0);
+    }
+
+    #[test]
+    fn navigation_script_scrolls_by_lines_pages_and_headings() {
+        assert_eq!(
+            navigation_script(Navigation::LineUp),
+            "window.scrollBy(0, -40);"
+        );
+        assert!(navigation_script(Navigation::HalfPageDown).contains("window.innerHeight / 2"));
+        assert!(navigation_script(Navigation::Bottom).contains("scrollHeight"));
+        let next = navigation_script(Navigation::NextHeading);
+        assert!(next.contains("if (top > 1) { target = top; break; }"));
+        assert!(next.contains(".topbar"));
+        assert!(navigation_script(Navigation::PreviousHeading).contains("if (top < -1)"));
+    }
+
+    #[test]
+    fn render_markdown_sanitizes_unsafe_html() {
+        let rendered = Renderer::new().render_markdown(
+            r#"<script>alert('xss')</script><a href="javascript:alert(1)">click</a>"#,
+            &Theme::light(),
+            RemoteContent::Allow,
+        );
+
+        assert!(!rendered.contains("<script"));
+        assert!(!rendered.contains("javascript:"));
+    }
+
+    #[test]
+    fn render_markdown_renders_fenced_code_blocks() {
+        let rendered = Renderer::new().render_markdown(
+            "```rust\nfn main() {}\n```",
+            &Theme::dark(),
+            RemoteContent::Allow,
+        );
+
+        assert!(rendered.contains("<pre"));
+        assert!(rendered.contains("main"));
+    }
+
+    #[test]
+    fn load_syntax_set_adds_user_syntaxes_and_caches_them() {
+        let dir = TempDir::new("syntaxes");
+        let syntaxes = dir.join("syntaxes");
+        let cache = dir.join("cache");
+        fs::create_dir_all(&syntaxes).unwrap();
+        fs::write(
+            syntaxes.join("dtest.sublime-syntax"),
+            "%YAML 1.2\n---\nname: Dustrown Test\nfile_extensions: [dtest]\nscope: source.dtest\n\
+             contexts:\n  main:\n    - match: '\\bkeyword\\b'\n      scope: keyword.control.dtest\n",
+        )
+        .unwrap();
+
+        let (syntax_set, errors) = load_syntax_set(Some(&syntaxes), Some(&cache));
+        assert!(errors.is_empty());
+        assert!(syntax_set.find_syntax_by_token("dtest").is_some());
+        assert!(cache.join("syntaxes.packdump").exists());
+
+        let (cached, _) = load_syntax_set(Some(&syntaxes), Some(&cache));
+        assert!(cached.find_syntax_by_token("dtest").is_some());
+
+        fs::write(syntaxes.join("broken.sublime-syntax"), "name: [").unwrap();
+        let (syntax_set, errors) = load_syntax_set(Some(&syntaxes), Some(&cache));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, syntaxes.join("broken.sublime-syntax"));
+        assert!(syntax_set.find_syntax_by_token("dtest").is_some());
+        assert!(syntax_set.find_syntax_by_token("rust").is_some());
+    }
+
+    #[test]
+    fn load_highlight_themes_names_user_themes_after_their_file() {
+        let dir = TempDir::new("tmthemes");
+        fs::write(
+            dir.join("Night Owl.tmTheme"),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\"><dict>\
+             <key>name</key><string>Night Owl</string><key>settings</key><array><dict>\
+             <key>settings</key><dict><key>background</key><string>#011627</string>\
+             <key>foreground</key><string>#d6deeb</string></dict></dict></array></dict></plist>",
+        )
+        .unwrap();
+        fs::write(dir.join("broken.tmTheme"), "not a plist").unwrap();
+
+        let (themes, errors) = load_highlight_themes(Some(&dir));
+
+        assert!(themes.themes.contains_key("Night Owl"));
+        assert!(themes.themes.contains_key("InspiredGitHub"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, dir.join("broken.tmTheme"));
+    }
+
+    #[test]
+    fn render_document_applies_theme_palette() {
+        let light = render_document("<p>Hello</p>", &Theme::light(), None);
+        let dark = render_document("<p>Hello</p>", &Theme::dark(), None);
+
+        assert!(light.contains("background: #ffffff"));
+        assert!(dark.contains("background: #0d1117"));
+        assert!(light.contains("<article class=\"markdown-body\"><p>Hello</p></article>"));
+        assert!(dark.contains("<article class=\"markdown-body\"><p>Hello</p></article>"));
+    }
+
+    #[test]
+    fn render_document_applies_theme_fonts_and_width() {
+        let mut theme = Theme::light();
+        theme.body_font = "Georgia, serif".to_string();
+        theme.code_font = Some("\"Iosevka\", monospace".to_string());
+        theme.max_width = 720;
+
+        let page = render_document("", &theme, None);
+
+        assert!(page.contains("font-family: Georgia, serif;"));
+        assert!(page.contains(
+            ".markdown-body code, .markdown-body pre { font-family: \"Iosevka\", monospace; }"
+        ));
+        assert!(page.contains("max-width: 720px;"));
+        assert!(!render_document("", &Theme::light(), None).contains("monospace"));
+    }
+
+    #[test]
+    fn render_document_forbids_page_scripts() {
+        let page = render_document("<p>Hello</p>", &Theme::light(), None);
+        assert!(page.contains(
+            r#"<meta http-equiv="Content-Security-Policy" content="script-src 'none'; object-src 'none'" />"#
+        ));
+    }
+
+    #[test]
+    fn render_document_emits_base_uri_when_present() {
+        let with_base = render_document("", &Theme::light(), Some("file:///docs/a%20b/"));
+        let without_base = render_document("", &Theme::light(), None);
+
+        assert!(with_base.contains("<base href=\"file:///docs/a%20b/\" />"));
+        assert!(!without_base.contains("<base"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn document_base_uri_points_at_encoded_parent_directory() {
+        assert_eq!(
+            document_base_uri(Path::new("/home/me/my docs/README.md")).as_deref(),
+            Some("file:///home/me/my%20docs/")
+        );
+        assert_eq!(
+            document_base_uri(Path::new("/README.md")).as_deref(),
+            Some("file:///")
+        );
+    }
+
+    #[test]
+    fn fixture_relative_images_resolve_against_document_directory() {
+        let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/markdown");
+        let markdown = fs::read_to_string(fixture_dir.join("relative-links.md")).unwrap();
+
+        let html =
+            Renderer::new().render_markdown(&markdown, &Theme::light(), RemoteContent::Allow);
+
+        assert!(html.contains("src=\"img/diagram.svg\""));
+        assert!(html.contains("href=\"./basic.md\""));
+        assert!(fixture_dir.join("img/diagram.svg").is_file());
+        assert!(fixture_dir.join("./basic.md").is_file());
+    }
+
+    #[test]
+    fn filename_or_path_prefers_filename_when_present() {
+        let file = Path::new("/tmp/sample.md");
+        let root = Path::new("/");
+
+        assert_eq!(filename_or_path(file), "sample.md");
+        assert_eq!(filename_or_path(root), "/");
+    }
+
+    #[test]
+    fn reload_watch_debounces_notifications() {
+        let mut watch = ReloadWatch::new(Path::new("/nonexistent/dustrown.md"));
+        let start = Instant::now();
+
+        assert!(!watch.take_due(start));
+
+        watch.notify(start);
+        assert!(!watch.take_due(start + RELOAD_DEBOUNCE / 2));
+
+        watch.notify(start + RELOAD_DEBOUNCE / 2);
+        assert!(!watch.take_due(start + RELOAD_DEBOUNCE));
+        assert!(watch.take_due(start + RELOAD_DEBOUNCE * 2));
+        assert!(!watch.take_due(start + RELOAD_DEBOUNCE * 3));
+    }
+
+    #[test]
+    fn reload_watch_poll_detects_modified_file() {
+        let dir = TempDir::new("watch");
+        let path = dir.join("a.md");
+        fs::write(&path, "# One").unwrap();
+        let mut watch = ReloadWatch::new(&path);
+        let start = Instant::now();
+
+        watch.poll(start);
+        assert!(!watch.take_due(start + RELOAD_DEBOUNCE));
+
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        watch.poll(start);
+        assert!(watch.take_due(start + RELOAD_DEBOUNCE));
+    }
+
+    #[test]
+    fn markdown_link_from_file_url_keeps_only_markdown_targets() {
+        assert_eq!(
+            markdown_link_from_file_url("file:///home/me/my%20docs/design.MD?x=1#set-up"),
+            Some(MarkdownLink {
+                path: PathBuf::from("/home/me/my docs/design.MD"),
+                fragment: Some("set-up".to_string()),
+            })
+        );
+        assert_eq!(
+            markdown_link_from_file_url("file:///C:/docs/a.markdown#"),
+            Some(MarkdownLink {
+                path: PathBuf::from("C:/docs/a.markdown"),
+                fragment: None,
+            })
+        );
+        assert_eq!(
+            markdown_link_from_file_url("file:///home/me/img/a.png"),
+            None
+        );
+        assert_eq!(
+            markdown_link_from_file_url("file:///home/me/docs/#intro"),
+            None
+        );
+        assert_eq!(
+            markdown_link_from_file_url("https://example.com/a.md"),
+            None
+        );
+    }
+
+    #[test]
+    fn classify_link_sends_web_links_out_and_blocks_other_schemes() {
+        let current = Some("file:///home/me/docs/");
+
+        assert_eq!(
+            classify_link("file:///home/me/docs/#usage", current),
+            LinkTarget::SameDocument
+        );
+        assert_eq!(
+            classify_link("file:///home/me/docs/guide.md", current),
+            LinkTarget::Markdown(MarkdownLink {
+                path: PathBuf::from("/home/me/docs/guide.md"),
+                fragment: None,
+            })
+        );
+        assert_eq!(
+            classify_link("HTTPS://example.com/a?b#c", current),
+            LinkTarget::External("HTTPS://example.com/a?b#c".to_string())
+        );
+        for blocked in [
+            "javascript:alert(1)",
+            "mailto:me@example.com",
+            "ftp://example.com/",
+            "file:///etc/passwd",
+            "file:///home/me/other/#x",
+        ] {
+            assert_eq!(
+                classify_link(blocked, current),
+                LinkTarget::Blocked(blocked.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn history_moves_between_back_and_forward_stacks() {
+        let mut history = History::default();
+        history.visit(PathBuf::from("a.md"));
+        history.visit(PathBuf::from("b.md"));
+
+        assert_eq!(
+            history.back(PathBuf::from("c.md")),
+            Some(PathBuf::from("b.md"))
+        );
+        assert!(history.can_go_forward());
+        assert_eq!(
+            history.forward(PathBuf::from("b.md")),
+            Some(PathBuf::from("c.md"))
+        );
+        assert_eq!(
+            history.back(PathBuf::from("c.md")),
+            Some(PathBuf::from("b.md"))
+        );
+
+        history.visit(PathBuf::from("b.md"));
+        assert!(!history.can_go_forward());
+        assert_eq!(history.forward(PathBuf::from("d.md")), None);
+    }
+
+    #[test]
+    fn links_and_history_load_documents_into_the_active_tab() {
+        let dir = TempDir::new("history");
+        let index = dir.join("index.md");
+        let design = dir.join("design.md");
+        fs::write(&index, "# Index").unwrap();
+        fs::write(&design, "# Design").unwrap();
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(Document::load(&index, &renderer, &Theme::light()));
+        let finish = |state: &mut AppState, load: PendingLoad| {
+            let document = load.run(&renderer, &Theme::light());
+            state.finish_load(load.id, document)
+        };
+
+        let load = state.follow_link(MarkdownLink {
+            path: design.clone(),
+            fragment: Some("design".to_string()),
+        });
+        assert!(state.active_document().unwrap().history.can_go_back());
+        assert_eq!(state.window_title(), "Dustrown - index.md");
+        assert_eq!(finish(&mut state, load), [0]);
+        assert_eq!(state.documents.len(), 1);
+        assert_eq!(state.window_title(), "Dustrown - design.md");
+        assert_eq!(state.documents[0].fragment.as_deref(), Some("design"));
+
+        let load = state.go_back().unwrap();
+        assert_eq!(finish(&mut state, load), [0]);
+        assert_eq!(state.active_document().unwrap().outline[0].title, "Index");
+        assert!(state.go_back().is_none());
+        let load = state.go_forward().unwrap();
+        assert_eq!(finish(&mut state, load), [0]);
+        assert_eq!(state.active_document().unwrap().outline[0].title, "Design");
+    }
+
+    #[test]
+    fn a_newer_navigation_drops_the_result_of_an_older_one() {
+        let dir = TempDir::new("navigation");
+        let (index, a, b) = (dir.join("index.md"), dir.join("a.md"), dir.join("b.md"));
+        for path in [&index, &a, &b] {
+            fs::write(path, "# Doc").unwrap();
+        }
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(Document::load(&index, &renderer, &Theme::light()));
+        let link = |path: &Path| MarkdownLink {
+            path: path.to_path_buf(),
+            fragment: None,
+        };
+
+        let first = state.follow_link(link(&a));
+        let second = state.follow_link(link(&b));
+        assert_eq!(state.loading_message().as_deref(), Some("Loading b.md…"));
+        assert_eq!(first.run(&renderer, &Theme::light()).map(|d| d.title), None);
+        let document = Document::load(&a, &renderer, &Theme::light());
+        assert!(state.finish_load(first.id, Some(document)).is_empty());
+        let document = second.run(&renderer, &Theme::light());
+        assert_eq!(state.finish_load(second.id, document), [0]);
+        assert_eq!(state.documents[0].title, "b.md");
+        assert_eq!(state.documents[0].history.back, [index]);
+
+        let late = state.follow_link(link(&a));
+        state.close_active();
+        let document = late.run(&renderer, &Theme::light());
+        assert!(state.finish_load(late.id, document).is_empty());
+        assert!(state.documents.is_empty());
+    }
+
+    #[test]
+    fn back_pressed_twice_quickly_goes_back_two_steps() {
+        let dir = TempDir::new("back-twice");
+        let paths = ["a.md", "b.md", "c.md"].map(|name| dir.join(name));
+        for path in &paths {
+            fs::write(path, "# Doc").unwrap();
+        }
+        let [a, b, c] = paths;
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        let mut document = Document::load(&c, &renderer, &Theme::light());
+        document.history.visit(a.clone());
+        document.history.visit(b.clone());
+        state.open_document(document);
+
+        let first = state.go_back().unwrap();
+        let second = state.go_back().unwrap();
+        assert_eq!(second.path, a);
+        assert!(state.go_back().is_none());
+        let document = first.run(&renderer, &Theme::light());
+        assert!(state.finish_load(first.id, document).is_empty());
+        let document = second.run(&renderer, &Theme::light());
+        assert_eq!(state.finish_load(second.id, document), [0]);
+        assert_eq!(state.documents[0].title, "a.md");
+        let history = &state.documents[0].history;
+        assert!(history.back.is_empty());
+        assert_eq!(history.forward, [c.clone(), b.clone()]);
+
+        let failed = state.go_forward().unwrap();
+        assert_eq!(failed.path, b);
+        assert!(state.finish_load(failed.id, None).is_empty());
+        assert_eq!(state.documents[0].history.forward, [c.clone(), b]);
+        state.go_forward().unwrap();
+        assert!(state.cancel_loads());
+        assert!(state.documents[0].history.back.is_empty());
+        assert_eq!(state.documents[0].title, "a.md");
+    }
+
+    #[test]
+    fn replace_body_script_escapes_html_into_js_string() {
+        let script = replace_body_script("<p class=\"x\">a\\b\nc</p>");
+
+        assert!(script.contains(r#"element.innerHTML = "\u003cp class=\"x\">a\\b\nc\u003c/p>";"#));
+        assert!(!script.contains("</p>"));
+    }
+
+    #[test]
+    fn render_assigns_unique_heading_ids() {
+        let rendered = Renderer::new().render(
+            "# Hello, World!\n\n## Setup `cargo`\n\n## Setup `cargo`\n\n# Ünïcode_ok 2",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+
+        assert!(rendered.html.contains("<h1 id=\"hello-world\">"));
+        assert!(rendered.html.contains("<h2 id=\"setup-cargo\">"));
+        assert!(rendered.html.contains("<h2 id=\"setup-cargo-1\">"));
+        assert!(rendered.html.contains("<h1 id=\"ünïcode_ok-2\">"));
+    }
+
+    #[test]
+    fn render_names_headings_without_slug_text_section() {
+        let rendered = Renderer::new().render(
+            "# !!!\n\n## Section\n\n### ???",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+
+        assert!(rendered.html.contains("<h1 id=\"section\">"));
+        assert!(rendered.html.contains("<h2 id=\"section-1\">"));
+        assert!(rendered.html.contains("<h3 id=\"section-2\">"));
+        assert!(!rendered.html.contains("id=\"\""));
+        assert_eq!(rendered.outline[0].id, "section");
+    }
+
+    #[test]
+    fn images_outside_the_document_folder_are_dropped() {
+        let markdown = "![in](img/a.png) ![back](img/../b.png) ![up](../c.png) \
+                        ![deep](img/../../d.png) ![encoded](%2e%2E/e.png) \
+                        <img src=\"..\\f.png\"> ![abs](/etc/g.png) ![drive](C:/h.png) \
+                        ![file](file:///i.png)";
+        let renderer = Renderer::new();
+
+        for remote in [RemoteContent::Block, RemoteContent::Allow] {
+            let rendered = renderer.render(markdown, &Theme::light(), remote);
+            assert!(rendered.html.contains("src=\"img/a.png\""));
+            assert!(rendered.html.contains("src=\"img/../b.png\""));
+            for dropped in [
+                "c.png", "d.png", "e.png", "f.png", "g.png", "h.png", "i.png",
+            ] {
+                assert!(!rendered.html.contains(dropped), "{dropped} kept");
+            }
+            assert_eq!(rendered.blocked_remote, 0);
+        }
+        assert!(is_within_document_folder("data:image/png;base64,AAAA"));
+        assert!(is_within_document_folder("a.png?v=../../x"));
+    }
+
+    #[test]
+    fn is_remote_url_treats_windows_drive_paths_as_local() {
+        assert!(!is_remote_url(r"C:\img\a.png"));
+        assert!(!is_remote_url("c:/img/a.png"));
+        assert!(!is_remote_url("img/a.png"));
+        assert!(!is_remote_url("file:///C:/img/a.png"));
+        assert!(is_remote_url("https://example.com/a.png"));
+        assert!(is_remote_url("//cdn.example.com/a.png"));
+        assert!(is_remote_url(r"\\evil.example/x.png"));
+        assert!(is_remote_url(r"/\evil.example/x.png"));
+        assert!(is_remote_url(r"\/evil.example/x.png"));
+        assert!(is_remote_url("x:y"));
+    }
+
+    #[test]
+    fn render_blocks_remote_images_unless_allowed() {
+        let markdown = "![a](https://example.com/a.png) ![b](//cdn.example.com/b.png)\n\n\
+                        ![local](img/c.png) <img src=\"HTTP://example.com/d.gif\" alt=\"d\">\n\n\
+                        <img src=\"\\\\example.com/e.png\"> <img src=\"/\\example.com/f.png\"> \
+                        <img src=\"\\/example.com/g.png\">";
+        let renderer = Renderer::new();
+
+        let blocked = renderer.render(markdown, &Theme::light(), RemoteContent::Block);
+        assert_eq!(blocked.blocked_remote, 6);
+        assert!(!blocked.html.contains("example.com"));
+        assert!(blocked
+            .html
+            .contains("<img src=\"img/c.png\" alt=\"local\">"));
+        assert!(blocked.html.contains("alt=\"d\""));
+
+        let allowed = renderer.render(markdown, &Theme::light(), RemoteContent::Allow);
+        assert_eq!(allowed.blocked_remote, 0);
+        assert!(allowed.html.contains("src=\"https://example.com/a.png\""));
+    }
+
+    #[test]
+    fn render_keeps_only_plain_inline_styles() {
+        let markdown = "<span style=\"color:#ff0000;font-weight:bold;\">red</span> \
+                        <span style=\"background:url(https://tracker.example/x)\">a</span> \
+                        <span style=\"color:red;background-image:image-set('x.png' 1x)\">b</span> \
+                        <span style=\"color:u\\72l(x)\">c</span>\n\n\
+                        ```rust\nfn main() {}\n```";
+
+        let html = Renderer::new().render_markdown(markdown, &Theme::light(), RemoteContent::Allow);
+
+        assert!(html.contains("<span style=\"color:#ff0000;font-weight:bold;\">red</span>"));
+        assert!(!html.contains("tracker.example"));
+        assert!(!html.contains("image-set"));
+        assert!(!html.contains("\\72"));
+        assert!(html.contains("<pre style=\"background-color:#"));
+        assert!(html.contains("<span style=\"color:#"));
+    }
+
+    #[test]
+    fn render_converts_math_to_sanitized_mathml() {
+        let rendered = Renderer::new().render(
+            "Euler: $e^{i\\pi} = -1$\n\n$$\\frac{a}{b}$$\n\n`$x$` and $<script>alert(1)</script>$",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+
+        assert!(rendered.html.contains(
+            "<math display=\"inline\"><semantics><mrow><msup><mi>e</mi><mrow><mi>i</mi><mi>π</mi></mrow></msup>"
+        ));
+        assert!(rendered
+            .html
+            .contains("<math display=\"block\"><semantics><mfrac><mi>a</mi><mi>b</mi></mfrac>"));
+        assert!(rendered.html.contains("<code>$x$</code>"));
+        assert!(!rendered.html.contains("<script"));
+    }
+
+    #[test]
+    fn render_moves_front_matter_into_a_metadata_card() {
+        let renderer = Renderer::new();
+        let yaml = renderer.render(
+            "---\ntitle: Release <notes>\nauthor: [Ann, Bo]\ndate: 2024-05-01\ntags: rust, gtk\n\
+             extra:\n  nested: true\n---\n\n# Body",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+
+        assert!(yaml.html.starts_with(
+            "<header class=\"front-matter\"><div class=\"front-matter-title\">Release &lt;notes&gt;</div>\
+             <div class=\"front-matter-byline\"><span class=\"front-matter-author\">Ann, Bo</span>\
+             <span class=\"front-matter-date\">2024-05-01</span></div>\
+             <ul class=\"front-matter-tags\"><li>rust</li><li>gtk</li></ul></header><h1"
+        ));
+        assert!(!yaml.html.contains("<hr"));
+        let front_matter = yaml.front_matter.unwrap();
+        assert_eq!(
+            front_matter.fields["extra"],
+            MetadataValue::Map(BTreeMap::from([(
+                "nested".to_string(),
+                MetadataValue::Text("true".to_string())
+            )]))
+        );
+
+        let toml = renderer.render(
+            "+++\ntitle = \"Plan\"\ndate = 2024-05-01\ntags = [\"a\"]\n+++\nText",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+        let front_matter = toml.front_matter.unwrap();
+        assert_eq!(front_matter.title(), Some("Plan"));
+        assert_eq!(front_matter.date(), Some("2024-05-01"));
+        assert_eq!(front_matter.tags(), vec!["a"]);
+    }
+
+    #[test]
+    fn render_turns_github_alerts_into_classed_containers() {
+        let rendered = Renderer::new().render(
+            "> [!WARNING]\n> Mind the *gap*.\n\n> Plain quote\n\n\
+             <div class=\"markdown-alert evil\" onclick=\"x()\">raw</div>",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+
+        assert!(rendered.html.contains(
+            "<div class=\"markdown-alert markdown-alert-warning\">\
+             <p class=\"markdown-alert-title\">Warning</p>\n<p>Mind the <em>gap</em>.</p>\n</div>"
+        ));
+        assert!(rendered.html.contains("<blockquote>\n<p>Plain quote</p>"));
+        assert!(rendered
+            .html
+            .contains("<div class=\"markdown-alert\">raw</div>"));
+        assert!(!rendered.html.contains("[!WARNING]"));
+    }
+
+    #[test]
+    fn render_document_styles_alerts_for_both_themes() {
+        assert!(render_document("", &Theme::light(), None)
+            .contains(".markdown-alert-caution { --alert-color: #d1242f; }"));
+        assert!(render_document("", &Theme::dark(), None)
+            .contains(".markdown-alert-caution { --alert-color: #f85149; }"));
+    }
+
+    #[test]
+    fn render_shows_unparsable_front_matter_as_text() {
+        let rendered = Renderer::new().render(
+            "---\njust a line\n---\n",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+
+        assert!(rendered.front_matter.is_none());
+        assert!(rendered
+            .html
+            .contains("<pre><code>just a line\n</code></pre>"));
+    }
+
+    #[test]
+    fn front_matter_title_names_the_document() {
+        let renderer = Renderer::new();
+        let path = Path::new("/tmp/notes.md");
+        let mut document = Document::from_markdown(
+            path,
+            "---\ntitle: Meeting notes\n---\n".to_string(),
+            &renderer,
+            &Theme::light(),
+        );
+        assert_eq!(document.title, "Meeting notes");
+
+        document.set_markdown("# No front matter".to_string(), &renderer, &Theme::light());
+        assert_eq!(document.title, "notes.md");
+    }
+
+    #[test]
+    fn allow_remote_content_re_renders_only_that_document() {
+        let renderer = Renderer::new();
+        let markdown = "![a](https://example.com/a.png)";
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded("/tmp/a.md", markdown));
+        assert_eq!(state.documents[0].blocked_remote, 1);
+
+        assert_eq!(
+            state.documents[0].blocked_remote_message().as_deref(),
+            Some("1 remote image was blocked to protect your privacy.")
+        );
+        let job = state.allow_remote_content().unwrap();
+        assert_eq!(job.reason, RenderReason::Content);
+        render_now(&mut state, vec![job], &renderer);
+        let document = &state.documents[0];
+        assert_eq!(document.blocked_remote, 0);
+        assert!(document.blocked_remote_message().is_none());
+        assert!(document.rendered_html.contains("https://example.com/a.png"));
+
+        let reopened = Document::from_markdown(
+            Path::new("/tmp/a.md"),
+            markdown.to_string(),
+            &renderer,
+            &Theme::light(),
+        );
+        assert_eq!(reopened.remote_content, RemoteContent::Block);
+    }
+
+    #[test]
+    fn render_builds_nested_outline() {
+        let rendered = Renderer::new().render(
+            "## Intro\n# One\n## One A\n#### Deep\n## One B\n# Two",
+            &Theme::light(),
+            RemoteContent::Block,
+        );
+        let titles = |headings: &[Heading]| -> Vec<String> {
+            headings.iter().map(|h| h.title.clone()).collect()
+        };
+
+        assert_eq!(titles(&rendered.outline), ["Intro", "One", "Two"]);
+        assert_eq!(titles(&rendered.outline[1].children), ["One A", "One B"]);
+        assert_eq!(titles(&rendered.outline[1].children[0].children), ["Deep"]);
+        assert_eq!(rendered.outline[1].children[0].children[0].id, "deep");
+        assert_eq!(rendered.outline[1].children[0].children[0].level, 4);
+    }
+
+    #[test]
+    fn find_status_steps_and_wraps_around() {
+        let mut status = FindStatus::with_total(3);
+        assert_eq!(status.label(), "1 of 3");
+
+        status.next();
+        status.next();
+        assert_eq!(status.label(), "3 of 3");
+        status.next();
+        assert_eq!(status.label(), "1 of 3");
+        status.previous();
+        assert_eq!(status.label(), "3 of 3");
+
+        let mut empty = FindStatus::with_total(0);
+        empty.next();
+        empty.previous();
+        assert_eq!(empty, FindStatus::default());
+        assert_eq!(empty.label(), "No matches");
+    }
+
+    fn loaded(path: &str, markdown: &str) -> Document {
+        Document::from_markdown(
+            Path::new(path),
+            markdown.to_string(),
+            &Renderer::new(),
+            &Theme::light(),
+        )
+    }
+
+    /// Runs `jobs` the way a frontend's workers would, one after another.
+    fn render_now(state: &mut AppState, jobs: Vec<RenderJob>, renderer: &Renderer) {
+        for job in jobs {
+            state.finish_render(job.run(renderer));
+        }
+    }
+
+    #[test]
+    fn document_from_markdown_sets_source_html_and_outline() {
+        let document = loaded("/tmp/title.md", "# Title");
+
+        assert_eq!(document.title, "title.md");
+        assert_eq!(document.source_path(), Some(Path::new("/tmp/title.md")));
+        assert_eq!(document.source_markdown.as_deref(), Some("# Title"));
+        assert!(document
+            .rendered_html
+            .contains("<h1 id=\"title\">Title</h1>"));
+        assert_eq!(document.outline[0].id, "title");
+    }
+
+    #[test]
+    fn heading_at_line_finds_the_section_holding_a_source_line() {
+        let document = loaded(
+            "/tmp/a.md",
+            "intro\n\n# One\n\ntext\n\n## Two\n\n```\n# not a heading\n```\n\n# Three\n",
+        );
+        let id_at = |line| document.heading_at_line(line).map(|h| h.id.as_str());
+
+        assert_eq!(id_at(1), None);
+        assert_eq!(id_at(3), Some("one"));
+        assert_eq!(id_at(5), Some("one"));
+        assert_eq!(id_at(10), Some("two"));
+        assert_eq!(id_at(13), Some("three"));
+    }
+
+    #[test]
+    fn documents_without_a_file_are_not_watched_or_remembered() {
+        let renderer = Renderer::new();
+        let piped =
+            |source| Document::from_source(source, "text".to_string(), &renderer, &Theme::light());
+        let mut state = AppState::new(Theme::light());
+
+        state.open_document(piped(DocumentSource::Stdin));
+        state.open_document(piped(DocumentSource::Memory("(dropped text)".to_string())));
+        state.open_document(piped(DocumentSource::Stdin));
+
+        let titles: Vec<&str> = state.documents.iter().map(|d| d.title.as_str()).collect();
+        assert_eq!(titles, [STDIN_TITLE, "(dropped text)", STDIN_TITLE]);
+        assert!(state.documents.iter().all(|d| d.source_path().is_none()));
+        assert!(state.watched_paths().is_empty());
+        assert!(state.recent.is_empty());
+        state.close_active();
+        assert_eq!(state.take_closed(), None);
+        assert!(state.go_back().is_none());
+    }
+
+    #[test]
+    fn open_text_shows_a_tab_that_a_background_render_fills_in() {
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+
+        let (index, job) = state.open_text(
+            DocumentSource::Memory("(dropped text)".to_string()),
+            "# Dropped".to_string(),
+            RemoteContent::Block,
+        );
+        assert_eq!(index, 0);
+        assert!(state.documents[0].is_rendering());
+        assert!(state.documents[0].outline.is_empty());
+        let render = job.unwrap().run(&renderer);
+        assert_eq!(render.reason, RenderReason::Theme);
+        assert_eq!(state.finish_render(render), Some(0));
+        assert_eq!(state.documents[0].outline[0].title, "Dropped");
+        assert_eq!(state.window_title(), "Dustrown - (dropped text)");
+    }
+
+    #[test]
+    fn document_open_error_shows_escaped_error_page() {
+        let document = Document::open_error(Path::new("/tmp/missing.md"), "No such <file>");
+
+        assert!(!document.is_loaded());
+        assert!(document.outline.is_empty());
+        assert!(document.rendered_html.contains("Could not open file"));
+        assert!(document.rendered_html.contains("No such &lt;file&gt;"));
+    }
+
+    #[test]
+    fn document_load_refuses_folders_and_binary_files() {
+        let dir = TempDir::new("binary");
+        let image = dir.join("logo.png");
+        fs::write(&image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, "# Notes").unwrap();
+        let renderer = Renderer::new();
+        let load = |path: &Path| Document::load(path, &renderer, &Theme::light());
+
+        let binary = load(&image);
+        assert!(!binary.is_loaded());
+        assert!(binary
+            .rendered_html
+            .contains("does not look like a text file"));
+        assert!(load(&dir).rendered_html.contains("This is a folder"));
+        assert!(load(&notes).is_loaded());
+    }
+
+    #[test]
+    fn large_text_file_flags_big_text_but_not_binaries() {
+        let dir = TempDir::new("large");
+        let log = dir.join("big.log");
+        fs::write(&log, "line\n".repeat(300_000)).unwrap();
+        let image = dir.join("big.png");
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.resize(1_500_000, 0);
+        fs::write(&image, png).unwrap();
+
+        assert_eq!(large_text_file(&log, 1), Some(1_500_000));
+        assert_eq!(large_text_file(&log, 2), None);
+        assert_eq!(large_text_file(&image, 1), None);
+        assert_eq!(large_text_file(&dir.join("missing.md"), 1), None);
+        assert_eq!(
+            large_file_prompt(&log, 1_500_000, 1),
+            "big.log is 1.4 MB, more than the 1 MB set by files.max_size_mb. \
+             Rendering it may take a while."
+        );
+    }
+
+    #[test]
+    fn loads_open_their_tab_unless_cancelled() {
+        let dir = TempDir::new("loads");
+        let notes = dir.join("notes.md");
+        fs::write(&notes, "# Notes").unwrap();
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+
+        let first = state.start_load(&notes, LoadOptions::default());
+        let second = state.start_load(&dir.join("other.md"), LoadOptions::default());
+        assert_eq!(state.loading_message().as_deref(), Some("Loading 2 files…"));
+        let document = first.run(&renderer, &Theme::light());
+        assert_eq!(state.finish_load(first.id, document), [0]);
+        assert_eq!(
+            state.loading_message().as_deref(),
+            Some("Loading other.md…")
+        );
+
+        assert!(state.cancel_loads());
+        assert_eq!(
+            second.run(&renderer, &Theme::light()).map(|d| d.title),
+            None
+        );
+        assert!(state.finish_load(second.id, None).is_empty());
+        let late = state.start_load(&notes, LoadOptions::default());
+        state.cancel_loads();
+        let document = Document::load(&notes, &renderer, &Theme::light());
+        assert!(state.finish_load(late.id, Some(document)).is_empty());
+        assert_eq!(state.documents.len(), 1);
+        assert_eq!(state.loading_message(), None);
+        assert!(!state.cancel_loads());
+    }
+
+    #[test]
+    fn loads_open_in_the_order_started_with_their_options() {
+        let dir = TempDir::new("load-order");
+        let (a, b) = (dir.join("a.md"), dir.join("b.md"));
+        fs::write(&a, "# A\n\ntext\n\n## Usage\n\nmore\n").unwrap();
+        fs::write(&b, "# B").unwrap();
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        let at_line = LoadOptions {
+            line: Some(6),
+            ..LoadOptions::default()
+        };
+        let in_background = LoadOptions {
+            scroll_position: Some(40),
+            select: false,
+            remember: false,
+            ..LoadOptions::default()
+        };
+
+        let first = state.start_load(&a, at_line);
+        let second = state.start_load(&b, in_background);
+        let document = second.run(&renderer, &Theme::light());
+        assert!(state.finish_load(second.id, document).is_empty());
+        let document = first.run(&renderer, &Theme::light());
+        assert_eq!(state.finish_load(first.id, document), [0, 1]);
+
+        assert_eq!(state.active, 0);
+        assert_eq!(state.documents[0].fragment.as_deref(), Some("usage"));
+        assert_eq!(state.documents[1].title, "b.md");
+        assert_eq!(state.documents[1].scroll_position, Some(40));
+        assert_eq!(state.recent, [a]);
+        assert_eq!(state.loading_message(), None);
+    }
+
+    #[test]
+    fn document_load_decodes_legacy_text_and_can_reopen_it() {
+        let dir = TempDir::new("encoding");
+        let path = dir.join("cafe.md");
+        fs::write(&path, b"# Caf\xE9 cr\xE8me, na\xEFve fa\xE7ade").unwrap();
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(Document::load(&path, &renderer, &Theme::light()));
+
+        assert_eq!(
+            state.documents[0].outline[0].title,
+            "Café crème, naïve façade"
+        );
+        assert_eq!(
+            state.documents[0].encoding.encoding,
+            encoding_rs::WINDOWS_1252
+        );
+        let job = state.reopen_with_encoding(encoding_rs::UTF_8).unwrap();
+        render_now(&mut state, vec![job], &renderer);
+        let document = &state.documents[0];
+        assert!(document.encoding.had_errors);
+        assert_eq!(
+            document.outline[0].title,
+            "Caf\u{FFFD} cr\u{FFFD}me, na\u{FFFD}ve fa\u{FFFD}ade"
+        );
+
+        let jobs = state.reload_path(&path, b"# Caf\xE9");
+        render_now(&mut state, jobs, &renderer);
+        assert_eq!(state.documents[0].outline[0].title, "Caf\u{FFFD}");
+        assert_eq!(
+            state.documents[0].encoding.chosen(),
+            Some(encoding_rs::UTF_8)
+        );
+    }
+
+    #[test]
+    fn reopening_with_an_encoding_survives_a_newer_render_and_a_missing_file() {
+        let dir = TempDir::new("reencode");
+        let path = dir.join("cafe.md");
+        fs::write(&path, b"# Caf\xE9").unwrap();
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(Document::load(&path, &renderer, &Theme::light()));
+
+        let reopen = state.reopen_with_encoding(encoding_rs::UTF_8).unwrap();
+        assert_eq!(state.documents[0].outline[0].title, "Café");
+        assert!(state.set_theme(1));
+        let jobs = state.render_jobs();
+        assert_eq!(state.finish_render(reopen.run(&renderer)), None);
+        render_now(&mut state, jobs, &renderer);
+        assert_eq!(state.documents[0].outline[0].title, "Caf\u{FFFD}");
+        assert_eq!(
+            state.documents[0].encoding.chosen(),
+            Some(encoding_rs::UTF_8)
+        );
+
+        fs::remove_file(&path).unwrap();
+        let job = state
+            .reopen_with_encoding(encoding_rs::WINDOWS_1252)
+            .unwrap();
+        assert_eq!(state.finish_render(job.run(&renderer)), None);
+        assert_eq!(state.documents[0].outline[0].title, "Caf\u{FFFD}");
+        assert!(!state.documents[0].is_rendering());
+    }
+
+    #[test]
+    fn open_document_adds_tabs_and_reuses_tab_for_same_path() {
+        let mut state = AppState::new(Theme::light());
+
+        assert_eq!(state.open_document(loaded("/tmp/a.md", "# A")), 0);
+        assert_eq!(state.open_document(loaded("/tmp/b.md", "# B")), 1);
+        assert_eq!(state.open_document(loaded("/tmp/a.md", "# A2")), 0);
+
+        assert_eq!(state.documents.len(), 2);
+        assert_eq!(state.active, 0);
+        assert_eq!(
+            state.active_document().unwrap().source_markdown.as_deref(),
+            Some("# A2")
+        );
+        assert_eq!(state.window_title(), "Dustrown - a.md");
+    }
+
+    #[test]
+    fn close_active_removes_only_the_active_tab() {
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded("/tmp/a.md", "# A"));
+        state.open_document(loaded("/tmp/b.md", "# B"));
+        state.open_document(loaded("/tmp/c.md", "# C"));
+        state.select(1);
+
+        assert_eq!(state.close_active().unwrap().title, "b.md");
+        assert_eq!(state.active_document().unwrap().title, "c.md");
+        assert_eq!(state.close_active().unwrap().title, "c.md");
+        assert_eq!(state.active_document().unwrap().title, "a.md");
+        assert_eq!(state.close_active().unwrap().title, "a.md");
+
+        assert!(state.close_active().is_none());
+        assert_eq!(state.window_title(), APP_TITLE);
+    }
+
+    #[test]
+    fn opening_documents_keeps_recent_files_newest_first() {
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded("/tmp/a.md", "# A"));
+        state.open_document(loaded("/tmp/b.md", "# B"));
+        state.open_document(loaded("/tmp/a.md", "# A"));
+        assert_eq!(
+            state.recent,
+            [PathBuf::from("/tmp/a.md"), PathBuf::from("/tmp/b.md")]
+        );
+
+        state.open_document(Document::open_error(Path::new("/tmp/b.md"), "gone"));
+        assert_eq!(state.recent, [PathBuf::from("/tmp/a.md")]);
+
+        for index in 0..12 {
+            state.open_document(loaded(&format!("/tmp/{index}.md"), "# N"));
+        }
+        assert_eq!(state.recent.len(), MAX_RECENT_FILES);
+        assert_eq!(state.recent[0], PathBuf::from("/tmp/11.md"));
+    }
+
+    #[test]
+    fn take_closed_reopens_last_closed_file_that_still_exists() {
+        let dir = TempDir::new("closed");
+        let kept = dir.join("kept.md");
+        fs::write(&kept, "# Kept").unwrap();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded(kept.to_str().unwrap(), "# Kept"));
+        state.open_document(loaded(dir.join("deleted.md").to_str().unwrap(), "# Gone"));
+
+        state.close_active();
+        state.close_active();
+
+        assert_eq!(state.recent_files(), [kept.as_path()]);
+        assert_eq!(state.take_closed(), Some(kept));
+        assert_eq!(state.take_closed(), None);
+    }
+
+    #[test]
+    fn select_next_and_previous_wrap_around() {
+        let mut state = AppState::new(Theme::light());
+        state.select_next();
+        assert_eq!(state.active, 0);
+
+        state.open_document(loaded("/tmp/a.md", "# A"));
+        state.open_document(loaded("/tmp/b.md", "# B"));
+
+        state.select_next();
+        assert_eq!(state.active, 0);
+        state.select_previous();
+        assert_eq!(state.active, 1);
+        assert!(!state.select(2));
+        assert_eq!(state.active, 1);
+    }
+
+    #[test]
+    fn toggle_theme_re_renders_loaded_documents_only() {
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded("/tmp/a.md", "```rust\nfn main() {}\n```"));
+        state.open_document(Document::open_error(Path::new("/tmp/x.md"), "boom"));
+        let light_html = state.documents[0].rendered_html.clone();
+        let error_html = state.documents[1].rendered_html.clone();
+
+        state.toggle_theme();
+        assert_eq!(state.documents[0].rendered_html, light_html);
+        let jobs = state.render_jobs();
+        assert_eq!(jobs.len(), 1);
+        assert!(state.documents[0].is_rendering());
+        render_now(&mut state, jobs, &Renderer::new());
+
+        assert_eq!(state.theme, Theme::dark());
+        assert!(!state.documents[0].is_rendering());
+        assert_ne!(state.documents[0].rendered_html, light_html);
+        assert_eq!(state.documents[1].rendered_html, error_html);
+    }
+
+    #[test]
+    fn reload_path_updates_every_loaded_tab_for_that_file() {
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded("/tmp/a.md", "# A"));
+        state.open_document(loaded("/tmp/b.md", "# B"));
+        state.open_document(Document::open_error(Path::new("/tmp/c.md"), "boom"));
+
+        let jobs = state.reload_path(Path::new("/tmp/a.md"), b"# New");
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(state.documents[0].outline[0].title, "A");
+        render_now(&mut state, jobs, &Renderer::new());
+
+        assert_eq!(state.documents[0].outline[0].title, "New");
+        assert_eq!(
+            state.watched_paths(),
+            [PathBuf::from("/tmp/a.md"), PathBuf::from("/tmp/b.md")]
+        );
+    }
+
+    #[test]
+    fn finish_render_drops_superseded_and_closed_results() {
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(loaded("/tmp/a.md", "# A"));
+        state.open_document(loaded("/tmp/b.md", "# B"));
+        state.active = 1;
+
+        let jobs = state.render_jobs();
+        assert_eq!(jobs[0].reason, RenderReason::Theme);
+        let stale: Vec<FinishedRender> = jobs.into_iter().map(|job| job.run(&renderer)).collect();
+        let newer = state.reload_path(Path::new("/tmp/b.md"), b"# New B");
+        state.active = 0;
+        state.close_active();
+
+        for render in stale {
+            assert_eq!(state.finish_render(render), None);
+        }
+        assert_eq!(state.documents[0].outline[0].title, "B");
+        assert!(state.documents[0].is_rendering());
+
+        let finished = newer.into_iter().next().unwrap().run(&renderer);
+        assert_eq!(state.finish_render(finished), Some(0));
+        assert_eq!(state.documents[0].outline[0].title, "New B");
+        assert!(!state.documents[0].is_rendering());
+    }
+
+    #[test]
+    fn default_body_contains_open_instruction() {
+        let body = default_body();
+        assert!(body.contains("Ctrl+O"));
+        assert!(body.contains("Open"));
+    }
+
+    #[test]
+    fn fixture_markdown_samples_render_expected_html_snippets() {
+        let basic = include_str!("../tests/fixtures/markdown/basic.md");
+        let table_tasklist = include_str!("../tests/fixtures/markdown/table-tasklist.md");
+        let unsafe_markdown = include_str!("../tests/fixtures/markdown/unsafe.md");
+
+        let renderer = Renderer::new();
 
-        let basic_html = render_markdown(basic, Theme::Light);
-        assert!(basic_html.contains("<h1>Fixture Title</h1>"));
+        let basic_html = renderer.render_markdown(basic, &Theme::light(), RemoteContent::Allow);
+        assert!(basic_html.contains("<h1 id=\"fixture-title\">Fixture Title</h1>"));
         assert!(basic_html.contains("<strong>fixture</strong>"));
         assert!(basic_html.contains("href=\"https://example.com\""));
         assert!(basic_html.contains("<pre"));
 
-        let table_html = render_markdown(table_tasklist, Theme::Light);
-        assert!(table_html.contains("<h2>Checklist</h2>"));
+        let table_html =
+            renderer.render_markdown(table_tasklist, &Theme::light(), RemoteContent::Allow);
+        assert!(table_html.contains("<h2 id=\"checklist\">Checklist</h2>"));
         assert!(table_html.contains("<table>"));
         assert!(table_html.contains("done"));
         assert!(table_html.contains("pending"));
 
-        let unsafe_html = render_markdown(unsafe_markdown, Theme::Dark);
+        let unsafe_html =
+            renderer.render_markdown(unsafe_markdown, &Theme::dark(), RemoteContent::Allow);
         assert!(!unsafe_html.contains("<script"));
         assert!(!unsafe_html.contains("javascript:"));
         assert!(!unsafe_html.contains("onerror="));
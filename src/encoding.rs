@@ -0,0 +1,247 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{
+    Encoding, BIG5, EUC_JP, EUC_KR, GBK, ISO_8859_15, ISO_8859_2, KOI8_R, SHIFT_JIS, UTF_16BE,
+    UTF_16LE, UTF_8, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252,
+};
+
+/// How much of a file is checked for binary content and UTF-16 without a
+/// byte order mark.
+pub const SNIFF_LEN: usize = 8192;
+
+/// Encodings offered by View → Reopen with Encoding, in menu order.
+pub fn reopen_encodings() -> [&'static Encoding; 14] {
+    [
+        UTF_8,
+        UTF_16LE,
+        UTF_16BE,
+        WINDOWS_1252,
+        ISO_8859_15,
+        WINDOWS_1250,
+        ISO_8859_2,
+        WINDOWS_1251,
+        KOI8_R,
+        SHIFT_JIS,
+        EUC_JP,
+        GBK,
+        BIG5,
+        EUC_KR,
+    ]
+}
+
+/// How the encoding of a text was settled on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodingOrigin {
+    /// The file starts with a byte order mark.
+    Bom,
+    /// Guessed from the bytes.
+    Detected,
+    /// Picked with View → Reopen with Encoding.
+    Chosen,
+}
+
+/// The encoding a text was decoded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub origin: EncodingOrigin,
+    /// Some bytes were not valid in `encoding` and show as U+FFFD.
+    pub had_errors: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            origin: EncodingOrigin::Detected,
+            had_errors: false,
+        }
+    }
+}
+
+impl TextEncoding {
+    /// The encoding to decode the text with again, say when the file
+    /// changes: the one picked by the user, or `None` to detect it afresh.
+    pub fn chosen(&self) -> Option<&'static Encoding> {
+        (self.origin == EncodingOrigin::Chosen).then_some(self.encoding)
+    }
+
+    /// Notice to show with the document, or `None` for clean UTF-8.
+    pub fn notice(&self) -> Option<String> {
+        if self.encoding == UTF_8 && !self.had_errors {
+            return None;
+        }
+        let name = self.encoding.name();
+        let mut notice = match self.origin {
+            EncodingOrigin::Bom => format!("Shown as {name}, from the byte order mark."),
+            EncodingOrigin::Detected => format!(
+                "Shown as {name}, detected from the contents. \
+                 If the text looks wrong, reopen it with another encoding."
+            ),
+            EncodingOrigin::Chosen => format!("Shown as {name}, as chosen."),
+        };
+        if self.had_errors {
+            notice.push_str(&format!(
+                " Some bytes are not valid {name} and show as \u{FFFD}."
+            ));
+        }
+        Some(notice)
+    }
+}
+
+/// Decodes `bytes` as `chosen`, or else as the encoding named by a byte
+/// order mark or guessed from the bytes. Invalid sequences are replaced,
+/// so this never fails.
+pub fn decode(bytes: &[u8], chosen: Option<&'static Encoding>) -> (String, TextEncoding) {
+    let (encoding, origin) = match chosen {
+        Some(encoding) => (encoding, EncodingOrigin::Chosen),
+        None => detect(bytes),
+    };
+    let (text, had_errors) = encoding.decode_with_bom_removal(bytes);
+    (
+        text.into_owned(),
+        TextEncoding {
+            encoding,
+            origin,
+            had_errors,
+        },
+    )
+}
+
+/// Whether `bytes` hold binary data rather than text: a NUL byte near the
+/// start, which text files do not have unless they are UTF-16.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    sniffed(bytes).contains(&0)
+        && Encoding::for_bom(bytes).is_none()
+        && utf16_without_bom(bytes).is_none()
+}
+
+fn detect(bytes: &[u8]) -> (&'static Encoding, EncodingOrigin) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, EncodingOrigin::Bom);
+    }
+    if let Some(encoding) = utf16_without_bom(bytes) {
+        return (encoding, EncodingOrigin::Detected);
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, EncodingOrigin::Detected);
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    (detector.guess(None, true), EncodingOrigin::Detected)
+}
+
+/// UTF-16 without a byte order mark, told apart by the NUL high bytes of
+/// mostly-ASCII text: nearly every code unit has one, always on the same
+/// side.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let units = sniffed(bytes).chunks_exact(2);
+    let count = units.len();
+    if count < 2 {
+        return None;
+    }
+    let (mut high_first, mut high_last) = (0, 0);
+    for unit in units {
+        high_first += usize::from(unit[0] == 0);
+        high_last += usize::from(unit[1] == 0);
+    }
+    let mostly = |nuls: usize| nuls * 10 >= count * 9;
+    match (high_first, high_last) {
+        (0, nuls) if mostly(nuls) => Some(UTF_16LE),
+        (nuls, 0) if mostly(nuls) => Some(UTF_16BE),
+        _ => None,
+    }
+}
+
+fn sniffed(bytes: &[u8]) -> &[u8] {
+    &bytes[..bytes.len().min(SNIFF_LEN)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let mut bytes = if bom { vec![0xFF, 0xFE] } else { Vec::new() };
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        bytes
+    }
+
+    #[test]
+    fn decode_keeps_utf8_without_a_notice() {
+        let (text, encoding) = decode("# Café".as_bytes(), None);
+
+        assert_eq!(text, "# Café");
+        assert_eq!(encoding, TextEncoding::default());
+        assert_eq!(encoding.notice(), None);
+    }
+
+    #[test]
+    fn decode_follows_a_byte_order_mark_and_drops_it() {
+        let (text, encoding) = decode(&utf16le("# Título", true), None);
+        assert_eq!(text, "# Título");
+        assert_eq!(encoding.encoding, UTF_16LE);
+        assert_eq!(encoding.origin, EncodingOrigin::Bom);
+
+        let (text, encoding) = decode(b"\xEF\xBB\xBF# Title", None);
+        assert_eq!(text, "# Title");
+        assert_eq!(encoding.origin, EncodingOrigin::Bom);
+        assert_eq!(encoding.notice(), None);
+    }
+
+    #[test]
+    fn decode_detects_utf16_without_a_byte_order_mark() {
+        let (text, encoding) = decode(&utf16le("# Notes\n\nPlain text.", false), None);
+
+        assert_eq!(text, "# Notes\n\nPlain text.");
+        assert_eq!(encoding.encoding, UTF_16LE);
+    }
+
+    #[test]
+    fn decode_guesses_legacy_single_byte_encodings() {
+        let bytes = b"# R\xE9sum\xE9\n\nCaf\xE9 cr\xE8me, na\xEFve fa\xE7ade \x96 \x93quoted\x94.";
+
+        let (text, encoding) = decode(bytes, None);
+
+        assert_eq!(encoding.encoding, WINDOWS_1252);
+        assert!(text.contains("Résumé"));
+        assert!(text.contains("“quoted”"));
+        assert!(encoding
+            .notice()
+            .unwrap()
+            .contains("windows-1252, detected"));
+    }
+
+    #[test]
+    fn decode_with_a_chosen_encoding_replaces_invalid_bytes() {
+        let (text, encoding) = decode(b"caf\xE9", Some(UTF_8));
+
+        assert_eq!(text, "caf\u{FFFD}");
+        assert_eq!(encoding.chosen(), Some(UTF_8));
+        assert!(encoding.had_errors);
+        assert_eq!(
+            encoding.notice().as_deref(),
+            Some("Shown as UTF-8, as chosen. Some bytes are not valid UTF-8 and show as \u{FFFD}.")
+        );
+        assert_eq!(decode(b"caf\xE9", None).1.chosen(), None);
+    }
+
+    #[test]
+    fn looks_binary_spares_utf16_text() {
+        assert!(looks_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!looks_binary(b"# Plain text"));
+        assert!(!looks_binary(&utf16le("# Notes", true)));
+        assert!(!looks_binary(&utf16le("# Notes", false)));
+    }
+
+    #[test]
+    fn reopen_encodings_are_unique() {
+        let encodings = reopen_encodings();
+        for (index, encoding) in encodings.iter().enumerate() {
+            assert!(
+                !encodings[..index].contains(encoding),
+                "{}",
+                encoding.name()
+            );
+        }
+    }
+}
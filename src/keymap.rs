@@ -0,0 +1,575 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::core::{LinkTarget, Navigation};
+
+/// How long a multi-key binding waits for its next key.
+pub const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Default bindings of both frontends, as key sequence and command.
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("Ctrl+O", "open"),
+    ("Ctrl+W", "close"),
+    ("Ctrl+Shift+T", "reopen-closed"),
+    ("Ctrl+Q", "quit"),
+    ("Ctrl+F", "find"),
+    ("F3", "find-next"),
+    ("Shift+F3", "find-previous"),
+    ("Ctrl+D", "theme"),
+    ("Ctrl+Shift+O", "outline"),
+    ("Ctrl+Tab", "next-tab"),
+    ("Ctrl+Shift+Tab", "previous-tab"),
+    ("Alt+Left", "back"),
+    ("Alt+Right", "forward"),
+];
+
+/// Vim-style reading keys, bound unless `vim_navigation` is turned off.
+const VIM_BINDINGS: &[(&str, &str)] = &[
+    ("j", "scroll-down"),
+    ("k", "scroll-up"),
+    ("d", "half-page-down"),
+    ("u", "half-page-up"),
+    ("g g", "top"),
+    ("G", "bottom"),
+    ("] ]", "next-heading"),
+    ("[ [", "previous-heading"),
+    ("/", "find"),
+];
+
+/// Keys written by name; any other key is a single character.
+const NAMED_KEYS: &[&str] = &[
+    "Tab",
+    "Enter",
+    "Escape",
+    "Space",
+    "Backspace",
+    "Delete",
+    "Insert",
+    "Home",
+    "End",
+    "PageUp",
+    "PageDown",
+    "Left",
+    "Right",
+    "Up",
+    "Down",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "F11",
+    "F12",
+];
+
+/// Something the user asked the app to do, from a key binding, the menu or
+/// the Windows page (which posts it as text, see [`parse_app_event`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppEvent {
+    Open,
+    Close,
+    ReopenClosed,
+    /// Opens entry `i` of [`AppState::recent_files`](crate::core::AppState::recent_files).
+    OpenRecent(usize),
+    /// Reads the active file again as entry `i` of
+    /// [`reopen_encodings`](crate::encoding::reopen_encodings).
+    ReopenWithEncoding(usize),
+    ToggleTheme,
+    SelectTheme(usize),
+    ToggleOutline,
+    Find,
+    FindNext,
+    FindPrevious,
+    SelectTab(usize),
+    CloseTab(usize),
+    NextTab,
+    PreviousTab,
+    /// Only sent by the Windows frontend's navigation handlers.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Link(LinkTarget),
+    Back,
+    Forward,
+    AllowRemote,
+    /// Stops reading the files being opened.
+    CancelLoad,
+    Navigate(Navigation),
+    /// Files dropped on the Windows window.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Dropped(Vec<PathBuf>),
+    /// The Windows page's scroll offset, kept to restore it.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Scrolled(u32),
+    /// A Windows worker thread finished reading a file.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Loaded,
+    /// A Windows worker thread finished rendering an open tab.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Rendered,
+    Quit,
+}
+
+pub fn parse_app_event(raw: &str) -> Option<AppEvent> {
+    match raw {
+        "open" => Some(AppEvent::Open),
+        "close" => Some(AppEvent::Close),
+        "reopen-closed" => Some(AppEvent::ReopenClosed),
+        "theme" => Some(AppEvent::ToggleTheme),
+        "outline" => Some(AppEvent::ToggleOutline),
+        "find" => Some(AppEvent::Find),
+        "find-next" => Some(AppEvent::FindNext),
+        "find-previous" => Some(AppEvent::FindPrevious),
+        "next-tab" => Some(AppEvent::NextTab),
+        "previous-tab" => Some(AppEvent::PreviousTab),
+        "back" => Some(AppEvent::Back),
+        "forward" => Some(AppEvent::Forward),
+        "allow-remote" => Some(AppEvent::AllowRemote),
+        "cancel-load" => Some(AppEvent::CancelLoad),
+        "scroll-down" => Some(AppEvent::Navigate(Navigation::LineDown)),
+        "scroll-up" => Some(AppEvent::Navigate(Navigation::LineUp)),
+        "half-page-down" => Some(AppEvent::Navigate(Navigation::HalfPageDown)),
+        "half-page-up" => Some(AppEvent::Navigate(Navigation::HalfPageUp)),
+        "top" => Some(AppEvent::Navigate(Navigation::Top)),
+        "bottom" => Some(AppEvent::Navigate(Navigation::Bottom)),
+        "next-heading" => Some(AppEvent::Navigate(Navigation::NextHeading)),
+        "previous-heading" => Some(AppEvent::Navigate(Navigation::PreviousHeading)),
+        "quit" => Some(AppEvent::Quit),
+        _ => {
+            if let Some(index) = raw.strip_prefix("tab:") {
+                index.parse().ok().map(AppEvent::SelectTab)
+            } else if let Some(index) = raw.strip_prefix("theme:") {
+                index.parse().ok().map(AppEvent::SelectTheme)
+            } else if let Some(index) = raw.strip_prefix("close-tab:") {
+                index.parse().ok().map(AppEvent::CloseTab)
+            } else if let Some(index) = raw.strip_prefix("recent:") {
+                index.parse().ok().map(AppEvent::OpenRecent)
+            } else if let Some(index) = raw.strip_prefix("encoding:") {
+                index.parse().ok().map(AppEvent::ReopenWithEncoding)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// A key plus modifiers, written like `Ctrl+Shift+O`, `Alt+Left` or `F3`.
+/// On its own, an uppercase letter stands for Shift plus that letter. Shift
+/// is dropped from other characters, since it already picked the character
+/// (`?` rather than `/`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// A lowercase character or one of the named keys, such as `PageDown`.
+    pub key: String,
+}
+
+impl KeyChord {
+    /// Chord for a key press reported by a frontend; `key` is a character
+    /// or a name as accepted in chord text.
+    pub fn from_key(ctrl: bool, alt: bool, shift: bool, key: &str) -> Option<Self> {
+        let mut chord = Self {
+            ctrl,
+            alt,
+            shift,
+            key: String::new(),
+        };
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_whitespace() => {
+                chord.shift &= c.is_alphabetic();
+                chord.key = c.to_lowercase().collect();
+            }
+            (Some(' '), None) => chord.key = "Space".to_string(),
+            _ => {
+                chord.key = NAMED_KEYS
+                    .iter()
+                    .find(|name| name.eq_ignore_ascii_case(key))?
+                    .to_string();
+            }
+        }
+        Some(chord)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        // `Ctrl++` binds the plus key itself.
+        if text.trim_end().ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let Some((key, modifiers)) = parts.split_last() else {
+            return Err("empty key chord".to_string());
+        };
+        let (mut ctrl, mut alt, mut shift) = (false, false, false);
+        for modifier in modifiers {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ctrl = true,
+                "alt" => alt = true,
+                "shift" => shift = true,
+                _ => return Err(format!("unknown modifier '{modifier}' in '{text}'")),
+            }
+        }
+        if modifiers.is_empty() && key.chars().count() == 1 && key.chars().all(char::is_uppercase) {
+            shift = true;
+        }
+        Self::from_key(ctrl, alt, shift, key)
+            .ok_or_else(|| format!("unknown key '{key}' in '{text}'"))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.alt, "Alt+"),
+            (self.shift, "Shift+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        if self.key.chars().count() == 1 {
+            f.write_str(&self.key.to_uppercase())
+        } else {
+            f.write_str(&self.key)
+        }
+    }
+}
+
+/// Parses chords separated by spaces, such as `Ctrl+K Ctrl+T` or `g g`.
+pub fn parse_sequence(text: &str) -> Result<Vec<KeyChord>, String> {
+    let sequence = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<KeyChord>, String>>()?;
+    if sequence.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(sequence)
+}
+
+pub fn sequence_text(sequence: &[KeyChord]) -> String {
+    let chords: Vec<String> = sequence.iter().map(ToString::to_string).collect();
+    chords.join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub keys: Vec<KeyChord>,
+    /// Command text as understood by [`parse_app_event`].
+    pub command: String,
+}
+
+/// The key bindings of both frontends: the defaults, adjusted by the
+/// `[keybindings]` table of the config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_table(&[DEFAULT_BINDINGS, VIM_BINDINGS])
+    }
+}
+
+/// What a key press did, see [`Keymap::press`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyPress {
+    Command(AppEvent),
+    /// The keys so far start a longer binding.
+    Pending,
+    Unbound,
+}
+
+/// Keys typed so far towards a multi-key binding.
+#[derive(Debug, Default)]
+pub struct PendingKeys {
+    keys: Vec<KeyChord>,
+    last_press: Option<Instant>,
+}
+
+impl Keymap {
+    /// The default bindings without the vim-style reading keys.
+    pub fn without_vim_navigation() -> Self {
+        Self::from_table(&[DEFAULT_BINDINGS])
+    }
+
+    fn from_table(tables: &[&[(&str, &str)]]) -> Self {
+        Self {
+            bindings: tables
+                .iter()
+                .flat_map(|table| table.iter())
+                .map(|(keys, command)| Binding {
+                    keys: parse_sequence(keys).expect("valid default binding"),
+                    command: command.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Replaces the bindings of `command` with `sequences`; no sequences
+    /// unbinds it.
+    pub fn rebind(&mut self, command: &str, sequences: Vec<Vec<KeyChord>>) {
+        self.bindings.retain(|binding| binding.command != command);
+        self.bindings
+            .extend(sequences.into_iter().map(|keys| Binding {
+                keys,
+                command: command.to_string(),
+            }));
+    }
+
+    /// The first single-chord binding of `event`, for menu labels.
+    pub fn shortcut(&self, event: &AppEvent) -> Option<&KeyChord> {
+        self.bindings
+            .iter()
+            .find(|binding| {
+                binding.keys.len() == 1 && parse_app_event(&binding.command).as_ref() == Some(event)
+            })
+            .map(|binding| &binding.keys[0])
+    }
+
+    /// Bindings that shadow each other: the same sequence twice, or one
+    /// sequence starting another, which could then never be typed.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (index, binding) in self.bindings.iter().enumerate() {
+            for other in &self.bindings[index + 1..] {
+                let (shorter, longer) = if binding.keys.len() <= other.keys.len() {
+                    (binding, other)
+                } else {
+                    (other, binding)
+                };
+                if longer.keys.starts_with(&shorter.keys) {
+                    conflicts.push(format!(
+                        "{} ({}) shadows {} ({})",
+                        sequence_text(&shorter.keys),
+                        shorter.command,
+                        sequence_text(&longer.keys),
+                        longer.command
+                    ));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Feeds one key press to the keymap. Keys that start a longer binding
+    /// are kept in `pending` until it completes, the next key does not fit,
+    /// or [`SEQUENCE_TIMEOUT`] passes.
+    pub fn press(&self, pending: &mut PendingKeys, chord: KeyChord, now: Instant) -> KeyPress {
+        if pending
+            .last_press
+            .is_some_and(|last| now.duration_since(last) > SEQUENCE_TIMEOUT)
+        {
+            pending.keys.clear();
+        }
+        pending.last_press = Some(now);
+        pending.keys.push(chord);
+
+        if let Some(binding) = self
+            .bindings
+            .iter()
+            .find(|binding| binding.keys == pending.keys)
+        {
+            pending.keys.clear();
+            return parse_app_event(&binding.command).map_or(KeyPress::Unbound, KeyPress::Command);
+        }
+        if self
+            .bindings
+            .iter()
+            .any(|binding| binding.keys.starts_with(&pending.keys))
+        {
+            return KeyPress::Pending;
+        }
+        // A key that breaks off a sequence may still start a new one.
+        let retry = pending.keys.len() > 1;
+        let chord = pending.keys.pop().expect("key was just pushed");
+        pending.keys.clear();
+        if retry {
+            self.press(pending, chord, now)
+        } else {
+            KeyPress::Unbound
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> KeyChord {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_app_event_maps_known_actions() {
+        assert_eq!(parse_app_event("open"), Some(AppEvent::Open));
+        assert_eq!(parse_app_event("close"), Some(AppEvent::Close));
+        assert_eq!(parse_app_event("theme"), Some(AppEvent::ToggleTheme));
+        assert_eq!(parse_app_event("outline"), Some(AppEvent::ToggleOutline));
+        assert_eq!(parse_app_event("find"), Some(AppEvent::Find));
+        assert_eq!(parse_app_event("find-next"), Some(AppEvent::FindNext));
+        assert_eq!(
+            parse_app_event("find-previous"),
+            Some(AppEvent::FindPrevious)
+        );
+        assert_eq!(parse_app_event("next-tab"), Some(AppEvent::NextTab));
+        assert_eq!(parse_app_event("previous-tab"), Some(AppEvent::PreviousTab));
+        assert_eq!(parse_app_event("tab:2"), Some(AppEvent::SelectTab(2)));
+        assert_eq!(parse_app_event("theme:1"), Some(AppEvent::SelectTheme(1)));
+        assert_eq!(parse_app_event("close-tab:0"), Some(AppEvent::CloseTab(0)));
+        assert_eq!(
+            parse_app_event("reopen-closed"),
+            Some(AppEvent::ReopenClosed)
+        );
+        assert_eq!(parse_app_event("recent:3"), Some(AppEvent::OpenRecent(3)));
+        assert_eq!(
+            parse_app_event("encoding:4"),
+            Some(AppEvent::ReopenWithEncoding(4))
+        );
+        assert_eq!(parse_app_event("back"), Some(AppEvent::Back));
+        assert_eq!(parse_app_event("forward"), Some(AppEvent::Forward));
+        assert_eq!(parse_app_event("allow-remote"), Some(AppEvent::AllowRemote));
+        assert_eq!(parse_app_event("cancel-load"), Some(AppEvent::CancelLoad));
+        assert_eq!(
+            parse_app_event("half-page-up"),
+            Some(AppEvent::Navigate(Navigation::HalfPageUp))
+        );
+        assert_eq!(
+            parse_app_event("previous-heading"),
+            Some(AppEvent::Navigate(Navigation::PreviousHeading))
+        );
+        assert_eq!(parse_app_event("quit"), Some(AppEvent::Quit));
+        assert_eq!(parse_app_event("tab:x"), None);
+        assert_eq!(parse_app_event("unknown"), None);
+    }
+
+    #[test]
+    fn key_chords_parse_and_print_canonically() {
+        let tab = chord("shift+ctrl+tab");
+        assert!(tab.ctrl && tab.shift && !tab.alt);
+        assert_eq!(tab.key, "Tab");
+        assert_eq!(tab.to_string(), "Ctrl+Shift+Tab");
+        assert_eq!(chord("G"), chord("Shift+g"));
+        assert_eq!(chord("Ctrl+O"), chord("ctrl+o"));
+        assert_eq!(chord("Shift+?"), chord("?"));
+        assert_eq!(chord("Ctrl++").key, "+");
+        assert_eq!(chord("F3").to_string(), "F3");
+        assert!("Ctrl+".parse::<KeyChord>().is_err());
+        assert!("Ctrl+Banana".parse::<KeyChord>().is_err());
+        assert!("Hyper+O".parse::<KeyChord>().is_err());
+        assert_eq!(
+            parse_sequence("Ctrl+K  Ctrl+T").map(|keys| sequence_text(&keys)),
+            Ok("Ctrl+K Ctrl+T".to_string())
+        );
+        assert!(parse_sequence(" ").is_err());
+    }
+
+    #[test]
+    fn every_default_binding_names_an_app_event() {
+        let keymap = Keymap::default();
+
+        for binding in keymap.bindings() {
+            assert!(parse_app_event(&binding.command).is_some(), "{binding:?}");
+        }
+        assert!(keymap.conflicts().is_empty());
+        assert_eq!(keymap.shortcut(&AppEvent::Open), Some(&chord("Ctrl+O")));
+    }
+
+    #[test]
+    fn press_completes_multi_key_sequences() {
+        let mut keymap = Keymap::default();
+        keymap.rebind("theme", vec![parse_sequence("Ctrl+K Ctrl+T").unwrap()]);
+        let mut pending = PendingKeys::default();
+        let now = Instant::now();
+
+        assert_eq!(
+            keymap.press(&mut pending, chord("Ctrl+D"), now),
+            KeyPress::Unbound
+        );
+        assert_eq!(
+            keymap.press(&mut pending, chord("Ctrl+K"), now),
+            KeyPress::Pending
+        );
+        assert_eq!(
+            keymap.press(&mut pending, chord("Ctrl+T"), now),
+            KeyPress::Command(AppEvent::ToggleTheme)
+        );
+        // A key outside the sequence drops it and counts on its own.
+        keymap.press(&mut pending, chord("Ctrl+K"), now);
+        assert_eq!(
+            keymap.press(&mut pending, chord("Ctrl+O"), now),
+            KeyPress::Command(AppEvent::Open)
+        );
+        // So does waiting too long.
+        keymap.press(&mut pending, chord("Ctrl+K"), now);
+        assert_eq!(
+            keymap.press(&mut pending, chord("Ctrl+T"), now + SEQUENCE_TIMEOUT * 2),
+            KeyPress::Unbound
+        );
+    }
+
+    #[test]
+    fn press_runs_vim_navigation_keys() {
+        let keymap = Keymap::default();
+        let mut pending = PendingKeys::default();
+        let now = Instant::now();
+        let mut press = |key: &str| keymap.press(&mut pending, chord(key), now);
+
+        assert_eq!(
+            press("j"),
+            KeyPress::Command(AppEvent::Navigate(Navigation::LineDown))
+        );
+        assert_eq!(press("g"), KeyPress::Pending);
+        assert_eq!(
+            press("g"),
+            KeyPress::Command(AppEvent::Navigate(Navigation::Top))
+        );
+        assert_eq!(
+            press("G"),
+            KeyPress::Command(AppEvent::Navigate(Navigation::Bottom))
+        );
+        assert_eq!(press("]"), KeyPress::Pending);
+        assert_eq!(
+            press("]"),
+            KeyPress::Command(AppEvent::Navigate(Navigation::NextHeading))
+        );
+        assert_eq!(press("/"), KeyPress::Command(AppEvent::Find));
+
+        let plain = Keymap::without_vim_navigation();
+        assert_eq!(
+            plain.press(&mut PendingKeys::default(), chord("j"), now),
+            KeyPress::Unbound
+        );
+    }
+
+    #[test]
+    fn conflicts_reports_shadowed_sequences() {
+        let mut keymap = Keymap::default();
+        keymap.rebind("theme", vec![parse_sequence("Ctrl+O Ctrl+T").unwrap()]);
+
+        assert_eq!(
+            keymap.conflicts(),
+            ["Ctrl+O (open) shadows Ctrl+O Ctrl+T (theme)"]
+        );
+    }
+}
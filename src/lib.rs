@@ -0,0 +1,39 @@
+//! The Markdown renderer and viewer behind the `dustrown` binary. The
+//! renderer and themes are exported for the benchmarks.
+
+pub mod cli;
+mod config;
+mod core;
+mod encoding;
+mod keymap;
+mod math;
+mod session;
+#[cfg(test)]
+mod test_support;
+mod theme;
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+mod windows_shared;
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(target_os = "windows")]
+mod windows;
+
+pub use crate::core::{RemoteContent, Renderer};
+pub use crate::theme::Theme;
+
+#[cfg(target_os = "linux")]
+pub fn run_gui(args: cli::ViewerArgs) {
+    linux::run(args);
+}
+
+#[cfg(target_os = "windows")]
+pub fn run_gui(args: cli::ViewerArgs) {
+    windows::run(args);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn run_gui(_args: cli::ViewerArgs) {
+    eprintln!("dustrown GUI currently supports Linux and Windows runtimes.");
+}
@@ -1,69 +1,1237 @@
-use std::{cell::RefCell, env, fs, path::Path, path::PathBuf, rc::Rc};
+use std::{
+    cell::Cell, cell::RefCell, fs, path::Path, path::PathBuf, rc::Rc, sync::Arc, time::Instant,
+};
 
-use gtk::{gdk, prelude::*};
-use webkit2gtk::{SettingsExt, WebView, WebViewExt};
+use gtk::{gdk, gio, glib, prelude::*};
+use javascriptcore::ValueExt;
+use webkit2gtk::{
+    FindControllerExt, FindOptions, LoadEvent, NavigationPolicyDecision,
+    NavigationPolicyDecisionExt, NavigationType, PolicyDecisionExt, PolicyDecisionType,
+    SettingsExt, URIRequestExt, WebView, WebViewExt,
+};
 
-use crate::core::{self, AppState};
+use crate::{
+    cli::{self, ViewerArgs},
+    config::{self, ConfigError},
+    core::{
+        self, AppState, Document, DocumentSource, FindStatus, FinishedRender, Heading, LinkTarget,
+        LoadOptions, MarkdownLink, PendingLoad, ReloadWatch, RenderJob, RenderReason, Renderer,
+    },
+    encoding,
+    keymap::{AppEvent, KeyChord, KeyPress, PendingKeys},
+    session::{self, Session},
+    theme::Theme,
+};
 
-fn apply_loaded_markdown(state: &mut AppState, markdown: String) {
-    state.source_markdown = Some(markdown.clone());
-    state.rendered_html = Some(core::render_markdown(&markdown, state.theme));
+const FIND_MAX_MATCHES: u32 = 10_000;
+
+/// Tab title for plain text dropped on the window.
+const DROPPED_TEXT_TITLE: &str = "(dropped text)";
+
+const OUTLINE_TITLE_COLUMN: u32 = 0;
+const OUTLINE_ID_COLUMN: u32 = 1;
+
+const EMPTY_PAGE: &str = "empty";
+const TABS_PAGE: &str = "tabs";
+
+/// Widgets shared by every tab. Notebook page `i` holds the webview of
+/// `AppState::documents[i]`.
+#[derive(Clone)]
+struct Ui {
+    window: gtk::Window,
+    content: gtk::Stack,
+    notebook: gtk::Notebook,
+    placeholder: WebView,
+    outline: gtk::TreeStore,
+    find_bar: FindBar,
+    back_item: gtk::MenuItem,
+    forward_item: gtk::MenuItem,
+    remote_bar: gtk::InfoBar,
+    remote_label: gtk::Label,
+    encoding_bar: gtk::InfoBar,
+    encoding_label: gtk::Label,
+    /// Shown while files are read for new tabs, with a Cancel button.
+    loading_bar: gtk::InfoBar,
+    loading_label: gtk::Label,
+    loading_spinner: gtk::Spinner,
+    /// View → Reopen with Encoding; item `i` rereads the active file as
+    /// `encoding::reopen_encodings()[i]`.
+    encoding_item: gtk::MenuItem,
+    encoding_menu: gtk::Menu,
+    /// View → Theme; radio item `i` selects `AppState::themes[i]`.
+    theme_menu: gtk::Menu,
+    /// File → Open Recent; item `i` opens `AppState::recent_files()[i]`.
+    recent_menu: gtk::Menu,
+    /// Set while the notebook is changed from code, so `switch-page` does not
+    /// feed the change back into the state.
+    syncing: Rc<Cell<bool>>,
+}
+
+/// Live reload for the open files: a GIO file monitor (inotify) per file
+/// reports changes, and a watch is polled directly when no monitor is
+/// available.
+#[derive(Default)]
+struct LiveReload {
+    files: Vec<WatchedFile>,
 }
 
-fn apply_open_error(state: &mut AppState, error_text: &str) {
-    let escaped = html_escape::encode_text(error_text);
-    state.source_markdown = None;
-    state.rendered_html = Some(format!("<h2>Could not open file</h2><p>{escaped}</p>"));
+struct WatchedFile {
+    watch: ReloadWatch,
+    monitor: Option<gio::FileMonitor>,
 }
 
-fn clear_open_file(state: &mut AppState) {
-    state.source_markdown = None;
-    state.rendered_html = None;
+fn new_webview() -> WebView {
+    let webview = WebView::new();
+    if let Some(settings) = WebViewExt::settings(&webview) {
+        // Only the document's own scripts are turned off: `run_javascript`,
+        // which live reload and the reading keys use, stops working when
+        // JavaScript is disabled outright. The page's CSP backs this up.
+        settings.set_enable_javascript_markup(false);
+    }
+    // Dropped files go to the window, which opens them in a tab; WebKit
+    // would show them raw in place of the document.
+    webview.drag_dest_unset();
+    webview
+}
+
+/// Full page for `document` (the welcome page when `None`) and the URI to
+/// load it under: the base its relative links resolve against, plus the
+/// fragment WebKit scrolls to once the page is parsed.
+fn document_page(document: Option<&Document>, theme: &Theme) -> (String, Option<String>) {
+    let body = document.map_or_else(core::default_body, |d| d.rendered_html.clone());
+    let base_uri = document
+        .and_then(Document::source_path)
+        .and_then(core::document_base_uri);
+    let page = core::render_document(&body, theme, base_uri.as_deref());
+    let fragment = document.and_then(|d| d.fragment.as_deref());
+    let load_uri = match (base_uri, fragment) {
+        (Some(base_uri), Some(fragment)) => Some(format!("{base_uri}#{fragment}")),
+        (base_uri, _) => base_uri,
+    };
+    (page, load_uri)
 }
 
-fn toggle_theme(state: &mut AppState) {
-    state.theme = state.theme.toggled();
+fn load_document(webview: &WebView, document: Option<&Document>, theme: &Theme) {
+    let (page, load_uri) = document_page(document, theme);
+    webview.load_html(&page, load_uri.as_deref());
+}
 
-    if let Some(markdown) = state.source_markdown.clone() {
-        state.rendered_html = Some(core::render_markdown(&markdown, state.theme));
+/// Navigation the viewer handles itself instead of letting the webview
+/// leave the document; `None` lets WebKit proceed.
+fn intercepted_navigation(
+    webview: &WebView,
+    decision: &webkit2gtk::PolicyDecision,
+    decision_type: PolicyDecisionType,
+) -> Option<LinkTarget> {
+    if !matches!(
+        decision_type,
+        PolicyDecisionType::NavigationAction | PolicyDecisionType::NewWindowAction
+    ) {
+        return None;
+    }
+    let action = decision
+        .downcast_ref::<NavigationPolicyDecision>()?
+        .navigation_action()?;
+    let uri = action.request()?.uri()?;
+    let user_initiated = decision_type == PolicyDecisionType::NewWindowAction
+        || matches!(
+            action.navigation_type(),
+            NavigationType::LinkClicked
+                | NavigationType::FormSubmitted
+                | NavigationType::FormResubmitted
+        );
+    if !user_initiated {
+        // Pages the viewer loads itself (`load_html`) live under file: URIs.
+        let own_load = uri.starts_with("file:") || uri.starts_with("about:");
+        return (!own_load).then(|| LinkTarget::Blocked(uri.to_string()));
+    }
+    match core::classify_link(&uri, webview.uri().as_deref()) {
+        LinkTarget::SameDocument => None,
+        target => Some(target),
     }
 }
 
-fn refresh_view(webview: &WebView, state: &AppState) {
-    let body = state
-        .rendered_html
-        .clone()
-        .unwrap_or_else(core::default_body);
-    let page = core::render_document(&body, state.theme);
-    webview.load_html(&page, None);
+fn open_external(window: &gtk::Window, url: &str, confirm: bool) {
+    if confirm {
+        let dialog = gtk::MessageDialog::new(
+            Some(window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            "Open this link in your browser?",
+        );
+        dialog.set_secondary_text(Some(url));
+        dialog.add_buttons(&[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open in Browser", gtk::ResponseType::Accept),
+        ]);
+        let response = dialog.run();
+        dialog.close();
+        if response != gtk::ResponseType::Accept {
+            return;
+        }
+    }
+    if let Err(err) = gio::AppInfo::launch_default_for_uri(url, None::<&gio::AppLaunchContext>) {
+        eprintln!("failed to open {url}: {err}");
+    }
 }
 
-fn open_path(path: &Path, webview: &WebView, window: &gtk::Window, state: &Rc<RefCell<AppState>>) {
-    match fs::read_to_string(path) {
-        Ok(markdown) => {
-            {
+fn report_blocked_link(window: &gtk::Window, url: &str) {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::Close,
+        "This link was blocked",
+    );
+    dialog.set_secondary_text(Some(&format!(
+        "Only web links and local Markdown files can be opened:\n{url}"
+    )));
+    dialog.run();
+    dialog.close();
+}
+
+fn confirm_large_file(window: &gtk::Window, path: &Path, size: u64, limit_mb: u64) -> bool {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        "This file is large",
+    );
+    dialog.set_secondary_text(Some(&core::large_file_prompt(path, size, limit_mb)));
+    dialog.add_buttons(&[
+        ("Cancel", gtk::ResponseType::Cancel),
+        ("Render Anyway", gtk::ResponseType::Accept),
+    ]);
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Accept
+}
+
+fn report_config_errors(window: &gtk::Window, errors: &[ConfigError]) {
+    if errors.is_empty() {
+        return;
+    }
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::Close,
+        "Some settings could not be loaded",
+    );
+    let details: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    dialog.set_secondary_text(Some(&details.join("\n")));
+    dialog.run();
+    dialog.close();
+}
+
+fn tab_webview(ui: &Ui, index: usize) -> Option<WebView> {
+    ui.notebook
+        .nth_page(Some(index as u32))
+        .and_downcast::<WebView>()
+}
+
+fn active_webview(ui: &Ui) -> Option<WebView> {
+    ui.notebook
+        .current_page()
+        .and_then(|index| tab_webview(ui, index as usize))
+}
+
+fn refresh_outline(outline: &gtk::TreeStore, headings: &[Heading]) {
+    fn append(store: &gtk::TreeStore, parent: Option<&gtk::TreeIter>, headings: &[Heading]) {
+        for heading in headings {
+            let iter = store.insert_with_values(
+                parent,
+                None,
+                &[
+                    (OUTLINE_TITLE_COLUMN, &heading.title),
+                    (OUTLINE_ID_COLUMN, &heading.id),
+                ],
+            );
+            append(store, Some(&iter), &heading.children);
+        }
+    }
+
+    outline.clear();
+    append(outline, None, headings);
+}
+
+/// Updates everything that follows the active tab: title, outline, and
+/// whether the welcome page is shown.
+/// Back and forward follow the tab's history, which moves as soon as a
+/// navigation starts and moves back if it fails.
+fn refresh_history_items(ui: &Ui, state: &AppState) {
+    let document = state.active_document();
+    ui.back_item
+        .set_sensitive(document.is_some_and(|d| d.history.can_go_back()));
+    ui.forward_item
+        .set_sensitive(document.is_some_and(|d| d.history.can_go_forward()));
+}
+
+fn refresh_chrome(ui: &Ui, state: &AppState) {
+    ui.window.set_title(&state.window_title());
+    ui.content
+        .set_visible_child_name(if state.documents.is_empty() {
+            EMPTY_PAGE
+        } else {
+            TABS_PAGE
+        });
+    let document = state.active_document();
+    let headings = document.map_or(&[][..], |d| &d.outline);
+    refresh_outline(&ui.outline, headings);
+    refresh_history_items(ui, state);
+    match document.and_then(Document::blocked_remote_message) {
+        Some(message) => {
+            ui.remote_label.set_text(&message);
+            ui.remote_bar.show();
+        }
+        None => ui.remote_bar.hide(),
+    }
+    ui.encoding_item
+        .set_sensitive(document.is_some_and(|d| d.is_loaded() && d.source_path().is_some()));
+    match document.and_then(|d| d.encoding.notice()) {
+        Some(notice) => {
+            ui.encoding_label.set_text(&notice);
+            ui.encoding_bar.show();
+        }
+        None => ui.encoding_bar.hide(),
+    }
+}
+
+fn show_active_tab(ui: &Ui, state: &Rc<RefCell<AppState>>) {
+    let active = {
+        let s = state.borrow();
+        (!s.documents.is_empty()).then_some(s.active)
+    };
+    if let Some(index) = active {
+        ui.syncing.set(true);
+        ui.notebook.set_current_page(Some(index as u32));
+        ui.syncing.set(false);
+    }
+    refresh_chrome(ui, &state.borrow());
+    ui.find_bar
+        .retarget(active.and_then(|index| tab_webview(ui, index)).as_ref());
+}
+
+/// Fills View → Theme with one radio item per available theme.
+fn build_theme_menu(ui: &Ui, state: &Rc<RefCell<AppState>>, renderer: &Arc<Renderer>) {
+    let mut group: Option<gtk::RadioMenuItem> = None;
+    for (index, theme) in state.borrow().themes.iter().enumerate() {
+        let item = match &group {
+            Some(group) => gtk::RadioMenuItem::with_label_from_widget(group, Some(&theme.name)),
+            None => gtk::RadioMenuItem::with_label(&theme.name),
+        };
+        ui.theme_menu.append(&item);
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        item.connect_toggled(move |item| {
+            if ui.syncing.get() || !item.is_active() {
+                return;
+            }
+            let jobs = {
                 let mut s = state.borrow_mut();
-                apply_loaded_markdown(&mut s, markdown);
-                refresh_view(webview, &s);
+                s.set_theme(index);
+                s.render_jobs()
+            };
+            apply_theme(&ui, &state);
+            render_in_background(jobs, &ui, &state, &renderer);
+        });
+        group = Some(item);
+    }
+    sync_theme_menu(ui, &state.borrow());
+}
+
+fn sync_theme_menu(ui: &Ui, state: &AppState) {
+    let active = state.theme_index();
+    ui.syncing.set(true);
+    for (index, item) in ui.theme_menu.children().into_iter().enumerate() {
+        if let Some(item) = item.downcast_ref::<gtk::RadioMenuItem>() {
+            item.set_active(active == Some(index));
+        }
+    }
+    ui.syncing.set(false);
+}
+
+/// Reloads every page with the current theme, except tabs being rendered
+/// again, which reload once their render finishes.
+fn apply_theme(ui: &Ui, state: &Rc<RefCell<AppState>>) {
+    let s = state.borrow();
+    load_document(&ui.placeholder, None, &s.theme);
+    for (index, document) in s.documents.iter().enumerate() {
+        if !document.is_rendering()
+            && let Some(webview) = tab_webview(ui, index)
+        {
+            reload_in_place(&webview, ui, state);
+        }
+    }
+    sync_theme_menu(ui, &s);
+}
+
+/// Loads the page of `webview`'s tab again, say with a new theme, and
+/// scrolls back to where the reader was once it is shown.
+fn reload_in_place(webview: &WebView, ui: &Ui, state: &Rc<RefCell<AppState>>) {
+    let page = webview.clone();
+    let ui = ui.clone();
+    let state = state.clone();
+    webview.run_javascript("window.scrollY", gio::Cancellable::NONE, move |result| {
+        let offset = result
+            .ok()
+            .and_then(|result| result.js_value())
+            .and_then(|value| u32::try_from(value.to_int32()).ok());
+        let Some(index) = ui.notebook.page_num(&page) else {
+            return;
+        };
+        let s = &mut *state.borrow_mut();
+        let Some(document) = s.documents.get_mut(index as usize) else {
+            return;
+        };
+        if offset.is_some() {
+            document.scroll_position = offset;
+        }
+        load_document(&page, Some(document), &s.theme);
+    });
+}
+
+/// Runs `jobs` on worker threads and shows each result in its tab, unless
+/// the tab was closed or rendered again in the meantime.
+fn render_in_background(
+    jobs: Vec<RenderJob>,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+) {
+    for job in jobs {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        glib::MainContext::default().spawn_local(async move {
+            if let Ok(render) = gio::spawn_blocking(move || job.run(&renderer)).await {
+                show_render(render, &ui, &state);
             }
-            window.set_title(&format!(
-                "{} - {}",
-                core::APP_TITLE,
-                core::filename_or_path(path)
-            ));
+        });
+    }
+}
+
+fn show_render(render: FinishedRender, ui: &Ui, state: &Rc<RefCell<AppState>>) {
+    let reason = render.reason;
+    let mut s = state.borrow_mut();
+    let Some(index) = s.finish_render(render) else {
+        return;
+    };
+    let document = &s.documents[index];
+    if let Some(webview) = tab_webview(ui, index) {
+        match reason {
+            RenderReason::Theme => reload_in_place(&webview, ui, state),
+            RenderReason::Content => webview.run_javascript(
+                &core::replace_body_script(&document.rendered_html),
+                gio::Cancellable::NONE,
+                |_| {},
+            ),
+        }
+        set_tab_title(ui, &webview, &document.title);
+    }
+    if index == s.active {
+        refresh_chrome(ui, &s);
+    }
+}
+
+/// Fills File → Open Recent with the recent files that still exist.
+fn rebuild_recent_menu(
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    for item in ui.recent_menu.children() {
+        ui.recent_menu.remove(&item);
+    }
+    let recent = state.borrow().recent_files();
+    if recent.is_empty() {
+        let item = gtk::MenuItem::with_label("No Recent Files");
+        item.set_sensitive(false);
+        ui.recent_menu.append(&item);
+    }
+    for path in recent {
+        let item = gtk::MenuItem::with_label(&core::filename_or_path(&path));
+        item.set_tooltip_text(Some(&path.to_string_lossy()));
+        ui.recent_menu.append(&item);
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        item.connect_activate(move |_| {
+            // Deferred: opening rebuilds this menu, item included.
+            let ui = ui.clone();
+            let state = state.clone();
+            let renderer = renderer.clone();
+            let live_reload = live_reload.clone();
+            let path = path.clone();
+            glib::idle_add_local_once(move || {
+                open_path(&path, &ui, &state, &renderer, &live_reload);
+            });
+        });
+    }
+    ui.recent_menu.show_all();
+}
+
+fn build_outline_view(ui: &Ui) -> gtk::TreeView {
+    let view = gtk::TreeView::with_model(&ui.outline);
+    view.set_headers_visible(false);
+    view.set_activate_on_single_click(true);
+    view.set_enable_search(false);
+
+    let column = gtk::TreeViewColumn::new();
+    let cell = gtk::CellRendererText::new();
+    cell.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    TreeViewColumnExt::pack_start(&column, &cell, true);
+    TreeViewColumnExt::add_attribute(&column, &cell, "text", OUTLINE_TITLE_COLUMN as i32);
+    view.append_column(&column);
+
+    let ui_for_rows = ui.clone();
+    view.connect_row_activated(move |view, path, _| {
+        let Some(model) = view.model() else {
+            return;
+        };
+        if let Some(iter) = model.iter(path)
+            && let Ok(id) = model.value(&iter, OUTLINE_ID_COLUMN as i32).get::<String>()
+            && let Some(webview) = active_webview(&ui_for_rows)
+        {
+            webview.run_javascript(
+                &core::scroll_to_heading_script(&id),
+                gio::Cancellable::NONE,
+                |_| {},
+            );
+        }
+    });
+
+    // Rows are rebuilt on every load; keep the new tree fully expanded.
+    ui.outline.connect_row_inserted({
+        let view = view.clone();
+        move |_, path, _| {
+            if path.depth() > 1 {
+                let mut parent = path.clone();
+                parent.up();
+                view.expand_row(&parent, false);
+            }
+        }
+    });
+
+    view
+}
+
+fn monitor_path(live_reload: &Rc<RefCell<LiveReload>>, path: &Path) -> Option<gio::FileMonitor> {
+    match gio::File::for_path(path)
+        .monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+    {
+        Ok(monitor) => {
+            let weak = Rc::downgrade(live_reload);
+            let watched = path.to_path_buf();
+            monitor.connect_changed(move |_, _, _, _| {
+                if let Some(live_reload) = weak.upgrade()
+                    && let Some(file) = live_reload
+                        .borrow_mut()
+                        .files
+                        .iter_mut()
+                        .find(|file| file.watch.path() == watched)
+                {
+                    file.watch.notify(Instant::now());
+                }
+            });
+            Some(monitor)
         }
         Err(err) => {
+            eprintln!(
+                "file monitor unavailable for {}, polling instead: {err}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+/// Watches exactly `paths`: stops watching closed files and starts watching
+/// newly opened ones.
+fn sync_watches(live_reload: &Rc<RefCell<LiveReload>>, paths: &[PathBuf]) {
+    live_reload.borrow_mut().files.retain(|file| {
+        let keep = paths.iter().any(|path| path == file.watch.path());
+        if !keep && let Some(monitor) = &file.monitor {
+            monitor.cancel();
+        }
+        keep
+    });
+
+    for path in paths {
+        if live_reload
+            .borrow()
+            .files
+            .iter()
+            .any(|file| file.watch.path() == path)
+        {
+            continue;
+        }
+        let monitor = monitor_path(live_reload, path);
+        live_reload.borrow_mut().files.push(WatchedFile {
+            watch: ReloadWatch::new(path),
+            monitor,
+        });
+    }
+}
+
+fn reload_if_changed(
+    live_reload: &Rc<RefCell<LiveReload>>,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+) {
+    let now = Instant::now();
+    let due: Vec<PathBuf> = live_reload
+        .borrow_mut()
+        .files
+        .iter_mut()
+        .filter_map(|file| {
+            if file.monitor.is_none() {
+                file.watch.poll(now);
+            }
+            file.watch
+                .take_due(now)
+                .then(|| file.watch.path().to_path_buf())
+        })
+        .collect();
+
+    for path in due {
+        // Editors often replace files non-atomically; keep the last good
+        // render when the file is briefly missing or unreadable.
+        let Ok(bytes) = fs::read(&path) else {
+            continue;
+        };
+        let jobs = state.borrow_mut().reload_path(&path, &bytes);
+        render_in_background(jobs, ui, state, renderer);
+    }
+}
+
+/// In-document search backed by WebKit's find controller of the active tab.
+#[derive(Clone)]
+struct FindBar {
+    bar: gtk::SearchBar,
+    entry: gtk::SearchEntry,
+    match_case: gtk::CheckButton,
+    status_label: gtk::Label,
+    status: Rc<RefCell<FindStatus>>,
+    controller: Rc<RefCell<Option<webkit2gtk::FindController>>>,
+}
+
+impl FindBar {
+    fn new() -> Self {
+        let entry = gtk::SearchEntry::new();
+        entry.set_width_chars(32);
+        let match_case = gtk::CheckButton::with_label("Match case");
+        let status_label = gtk::Label::new(None);
+        let previous = gtk::Button::from_icon_name(Some("go-up-symbolic"), gtk::IconSize::Button);
+        previous.set_tooltip_text(Some("Previous match (Shift+F3)"));
+        let next = gtk::Button::from_icon_name(Some("go-down-symbolic"), gtk::IconSize::Button);
+        next.set_tooltip_text(Some("Next match (F3)"));
+
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        row.pack_start(&entry, false, false, 0);
+        row.pack_start(&previous, false, false, 0);
+        row.pack_start(&next, false, false, 0);
+        row.pack_start(&match_case, false, false, 0);
+        row.pack_start(&status_label, false, false, 0);
+
+        let bar = gtk::SearchBar::new();
+        bar.set_show_close_button(true);
+        bar.add(&row);
+        bar.connect_entry(&entry);
+
+        let find_bar = Self {
+            bar,
+            entry,
+            match_case,
+            status_label,
+            status: Rc::new(RefCell::new(FindStatus::default())),
+            controller: Rc::new(RefCell::new(None)),
+        };
+
+        let this = find_bar.clone();
+        find_bar
+            .entry
+            .connect_search_changed(move |_| this.search());
+        let this = find_bar.clone();
+        find_bar.match_case.connect_toggled(move |_| this.search());
+        let this = find_bar.clone();
+        find_bar.entry.connect_activate(move |_| this.next());
+        let this = find_bar.clone();
+        next.connect_clicked(move |_| this.next());
+        let this = find_bar.clone();
+        previous.connect_clicked(move |_| this.previous());
+        let this = find_bar.clone();
+        find_bar.bar.connect_search_mode_enabled_notify(move |bar| {
+            if !bar.is_search_mode() {
+                if let Some(controller) = this.controller() {
+                    controller.search_finish();
+                }
+                this.status_label.set_text("");
+            }
+        });
+
+        find_bar
+    }
+
+    /// Listens for results from a new tab's webview.
+    fn attach(&self, webview: &WebView) {
+        let Some(controller) = webview.find_controller() else {
+            return;
+        };
+        let this = self.clone();
+        controller.connect_counted_matches(move |controller, count| {
+            if this.is_target(controller) {
+                this.set_status(FindStatus::with_total(count));
+            }
+        });
+        let this = self.clone();
+        controller.connect_failed_to_find_text(move |controller| {
+            if this.is_target(controller) {
+                this.set_status(FindStatus::default());
+            }
+        });
+    }
+
+    /// Moves the search to `webview`, repeating the current query there.
+    fn retarget(&self, webview: Option<&WebView>) {
+        let controller = webview.and_then(|webview| webview.find_controller());
+        if *self.controller.borrow() == controller {
+            return;
+        }
+        if let Some(previous) = self.controller.replace(controller) {
+            previous.search_finish();
+        }
+        if self.bar.is_search_mode() {
+            self.search();
+        }
+    }
+
+    fn controller(&self) -> Option<webkit2gtk::FindController> {
+        self.controller.borrow().clone()
+    }
+
+    fn is_target(&self, controller: &webkit2gtk::FindController) -> bool {
+        self.controller.borrow().as_ref() == Some(controller)
+    }
+
+    fn open(&self) {
+        self.bar.set_search_mode(true);
+        self.entry.grab_focus();
+    }
+
+    fn options(&self) -> u32 {
+        let mut options = FindOptions::WRAP_AROUND;
+        if !self.match_case.is_active() {
+            options |= FindOptions::CASE_INSENSITIVE;
+        }
+        options.bits()
+    }
+
+    fn search(&self) {
+        let text = self.entry.text();
+        let Some(controller) = self.controller() else {
+            self.set_status(FindStatus::default());
+            return;
+        };
+        if text.is_empty() {
+            controller.search_finish();
+            self.status_label.set_text("");
+            return;
+        }
+        controller.count_matches(&text, self.options(), FIND_MAX_MATCHES);
+        controller.search(&text, self.options(), FIND_MAX_MATCHES);
+    }
+
+    fn next(&self) {
+        if self.entry.text().is_empty() {
+            self.open();
+            return;
+        }
+        let Some(controller) = self.controller() else {
+            return;
+        };
+        controller.search_next();
+        let mut status = self.status.borrow_mut();
+        status.next();
+        self.status_label.set_text(&status.label());
+    }
+
+    fn previous(&self) {
+        if self.entry.text().is_empty() {
+            self.open();
+            return;
+        }
+        let Some(controller) = self.controller() else {
+            return;
+        };
+        controller.search_previous();
+        let mut status = self.status.borrow_mut();
+        status.previous();
+        self.status_label.set_text(&status.label());
+    }
+
+    fn set_status(&self, status: FindStatus) {
+        self.status_label.set_text(&status.label());
+        *self.status.borrow_mut() = status;
+    }
+}
+
+/// Adds a notebook page for the document just pushed onto the state.
+fn add_tab(
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+    title: &str,
+) -> WebView {
+    let webview = new_webview();
+    ui.find_bar.attach(&webview);
+
+    let label = gtk::Label::new(Some(title));
+    let close = gtk::Button::from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+    close.set_relief(gtk::ReliefStyle::None);
+    close.set_tooltip_text(Some("Close tab (Ctrl+W)"));
+    let tab = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    tab.pack_start(&label, false, false, 0);
+    tab.pack_start(&close, false, false, 0);
+    tab.set_tooltip_text(Some(title));
+    tab.show_all();
+
+    webview.show();
+    ui.syncing.set(true);
+    ui.notebook.append_page(&webview, Some(&tab));
+    ui.syncing.set(false);
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        webview.connect_decide_policy(move |webview, decision, decision_type| {
+            let Some(target) = intercepted_navigation(webview, decision, decision_type) else {
+                return false;
+            };
+            decision.ignore();
+            // Replacing the page or running a dialog from inside its own
+            // policy decision is not safe; act once WebKit is done with it.
+            let ui = ui.clone();
+            let state = state.clone();
+            let renderer = renderer.clone();
+            let live_reload = live_reload.clone();
+            let page = webview.clone();
+            glib::idle_add_local_once(move || match target {
+                LinkTarget::Markdown(link) => {
+                    if let Some(index) = ui.notebook.page_num(&page) {
+                        state.borrow_mut().select(index as usize);
+                        follow_link(link, &ui, &state, &renderer, &live_reload);
+                    }
+                }
+                LinkTarget::External(url) => {
+                    let confirm = state.borrow().confirm_external_links;
+                    open_external(&ui.window, &url, confirm);
+                }
+                LinkTarget::Blocked(url) => report_blocked_link(&ui.window, &url),
+                LinkTarget::SameDocument => {}
+            });
+            true
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        webview.connect_load_changed(move |webview, event| {
+            if event != LoadEvent::Finished {
+                return;
+            }
+            let Some(index) = ui.notebook.page_num(webview) else {
+                return;
+            };
+            // WebKit has scrolled to the fragment by now; later reloads keep
+            // the reader's place instead of jumping back to it.
+            let offset = state
+                .borrow_mut()
+                .documents
+                .get_mut(index as usize)
+                .and_then(|document| {
+                    document.fragment = None;
+                    document.scroll_position.take()
+                });
+            if let Some(offset) = offset {
+                webview.run_javascript(
+                    &core::scroll_to_offset_script(offset),
+                    gio::Cancellable::NONE,
+                    |_| {},
+                );
+            }
+        });
+    }
+
+    let page = webview.downgrade();
+    let ui = ui.clone();
+    let state = state.clone();
+    let live_reload = live_reload.clone();
+    close.connect_clicked(move |_| {
+        if let Some(page) = page.upgrade()
+            && let Some(index) = ui.notebook.page_num(&page)
+        {
+            state.borrow_mut().select(index as usize);
+            close_active_tab(&ui, &state, &live_reload);
+        }
+    });
+
+    webview
+}
+
+fn set_tab_title(ui: &Ui, webview: &WebView, title: &str) {
+    let Some(tab) = ui.notebook.tab_label(webview) else {
+        return;
+    };
+    tab.set_tooltip_text(Some(title));
+    if let Some(label) = tab
+        .downcast_ref::<gtk::Box>()
+        .and_then(|tab| tab.children().into_iter().next())
+        .and_downcast::<gtk::Label>()
+    {
+        label.set_text(title);
+    }
+}
+
+/// Loads the document at `index` into its tab, creating the tab if needed,
+/// and makes it the visible one.
+fn present_document(
+    index: usize,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    let (title, theme) = {
+        let s = state.borrow();
+        (s.documents[index].title.clone(), s.theme.clone())
+    };
+    let webview = match tab_webview(ui, index) {
+        Some(webview) => {
+            set_tab_title(ui, &webview, &title);
+            webview
+        }
+        None => add_tab(ui, state, renderer, live_reload, &title),
+    };
+    load_document(&webview, state.borrow().documents.get(index), &theme);
+    show_active_tab(ui, state);
+    sync_watches(live_reload, &state.borrow().watched_paths());
+    rebuild_recent_menu(ui, state, renderer, live_reload);
+}
+
+/// Opens the file at `path` in a new tab once a worker thread has read and
+/// rendered it, asking first when it is over the size limit. The loading
+/// bar shows meanwhile.
+fn open_path(
+    path: &Path,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    open_path_at(
+        path,
+        LoadOptions::default(),
+        ui,
+        state,
+        renderer,
+        live_reload,
+    );
+}
+
+/// Like [`open_path`], showing the tab as `options` say.
+fn open_path_at(
+    path: &Path,
+    options: LoadOptions,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    let limit_mb = state.borrow().max_file_size_mb;
+    if let Some(size) = core::large_text_file(path, limit_mb)
+        && !confirm_large_file(&ui.window, path, size, limit_mb)
+    {
+        return;
+    }
+    let load = state.borrow_mut().start_load(path, options);
+    run_load(load, ui, state, renderer, live_reload);
+}
+
+/// Reads and renders `load` on a worker thread with the loading bar shown,
+/// then presents the tabs it opens or replaces.
+fn run_load(
+    load: PendingLoad,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    let theme = state.borrow().theme.clone();
+    refresh_loading(ui, &state.borrow());
+    refresh_history_items(ui, &state.borrow());
+
+    let worker_renderer = renderer.clone();
+    let ui = ui.clone();
+    let state = state.clone();
+    let renderer = renderer.clone();
+    let live_reload = live_reload.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let id = load.id;
+        let document = gio::spawn_blocking(move || load.run(&worker_renderer, &theme))
+            .await
+            .ok()
+            .flatten();
+        let opened = state.borrow_mut().finish_load(id, document);
+        for index in opened {
+            present_document(index, &ui, &state, &renderer, &live_reload);
+        }
+        refresh_loading(&ui, &state.borrow());
+        refresh_history_items(&ui, &state.borrow());
+    });
+}
+
+fn refresh_loading(ui: &Ui, state: &AppState) {
+    match state.loading_message() {
+        Some(message) => {
+            ui.loading_label.set_text(&message);
+            ui.loading_spinner.start();
+            ui.loading_bar.show();
+        }
+        None => {
+            ui.loading_spinner.stop();
+            ui.loading_bar.hide();
+        }
+    }
+}
+
+/// Opens Markdown that does not come from a file in a new tab, rendered on
+/// a worker thread.
+fn open_text(
+    source: DocumentSource,
+    markdown: String,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    let (index, job) = state
+        .borrow_mut()
+        .open_text(source, markdown, renderer.remote_content);
+    present_document(index, ui, state, renderer, live_reload);
+    render_in_background(job.into_iter().collect(), ui, state, renderer);
+}
+
+/// Opens a linked document in the active tab, recording it in the history,
+/// once a worker thread has read and rendered it.
+fn follow_link(
+    link: MarkdownLink,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    let limit_mb = state.borrow().max_file_size_mb;
+    if let Some(size) = core::large_text_file(&link.path, limit_mb)
+        && !confirm_large_file(&ui.window, &link.path, size, limit_mb)
+    {
+        return;
+    }
+    let load = state.borrow_mut().follow_link(link);
+    run_load(load, ui, state, renderer, live_reload);
+}
+
+fn go_in_history(
+    forward: bool,
+    ui: &Ui,
+    state: &Rc<RefCell<AppState>>,
+    renderer: &Arc<Renderer>,
+    live_reload: &Rc<RefCell<LiveReload>>,
+) {
+    let load = {
+        let mut s = state.borrow_mut();
+        if forward {
+            s.go_forward()
+        } else {
+            s.go_back()
+        }
+    };
+    if let Some(load) = load {
+        run_load(load, ui, state, renderer, live_reload);
+    }
+}
+
+fn close_active_tab(ui: &Ui, state: &Rc<RefCell<AppState>>, live_reload: &Rc<RefCell<LiveReload>>) {
+    let closed = {
+        let mut s = state.borrow_mut();
+        let index = s.active;
+        s.close_active().map(|_| index)
+    };
+    let Some(index) = closed else {
+        return;
+    };
+    ui.syncing.set(true);
+    ui.notebook.remove_page(Some(index as u32));
+    ui.syncing.set(false);
+    show_active_tab(ui, state);
+    sync_watches(live_reload, &state.borrow().watched_paths());
+}
+
+/// Saves the session, with the scroll offset of every tab, then leaves the
+/// main loop. The offsets come back asynchronously, one per tab.
+fn save_session_and_quit(ui: &Ui, state: &Rc<RefCell<AppState>>) {
+    let finish = {
+        let state = state.clone();
+        move || {
+            if let Err(err) = session::save_session(&Session::capture(&state.borrow())) {
+                eprintln!("failed to save the session: {err}");
+            }
+            gtk::main_quit();
+        }
+    };
+    let tabs: Vec<(usize, WebView)> = (0..state.borrow().documents.len())
+        .filter_map(|index| tab_webview(ui, index).map(|webview| (index, webview)))
+        .collect();
+    if tabs.is_empty() {
+        finish();
+        return;
+    }
+    let pending = Rc::new(Cell::new(tabs.len()));
+    let finish = Rc::new(finish);
+    for (index, webview) in tabs {
+        let state = state.clone();
+        let pending = pending.clone();
+        let finish = finish.clone();
+        webview.run_javascript("window.scrollY", gio::Cancellable::NONE, move |result| {
+            let offset = result
+                .ok()
+                .and_then(|result| result.js_value())
+                .and_then(|value| u32::try_from(value.to_int32()).ok());
+            if let Some(document) = state.borrow_mut().documents.get_mut(index) {
+                document.scroll_position = offset;
+            }
+            pending.set(pending.get() - 1);
+            if pending.get() == 0 {
+                finish();
+            }
+        });
+    }
+}
+
+/// GDK key and modifiers shown in a menu label for `chord`, or `None` when
+/// GDK has no key for it.
+fn gtk_accelerator(chord: &KeyChord) -> Option<(u32, gdk::ModifierType)> {
+    let key = match chord.key.as_str() {
+        // Shift turns Tab into ISO_Left_Tab.
+        "Tab" if chord.shift => gdk::keys::Key::from_name("ISO_Left_Tab"),
+        "Enter" => gdk::keys::Key::from_name("Return"),
+        "Space" => gdk::keys::Key::from_name("space"),
+        "PageUp" => gdk::keys::Key::from_name("Page_Up"),
+        "PageDown" => gdk::keys::Key::from_name("Page_Down"),
+        key if key.chars().count() == 1 => {
+            gdk::keys::Key::from_unicode(key.chars().next().unwrap_or_default())
+        }
+        key => gdk::keys::Key::from_name(key),
+    };
+    if *key == 0 || key == gdk::keys::constants::VoidSymbol {
+        return None;
+    }
+    let mut modifiers = gdk::ModifierType::empty();
+    modifiers.set(gdk::ModifierType::CONTROL_MASK, chord.ctrl);
+    modifiers.set(gdk::ModifierType::MOD1_MASK, chord.alt);
+    modifiers.set(gdk::ModifierType::SHIFT_MASK, chord.shift);
+    Some((*key, modifiers))
+}
+
+/// The chord of a key press, or `None` for keys the keymap cannot bind,
+/// such as a modifier on its own.
+fn key_chord(event: &gdk::EventKey) -> Option<KeyChord> {
+    let state = event.state();
+    let keyval = event.keyval();
+    let key = match keyval.to_unicode() {
+        Some(c) if !c.is_control() => c.to_string(),
+        _ => match keyval.name()?.as_str() {
+            "ISO_Left_Tab" => "Tab".to_string(),
+            "Return" | "KP_Enter" => "Enter".to_string(),
+            "BackSpace" => "Backspace".to_string(),
+            "Page_Up" => "PageUp".to_string(),
+            "Page_Down" => "PageDown".to_string(),
+            name => name.to_string(),
+        },
+    };
+    KeyChord::from_key(
+        state.contains(gdk::ModifierType::CONTROL_MASK),
+        state.contains(gdk::ModifierType::MOD1_MASK),
+        state.contains(gdk::ModifierType::SHIFT_MASK),
+        &key,
+    )
+}
+
+/// Carries out a command of the keymap.
+fn run_command(ui: &Ui, menu_commands: &[(AppEvent, gtk::MenuItem)], event: AppEvent) {
+    match event {
+        AppEvent::SelectTab(index) | AppEvent::CloseTab(index) => {
+            let Ok(page) = u32::try_from(index) else {
+                return;
+            };
+            if page >= ui.notebook.n_pages() {
+                return;
+            }
+            ui.notebook.set_current_page(Some(page));
+            if event == AppEvent::CloseTab(index) {
+                run_command(ui, menu_commands, AppEvent::Close);
+            }
+        }
+        AppEvent::SelectTheme(index) => {
+            if let Some(item) = ui
+                .theme_menu
+                .children()
+                .get(index)
+                .and_then(|item| item.downcast_ref::<gtk::RadioMenuItem>())
             {
-                let mut s = state.borrow_mut();
-                apply_open_error(&mut s, &err.to_string());
-                refresh_view(webview, &s);
+                item.set_active(true);
+            }
+        }
+        AppEvent::OpenRecent(index) => {
+            if let Some(item) = ui.recent_menu.children().get(index) {
+                item.activate();
+            }
+        }
+        AppEvent::ReopenWithEncoding(index) => {
+            if let Some(item) = ui.encoding_menu.children().get(index) {
+                item.activate();
+            }
+        }
+        AppEvent::AllowRemote => ui.remote_bar.response(gtk::ResponseType::Accept),
+        AppEvent::CancelLoad => ui.loading_bar.response(gtk::ResponseType::Cancel),
+        AppEvent::Navigate(navigation) => {
+            let webview = active_webview(ui).unwrap_or_else(|| ui.placeholder.clone());
+            webview.run_javascript(
+                &core::navigation_script(navigation),
+                gio::Cancellable::NONE,
+                |_| {},
+            );
+        }
+        AppEvent::Link(_) => {}
+        event => {
+            if let Some((_, item)) = menu_commands.iter().find(|(known, _)| *known == event) {
+                item.activate();
             }
-            window.set_title(core::APP_TITLE);
         }
     }
 }
 
-fn open_file_dialog(window: &gtk::Window) -> Option<PathBuf> {
+fn open_file_dialog(window: &gtk::Window, extensions: &[String]) -> Vec<PathBuf> {
     let dialog = gtk::FileChooserDialog::with_buttons(
         Some("Open Markdown File"),
         Some(window),
@@ -76,34 +1244,36 @@ fn open_file_dialog(window: &gtk::Window) -> Option<PathBuf> {
 
     let filter = gtk::FileFilter::new();
     filter.set_name(Some("Markdown files"));
-    filter.add_pattern("*.md");
-    filter.add_pattern("*.markdown");
-    filter.add_pattern("*.mdown");
-    filter.add_pattern("*.mkd");
-    filter.add_pattern("*.txt");
+    for extension in extensions {
+        filter.add_pattern(&format!("*.{extension}"));
+    }
     dialog.add_filter(filter);
+    dialog.set_select_multiple(true);
 
     let result = if dialog.run() == gtk::ResponseType::Accept {
-        dialog.filename()
+        dialog.filenames()
     } else {
-        None
+        Vec::new()
     };
 
     dialog.close();
     result
 }
 
-pub fn run() {
+pub fn run(args: ViewerArgs) {
     if let Err(err) = gtk::init() {
         eprintln!("failed to initialize GTK: {err}");
         return;
     }
 
-    let initial_path = env::args().nth(1).map(PathBuf::from);
+    let (config, mut load_errors) = config::load_config();
 
     let window = gtk::Window::new(gtk::WindowType::Toplevel);
     window.set_title(core::APP_TITLE);
-    window.set_default_size(980, 760);
+    window.set_default_size(
+        i32::try_from(config.window_width).unwrap_or(i32::MAX),
+        i32::try_from(config.window_height).unwrap_or(i32::MAX),
+    );
 
     let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
     let menu_bar = gtk::MenuBar::new();
@@ -111,197 +1281,522 @@ pub fn run() {
     let file_menu_item = gtk::MenuItem::with_label("File");
     let file_menu = gtk::Menu::new();
     let open_item = gtk::MenuItem::with_label("Open...");
-    let close_item = gtk::MenuItem::with_label("Close File");
+    let recent_menu_item = gtk::MenuItem::with_label("Open Recent");
+    let recent_menu = gtk::Menu::new();
+    recent_menu_item.set_submenu(Some(&recent_menu));
+    let close_item = gtk::MenuItem::with_label("Close Tab");
+    let reopen_item = gtk::MenuItem::with_label("Reopen Closed Tab");
     let quit_item = gtk::MenuItem::with_label("Quit");
     file_menu.append(&open_item);
+    file_menu.append(&recent_menu_item);
     file_menu.append(&close_item);
+    file_menu.append(&reopen_item);
     file_menu.append(&gtk::SeparatorMenuItem::new());
     file_menu.append(&quit_item);
     file_menu_item.set_submenu(Some(&file_menu));
 
+    let edit_menu_item = gtk::MenuItem::with_label("Edit");
+    let edit_menu = gtk::Menu::new();
+    let find_item = gtk::MenuItem::with_label("Find...");
+    let find_next_item = gtk::MenuItem::with_label("Find Next");
+    let find_previous_item = gtk::MenuItem::with_label("Find Previous");
+    edit_menu.append(&find_item);
+    edit_menu.append(&find_next_item);
+    edit_menu.append(&find_previous_item);
+    edit_menu_item.set_submenu(Some(&edit_menu));
+
+    let go_menu_item = gtk::MenuItem::with_label("Go");
+    let go_menu = gtk::Menu::new();
+    let back_item = gtk::MenuItem::with_label("Back");
+    let forward_item = gtk::MenuItem::with_label("Forward");
+    go_menu.append(&back_item);
+    go_menu.append(&forward_item);
+    go_menu_item.set_submenu(Some(&go_menu));
+
     let view_menu_item = gtk::MenuItem::with_label("View");
     let view_menu = gtk::Menu::new();
     let toggle_theme_item = gtk::MenuItem::with_label("Toggle Light/Dark");
+    let theme_menu_item = gtk::MenuItem::with_label("Theme");
+    let theme_menu = gtk::Menu::new();
+    theme_menu_item.set_submenu(Some(&theme_menu));
+    let encoding_item = gtk::MenuItem::with_label("Reopen with Encoding");
+    let encoding_menu = gtk::Menu::new();
+    encoding_item.set_submenu(Some(&encoding_menu));
+    let outline_item = gtk::CheckMenuItem::with_label("Show Outline");
+    outline_item.set_active(true);
+    let next_tab_item = gtk::MenuItem::with_label("Next Tab");
+    let previous_tab_item = gtk::MenuItem::with_label("Previous Tab");
     view_menu.append(&toggle_theme_item);
+    view_menu.append(&theme_menu_item);
+    view_menu.append(&encoding_item);
+    view_menu.append(&outline_item);
+    view_menu.append(&gtk::SeparatorMenuItem::new());
+    view_menu.append(&next_tab_item);
+    view_menu.append(&previous_tab_item);
     view_menu_item.set_submenu(Some(&view_menu));
 
     menu_bar.append(&file_menu_item);
+    menu_bar.append(&edit_menu_item);
     menu_bar.append(&view_menu_item);
+    menu_bar.append(&go_menu_item);
 
-    let accel_group = gtk::AccelGroup::new();
-    window.add_accel_group(&accel_group);
-    open_item.add_accelerator(
-        "activate",
-        &accel_group,
-        *gdk::keys::constants::O,
-        gdk::ModifierType::CONTROL_MASK,
-        gtk::AccelFlags::VISIBLE,
-    );
-    close_item.add_accelerator(
-        "activate",
-        &accel_group,
-        *gdk::keys::constants::W,
-        gdk::ModifierType::CONTROL_MASK,
-        gtk::AccelFlags::VISIBLE,
-    );
-    toggle_theme_item.add_accelerator(
-        "activate",
-        &accel_group,
-        *gdk::keys::constants::D,
-        gdk::ModifierType::CONTROL_MASK,
-        gtk::AccelFlags::VISIBLE,
-    );
-    quit_item.add_accelerator(
-        "activate",
-        &accel_group,
-        *gdk::keys::constants::Q,
-        gdk::ModifierType::CONTROL_MASK,
-        gtk::AccelFlags::VISIBLE,
-    );
+    // Key bindings activate these items, so keys and menus share one path.
+    let menu_commands: Rc<Vec<(AppEvent, gtk::MenuItem)>> = Rc::new(vec![
+        (AppEvent::Open, open_item.clone()),
+        (AppEvent::Close, close_item.clone()),
+        (AppEvent::ReopenClosed, reopen_item.clone()),
+        (AppEvent::Quit, quit_item.clone()),
+        (AppEvent::Find, find_item.clone()),
+        (AppEvent::FindNext, find_next_item.clone()),
+        (AppEvent::FindPrevious, find_previous_item.clone()),
+        (AppEvent::ToggleTheme, toggle_theme_item.clone()),
+        (AppEvent::ToggleOutline, outline_item.clone().upcast()),
+        (AppEvent::NextTab, next_tab_item.clone()),
+        (AppEvent::PreviousTab, previous_tab_item.clone()),
+        (AppEvent::Back, back_item.clone()),
+        (AppEvent::Forward, forward_item.clone()),
+    ]);
+    for (event, item) in menu_commands.iter() {
+        if let Some((key, modifiers)) = config.keymap.shortcut(event).and_then(gtk_accelerator)
+            && let Some(label) = item
+                .child()
+                .and_then(|child| child.downcast::<gtk::AccelLabel>().ok())
+        {
+            label.set_accel(key, modifiers);
+        }
+    }
 
-    let scroller = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
-    scroller.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    let placeholder = new_webview();
+    let notebook = gtk::Notebook::new();
+    notebook.set_scrollable(true);
+    notebook.set_show_border(false);
 
-    let webview = WebView::new();
-    if let Some(settings) = WebViewExt::settings(&webview) {
-        settings.set_enable_javascript(false);
-    }
-    scroller.add(&webview);
+    let content = gtk::Stack::new();
+    content.add_named(&placeholder, EMPTY_PAGE);
+    content.add_named(&notebook, TABS_PAGE);
+
+    let remote_label = gtk::Label::new(None);
+    let remote_bar = gtk::InfoBar::new();
+    remote_bar.set_message_type(gtk::MessageType::Info);
+    remote_bar.content_area().add(&remote_label);
+    remote_bar.add_button("Load Remote Images", gtk::ResponseType::Accept);
+    remote_bar.set_no_show_all(true);
+    remote_label.show();
+
+    let encoding_label = gtk::Label::new(None);
+    let encoding_bar = gtk::InfoBar::new();
+    encoding_bar.set_message_type(gtk::MessageType::Warning);
+    encoding_bar.content_area().add(&encoding_label);
+    encoding_bar.set_no_show_all(true);
+    encoding_label.show();
+
+    let loading_spinner = gtk::Spinner::new();
+    let loading_label = gtk::Label::new(None);
+    let loading_bar = gtk::InfoBar::new();
+    loading_bar.set_message_type(gtk::MessageType::Info);
+    loading_bar.content_area().add(&loading_spinner);
+    loading_bar.content_area().add(&loading_label);
+    loading_bar.add_button("Cancel", gtk::ResponseType::Cancel);
+    loading_bar.set_no_show_all(true);
+    loading_spinner.show();
+    loading_label.show();
+
+    let ui = Ui {
+        window: window.clone(),
+        content: content.clone(),
+        notebook: notebook.clone(),
+        placeholder: placeholder.clone(),
+        outline: gtk::TreeStore::new(&[String::static_type(), String::static_type()]),
+        find_bar: FindBar::new(),
+        back_item: back_item.clone(),
+        forward_item: forward_item.clone(),
+        remote_bar: remote_bar.clone(),
+        remote_label,
+        encoding_bar: encoding_bar.clone(),
+        encoding_label,
+        loading_bar: loading_bar.clone(),
+        loading_label,
+        loading_spinner,
+        encoding_item,
+        encoding_menu: encoding_menu.clone(),
+        theme_menu,
+        recent_menu,
+        syncing: Rc::new(Cell::new(false)),
+    };
+
+    let outline_scroller =
+        gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    outline_scroller.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    outline_scroller.set_size_request(220, -1);
+    outline_scroller.add(&build_outline_view(&ui));
+
+    let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+    paned.pack1(&outline_scroller, false, false);
+    paned.pack2(&content, true, false);
 
     vbox.pack_start(&menu_bar, false, false, 0);
-    vbox.pack_start(&scroller, true, true, 0);
+    vbox.pack_start(&ui.find_bar.bar, false, false, 0);
+    vbox.pack_start(&remote_bar, false, false, 0);
+    vbox.pack_start(&encoding_bar, false, false, 0);
+    vbox.pack_start(&loading_bar, false, false, 0);
+    vbox.pack_start(&paned, true, true, 0);
     window.add(&vbox);
 
-    let state = Rc::new(RefCell::new(AppState {
-        theme: core::detect_theme(),
-        source_markdown: None,
-        rendered_html: None,
-    }));
+    let state = Rc::new(RefCell::new(AppState::new(core::detect_theme())));
+    state.borrow_mut().max_file_size_mb = config.max_file_size_mb;
 
+    let (renderer, renderer_errors) = Renderer::with_config(&config);
+    load_errors.extend(renderer_errors);
+    let renderer = Arc::new(renderer);
+    let live_reload = Rc::new(RefCell::new(LiveReload::default()));
+    load_errors.extend(state.borrow_mut().load_themes(&config, &renderer));
+    // The theme is settled before any file loads, so nothing renders twice.
+    let mut startup_files =
+        session::load_session().restore(&mut state.borrow_mut(), &config, args.targets.is_empty());
+    if let Some(name) = &args.theme
+        && !state.borrow_mut().select_theme_by_name(name)
     {
-        let s = state.borrow();
-        refresh_view(&webview, &s);
+        eprintln!("dustrown: unknown theme '{name}'");
     }
+    build_theme_menu(&ui, &state, &renderer);
+
+    load_document(&placeholder, None, &state.borrow().theme);
 
-    if let Some(path) = initial_path {
-        open_path(&path, &webview, &window, &state);
+    let theme = state.borrow().theme.clone();
+    if let Some(document) = cli::stdin_document(&args.targets, &renderer, &theme) {
+        let index = state.borrow_mut().open_document(document);
+        present_document(index, &ui, &state, &renderer, &live_reload);
     }
+    startup_files.extend(cli::target_files(&args.targets, &config.file_extensions));
+    rebuild_recent_menu(&ui, &state, &renderer, &live_reload);
 
     {
-        let webview = webview.clone();
-        let window = window.clone();
+        let ui = ui.clone();
         let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        let file_extensions = config.file_extensions.clone();
         open_item.connect_activate(move |_| {
-            if let Some(path) = open_file_dialog(&window) {
-                open_path(&path, &webview, &window, &state);
+            for path in open_file_dialog(&ui.window, &file_extensions) {
+                open_path(&path, &ui, &state, &renderer, &live_reload);
             }
         });
     }
 
     {
-        let webview = webview.clone();
-        let window = window.clone();
+        let ui = ui.clone();
         let state = state.clone();
-        close_item.connect_activate(move |_| {
-            {
-                let mut s = state.borrow_mut();
-                clear_open_file(&mut s);
-                refresh_view(&webview, &s);
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        window.drag_dest_set(
+            gtk::DestDefaults::ALL,
+            &[
+                gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::OTHER_APP, 0),
+                gtk::TargetEntry::new("text/plain", gtk::TargetFlags::OTHER_APP, 0),
+            ],
+            gdk::DragAction::COPY,
+        );
+        window.connect_drag_data_received(move |_, _, _, _, data, _, _| {
+            let uris = data.uris();
+            if uris.is_empty() {
+                // Text dragged from an editor or a browser: show it as is.
+                if let Some(text) = data.text() {
+                    let source = DocumentSource::Memory(DROPPED_TEXT_TITLE.to_string());
+                    open_text(
+                        source,
+                        text.to_string(),
+                        &ui,
+                        &state,
+                        &renderer,
+                        &live_reload,
+                    );
+                }
+                return;
+            }
+            for uri in uris {
+                if let Some(path) = gio::File::for_uri(&uri).path() {
+                    open_path(&path, &ui, &state, &renderer, &live_reload);
+                }
+            }
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let live_reload = live_reload.clone();
+        close_item.connect_activate(move |_| close_active_tab(&ui, &state, &live_reload));
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        reopen_item.connect_activate(move |_| {
+            let path = state.borrow_mut().take_closed();
+            if let Some(path) = path {
+                open_path(&path, &ui, &state, &renderer, &live_reload);
+            }
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        notebook.connect_switch_page(move |_, _, index| {
+            if ui.syncing.get() {
+                return;
+            }
+            state.borrow_mut().select(index as usize);
+            refresh_chrome(&ui, &state.borrow());
+            ui.find_bar
+                .retarget(tab_webview(&ui, index as usize).as_ref());
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        next_tab_item.connect_activate(move |_| {
+            state.borrow_mut().select_next();
+            show_active_tab(&ui, &state);
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        previous_tab_item.connect_activate(move |_| {
+            state.borrow_mut().select_previous();
+            show_active_tab(&ui, &state);
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        loading_bar.connect_response(move |_, _| {
+            state.borrow_mut().cancel_loads();
+            refresh_loading(&ui, &state.borrow());
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        remote_bar.connect_response(move |_, response| {
+            if response != gtk::ResponseType::Accept {
+                return;
             }
-            window.set_title(core::APP_TITLE);
+            let job = state.borrow_mut().allow_remote_content();
+            render_in_background(job.into_iter().collect(), &ui, &state, &renderer);
+        });
+    }
+
+    for encoding in encoding::reopen_encodings() {
+        let item = gtk::MenuItem::with_label(encoding.name());
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        item.connect_activate(move |_| {
+            let job = state.borrow_mut().reopen_with_encoding(encoding);
+            render_in_background(job.into_iter().collect(), &ui, &state, &renderer);
         });
+        encoding_menu.append(&item);
     }
 
     {
-        let webview = webview.clone();
+        let ui = ui.clone();
         let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        back_item.connect_activate(move |_| {
+            go_in_history(false, &ui, &state, &renderer, &live_reload);
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        forward_item.connect_activate(move |_| {
+            go_in_history(true, &ui, &state, &renderer, &live_reload);
+        });
+    }
+
+    {
+        let find_bar = ui.find_bar.clone();
+        find_item.connect_activate(move |_| find_bar.open());
+    }
+    {
+        let find_bar = ui.find_bar.clone();
+        find_next_item.connect_activate(move |_| find_bar.next());
+    }
+    {
+        let find_bar = ui.find_bar.clone();
+        find_previous_item.connect_activate(move |_| find_bar.previous());
+    }
+
+    {
+        let outline_scroller = outline_scroller.clone();
+        outline_item.connect_toggled(move |item| {
+            outline_scroller.set_visible(item.is_active());
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
         toggle_theme_item.connect_activate(move |_| {
-            let mut s = state.borrow_mut();
-            toggle_theme(&mut s);
-            refresh_view(&webview, &s);
+            let jobs = {
+                let mut s = state.borrow_mut();
+                s.toggle_theme();
+                s.render_jobs()
+            };
+            apply_theme(&ui, &state);
+            render_in_background(jobs, &ui, &state, &renderer);
         });
     }
 
-    quit_item.connect_activate(|_| gtk::main_quit());
-    window.connect_delete_event(|_, _| {
-        gtk::main_quit();
-        gtk::glib::Propagation::Proceed
-    });
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        let renderer = renderer.clone();
+        let live_reload = live_reload.clone();
+        glib::timeout_add_local(core::RELOAD_TICK, move || {
+            reload_if_changed(&live_reload, &ui, &state, &renderer);
+            glib::ControlFlow::Continue
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let keymap = config.keymap.clone();
+        let pending = RefCell::new(PendingKeys::default());
+        window.connect_key_press_event(move |window, event| {
+            let Some(chord) = key_chord(event) else {
+                return glib::Propagation::Proceed;
+            };
+            // Leave plain typing in the find bar alone.
+            if !chord.ctrl
+                && !chord.alt
+                && chord.key.chars().count() == 1
+                && window
+                    .focused_widget()
+                    .is_some_and(|focus| focus.is::<gtk::Entry>())
+            {
+                return glib::Propagation::Proceed;
+            }
+            match keymap.press(&mut pending.borrow_mut(), chord, Instant::now()) {
+                KeyPress::Command(event) => {
+                    run_command(&ui, &menu_commands, event);
+                    glib::Propagation::Stop
+                }
+                KeyPress::Pending => glib::Propagation::Stop,
+                KeyPress::Unbound => glib::Propagation::Proceed,
+            }
+        });
+    }
+
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        quit_item.connect_activate(move |_| save_session_and_quit(&ui, &state));
+    }
+    {
+        let ui = ui.clone();
+        let state = state.clone();
+        // The tabs stay until their scroll offsets are saved.
+        window.connect_delete_event(move |_, _| {
+            save_session_and_quit(&ui, &state);
+            glib::Propagation::Stop
+        });
+    }
 
     window.show_all();
+    refresh_chrome(&ui, &state.borrow());
+    report_config_errors(&window, &load_errors);
+    // Opened once the window is up, so a size prompt has a parent and the
+    // loading bar can cancel them.
+    for (path, options) in startup_files {
+        open_path_at(&path, options, &ui, &state, &renderer, &live_reload);
+    }
     gtk::main();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::Theme;
-
-    fn state(theme: Theme, markdown: Option<&str>) -> AppState {
-        let source_markdown = markdown.map(str::to_string);
-        let rendered_html = markdown.map(|m| core::render_markdown(m, theme));
-        AppState {
-            theme,
-            source_markdown,
-            rendered_html,
-        }
-    }
 
     #[test]
-    fn apply_loaded_markdown_sets_source_and_rendered_html() {
-        let mut app_state = state(Theme::Light, None);
+    fn gtk_accelerator_maps_configured_chords_to_gdk_keys() {
+        let accelerator = |chord: &str| gtk_accelerator(&chord.parse().unwrap());
 
-        apply_loaded_markdown(&mut app_state, "# Title".to_string());
-
-        assert_eq!(app_state.source_markdown.as_deref(), Some("# Title"));
-        assert!(app_state
-            .rendered_html
-            .as_deref()
-            .is_some_and(|html| html.contains("<h1>Title</h1>")));
+        assert_eq!(
+            accelerator("Ctrl+O"),
+            Some((*gdk::keys::constants::o, gdk::ModifierType::CONTROL_MASK))
+        );
+        assert_eq!(
+            accelerator("Ctrl+Shift+Tab"),
+            Some((
+                *gdk::keys::constants::ISO_Left_Tab,
+                gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::SHIFT_MASK
+            ))
+        );
+        assert_eq!(
+            accelerator("Alt+PageDown"),
+            Some((
+                *gdk::keys::constants::Page_Down,
+                gdk::ModifierType::MOD1_MASK
+            ))
+        );
     }
 
     #[test]
-    fn apply_open_error_clears_source_and_sets_error_html() {
-        let mut app_state = state(Theme::Dark, Some("# Existing"));
-
-        apply_open_error(&mut app_state, "No such file or directory");
+    fn document_page_without_document_shows_welcome_page() {
+        let (page, base_uri) = document_page(None, &Theme::light());
 
-        assert!(app_state.source_markdown.is_none());
-        assert!(app_state
-            .rendered_html
-            .as_deref()
-            .is_some_and(|html| html.contains("Could not open file")));
+        assert!(page.contains("Ctrl+O"));
+        assert!(base_uri.is_none());
     }
 
     #[test]
-    fn clear_open_file_resets_markdown_state() {
-        let mut app_state = state(Theme::Light, Some("# Existing"));
+    fn document_page_resolves_against_document_directory() {
+        let document = Document::from_markdown(
+            Path::new("/tmp/docs/title.md"),
+            "# Title".to_string(),
+            &Renderer::new(),
+            &Theme::dark(),
+        );
 
-        clear_open_file(&mut app_state);
+        let (page, base_uri) = document_page(Some(&document), &Theme::dark());
 
-        assert!(app_state.source_markdown.is_none());
-        assert!(app_state.rendered_html.is_none());
+        assert_eq!(base_uri.as_deref(), Some("file:///tmp/docs/"));
+        assert!(page.contains("<h1 id=\"title\">Title</h1>"));
+        assert!(page.contains("<base href=\"file:///tmp/docs/\" />"));
     }
 
     #[test]
-    fn toggle_theme_re_renders_when_markdown_exists() {
-        let mut app_state = state(Theme::Light, Some("# Existing"));
-        let before_source = app_state.source_markdown.clone();
+    fn document_page_loads_under_fragment_of_followed_link() {
+        let mut document = Document::open_error(Path::new("/tmp/docs/design.md"), "x");
+        document.fragment = Some("usage".to_string());
 
-        toggle_theme(&mut app_state);
+        let (page, load_uri) = document_page(Some(&document), &Theme::light());
 
-        assert!(matches!(app_state.theme, Theme::Dark));
-        assert_eq!(app_state.source_markdown, before_source);
-        assert!(app_state.rendered_html.is_some());
+        assert_eq!(load_uri.as_deref(), Some("file:///tmp/docs/#usage"));
+        assert!(page.contains("<base href=\"file:///tmp/docs/\" />"));
     }
 
     #[test]
-    fn toggle_theme_only_switches_theme_when_no_markdown_loaded() {
-        let mut app_state = state(Theme::Light, None);
+    fn document_page_for_open_error_shows_error() {
+        let document = Document::open_error(Path::new("/tmp/missing.md"), "No such file");
 
-        toggle_theme(&mut app_state);
+        let (page, _) = document_page(Some(&document), &Theme::light());
 
-        assert!(matches!(app_state.theme, Theme::Dark));
-        assert!(app_state.rendered_html.is_none());
+        assert!(page.contains("Could not open file"));
     }
 }
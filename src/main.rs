@@ -1,23 +1,18 @@
-mod core;
-mod windows_shared;
+use std::{env, process::ExitCode};
 
-#[cfg(target_os = "linux")]
-mod linux;
+use dustrown::{cli, run_gui};
 
-#[cfg(target_os = "windows")]
-mod windows;
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "render") {
+        return cli::run_render(args.skip(1));
+    }
 
-#[cfg(target_os = "linux")]
-fn main() {
-    linux::run();
-}
-
-#[cfg(target_os = "windows")]
-fn main() {
-    windows::run();
-}
-
-#[cfg(not(any(target_os = "linux", target_os = "windows")))]
-fn main() {
-    eprintln!("dustrown GUI currently supports Linux and Windows runtimes.");
+    match cli::viewer_args(args) {
+        Ok(args) => {
+            run_gui(args);
+            ExitCode::SUCCESS
+        }
+        Err(code) => code,
+    }
 }
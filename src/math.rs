@@ -0,0 +1,841 @@
+/// Converts TeX math to MathML, which WebKit and WebView2 render natively,
+/// so formulas need no JavaScript. Covers the subset used in notes: scripts,
+/// fractions, roots, Greek letters and operators, `\left`/`\right`, fonts,
+/// accents and matrix-like environments. Unknown commands show up as
+/// `<merror>` instead of failing the page.
+pub fn latex_to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+        display,
+        variant: None,
+        depth: 0,
+        too_deep: false,
+    };
+    let rows = parser.parse_until(Stop::End);
+    let body = if parser.too_deep {
+        format!(
+            "<merror><mtext>{}</mtext></merror>",
+            html_escape::encode_text(tex.trim())
+        )
+    } else {
+        mrow(rows)
+    };
+    format!(
+        "<math display=\"{}\"><semantics>{body}\
+         <annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { "block" } else { "inline" },
+        html_escape::encode_text(tex.trim())
+    )
+}
+
+/// How deeply groups, arguments and environments may nest. Deeper input is
+/// shown as an error rather than risking a stack overflow.
+const MAX_DEPTH: usize = 256;
+
+/// Why `Parser::parse_row` returned.
+#[derive(Debug, PartialEq, Eq)]
+enum Stop {
+    End,
+    Group,
+    Right(String),
+    EndEnvironment,
+    Cell,
+    Row,
+}
+
+/// A parsed element; `limits` places scripts above/below in display mode.
+struct Atom {
+    html: String,
+    limits: bool,
+}
+
+impl Atom {
+    fn new(html: String) -> Self {
+        Self {
+            html,
+            limits: false,
+        }
+    }
+
+    fn with_limits(html: String) -> Self {
+        Self { html, limits: true }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+    /// `mathvariant` applied by `\mathbb` and friends to identifiers.
+    variant: Option<&'static str>,
+    /// Elements being parsed around the current one.
+    depth: usize,
+    /// Nesting went past `MAX_DEPTH`; the rest of the input was skipped.
+    too_deep: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads a command name after `\`: a run of letters, or one symbol.
+    fn read_command(&mut self) -> String {
+        self.pos += 1;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start && self.peek().is_some() {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn peek_command(&mut self) -> String {
+        let start = self.pos;
+        let name = self.read_command();
+        self.pos = start;
+        name
+    }
+
+    /// Raw text of a `{...}` argument (or a single character).
+    fn read_raw_group(&mut self) -> String {
+        self.skip_spaces();
+        if self.peek() != Some('{') {
+            let c = self.peek().map(String::from).unwrap_or_default();
+            self.pos += c.chars().count();
+            return c;
+        }
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                '\\' => self.pos += 1,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        let end = self.pos.min(self.chars.len());
+        self.pos = end + 1;
+        self.chars[start..end].iter().collect()
+    }
+
+    fn parse_until(&mut self, until: Stop) -> Vec<String> {
+        let mut items = Vec::new();
+        loop {
+            let (row, stop) = self.parse_row();
+            items.extend(row);
+            if stop == until || stop == Stop::End {
+                return items;
+            }
+        }
+    }
+
+    fn parse_row(&mut self) -> (Vec<String>, Stop) {
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            let Some(c) = self.peek() else {
+                return (items, Stop::End);
+            };
+            match c {
+                '}' => {
+                    self.pos += 1;
+                    return (items, Stop::Group);
+                }
+                '&' => {
+                    self.pos += 1;
+                    return (items, Stop::Cell);
+                }
+                '\\' => match self.peek_command().as_str() {
+                    "right" => {
+                        self.read_command();
+                        let delimiter = self.parse_delimiter();
+                        return (items, Stop::Right(delimiter));
+                    }
+                    "end" => {
+                        self.read_command();
+                        self.read_raw_group();
+                        return (items, Stop::EndEnvironment);
+                    }
+                    "\\" | "cr" => {
+                        self.read_command();
+                        return (items, Stop::Row);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+            let atom = if matches!(c, '^' | '_' | '\'') {
+                Atom::new("<mrow></mrow>".to_string())
+            } else {
+                self.parse_atom(false)
+            };
+            items.push(self.parse_scripts(atom));
+        }
+    }
+
+    fn parse_scripts(&mut self, base: Atom) -> String {
+        let mut sub = None;
+        let mut sup = Vec::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_atom(true).html);
+                }
+                Some('^') => {
+                    self.pos += 1;
+                    sup.push(self.parse_atom(true).html);
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    sup.push("<mo>′</mo>".to_string());
+                }
+                _ => break,
+            }
+        }
+
+        let sup = (!sup.is_empty()).then(|| mrow(sup));
+        let over = base.limits && self.display;
+        let base = base.html;
+        match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) if over => format!("<munder>{base}{sub}</munder>"),
+            (Some(sub), None) => format!("<msub>{base}{sub}</msub>"),
+            (None, Some(sup)) if over => format!("<mover>{base}{sup}</mover>"),
+            (None, Some(sup)) => format!("<msup>{base}{sup}</msup>"),
+            (Some(sub), Some(sup)) if over => {
+                format!("<munderover>{base}{sub}{sup}</munderover>")
+            }
+            (Some(sub), Some(sup)) => format!("<msubsup>{base}{sub}{sup}</msubsup>"),
+        }
+    }
+
+    /// Parses one element; script arguments take a single digit, as in TeX
+    /// (`x^23` is `x²3`). Every level of nesting passes through here.
+    fn parse_atom(&mut self, script: bool) -> Atom {
+        if self.depth == MAX_DEPTH {
+            self.too_deep = true;
+            self.pos = self.chars.len();
+            return Atom::new("<mrow></mrow>".to_string());
+        }
+        self.depth += 1;
+        let atom = self.parse_nested_atom(script);
+        self.depth -= 1;
+        atom
+    }
+
+    fn parse_nested_atom(&mut self, script: bool) -> Atom {
+        self.skip_spaces();
+        let Some(c) = self.peek() else {
+            return Atom::new("<mrow></mrow>".to_string());
+        };
+        if c == '{' {
+            self.pos += 1;
+            return Atom::new(mrow(self.parse_until(Stop::Group)));
+        }
+        if c == '\\' {
+            let name = self.read_command();
+            return self.parse_command(&name);
+        }
+        self.pos += 1;
+        if c.is_ascii_digit() {
+            let mut number = c.to_string();
+            while !script
+                && let Some(next) = self.peek()
+                && (next.is_ascii_digit()
+                    || (next == '.'
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_some_and(char::is_ascii_digit)))
+            {
+                number.push(next);
+                self.pos += 1;
+            }
+            return Atom::new(self.token("mn", &number));
+        }
+        if c.is_alphabetic() {
+            return Atom::new(self.token("mi", &c.to_string()));
+        }
+        match c {
+            '~' => Atom::new(space("0.333em")),
+            _ => Atom::new(mo(&c.to_string())),
+        }
+    }
+
+    fn token(&self, tag: &str, text: &str) -> String {
+        let text = html_escape::encode_text(text);
+        match self.variant {
+            Some(variant) => format!("<{tag} mathvariant=\"{variant}\">{text}</{tag}>"),
+            None => format!("<{tag}>{text}</{tag}>"),
+        }
+    }
+
+    fn parse_delimiter(&mut self) -> String {
+        self.skip_spaces();
+        match self.peek() {
+            Some('\\') => {
+                let name = self.read_command();
+                delimiter(&name).unwrap_or_default().to_string()
+            }
+            Some('.') => {
+                self.pos += 1;
+                String::new()
+            }
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn parse_command(&mut self, name: &str) -> Atom {
+        match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_atom(false).html;
+                let denominator = self.parse_atom(false).html;
+                Atom::new(format!("<mfrac>{numerator}{denominator}</mfrac>"))
+            }
+            "binom" => {
+                let top = self.parse_atom(false).html;
+                let bottom = self.parse_atom(false).html;
+                Atom::new(format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{top}{bottom}</mfrac><mo>)</mo></mrow>"
+                ))
+            }
+            "sqrt" => {
+                self.skip_spaces();
+                let index = (self.peek() == Some('[')).then(|| {
+                    self.pos += 1;
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c != ']') {
+                        self.pos += 1;
+                    }
+                    let text: String = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    let mut inner = Parser {
+                        chars: text.chars().collect(),
+                        pos: 0,
+                        display: false,
+                        variant: self.variant,
+                        depth: self.depth,
+                        too_deep: false,
+                    };
+                    let index = mrow(inner.parse_until(Stop::End));
+                    if inner.too_deep {
+                        self.too_deep = true;
+                        self.pos = self.chars.len();
+                    }
+                    index
+                });
+                let radicand = self.parse_atom(false).html;
+                Atom::new(match index {
+                    Some(index) => format!("<mroot>{radicand}{index}</mroot>"),
+                    None => format!("<msqrt>{radicand}</msqrt>"),
+                })
+            }
+            "left" => {
+                let open = self.parse_delimiter();
+                let mut items = Vec::new();
+                let close = loop {
+                    let (row, stop) = self.parse_row();
+                    items.extend(row);
+                    match stop {
+                        Stop::Right(close) => break close,
+                        Stop::End => break String::new(),
+                        _ => {}
+                    }
+                };
+                Atom::new(format!(
+                    "<mrow>{}{}{}</mrow>",
+                    stretchy(&open),
+                    items.concat(),
+                    stretchy(&close)
+                ))
+            }
+            "begin" => {
+                let environment = self.read_raw_group();
+                Atom::new(self.parse_environment(environment.trim()))
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mbox" | "hbox" => {
+                let text = self.read_raw_group();
+                Atom::new(format!(
+                    "<mtext>{}</mtext>",
+                    html_escape::encode_text(&text)
+                ))
+            }
+            "operatorname" | "operatorname*" => {
+                let text = self.read_raw_group();
+                Atom::new(format!(
+                    "<mi>{}</mi>",
+                    html_escape::encode_text(text.trim())
+                ))
+            }
+            "mathbb" | "mathbf" | "boldsymbol" | "bm" | "mathcal" | "mathrm" | "mathit"
+            | "mathsf" | "mathtt" | "mathfrak" | "mathscr" => {
+                let variant = match name {
+                    "mathbb" => "double-struck",
+                    "mathbf" | "boldsymbol" | "bm" => "bold",
+                    "mathcal" | "mathscr" => "script",
+                    "mathrm" => "normal",
+                    "mathit" => "italic",
+                    "mathsf" => "sans-serif",
+                    "mathtt" => "monospace",
+                    _ => "fraktur",
+                };
+                let outer = self.variant.replace(variant);
+                let atom = self.parse_atom(false);
+                self.variant = outer;
+                atom
+            }
+            "overbrace" | "underbrace" => {
+                let base = self.parse_atom(false).html;
+                Atom::with_limits(if name == "overbrace" {
+                    format!("<mover>{base}<mo>⏞</mo></mover>")
+                } else {
+                    format!("<munder>{base}<mo>⏟</mo></munder>")
+                })
+            }
+            "underline" => {
+                let base = self.parse_atom(false).html;
+                Atom::new(format!(
+                    "<munder accentunder=\"true\">{base}<mo>_</mo></munder>"
+                ))
+            }
+            "bmod" => Atom::new("<mo>mod</mo>".to_string()),
+            "pmod" => {
+                let argument = self.parse_atom(false).html;
+                Atom::new(format!(
+                    "<mrow><mo>(</mo><mi>mod</mi>{}{argument}<mo>)</mo></mrow>",
+                    space("0.333em")
+                ))
+            }
+            _ => {
+                if let Some(accent) = accent(name) {
+                    let base = self.parse_atom(false).html;
+                    return Atom::new(format!(
+                        "<mover accent=\"true\">{base}<mo>{accent}</mo></mover>"
+                    ));
+                }
+                symbol(name, self.variant)
+            }
+        }
+    }
+
+    fn parse_environment(&mut self, environment: &str) -> String {
+        if environment == "array" {
+            // Column specification, e.g. `{cc|c}`; alignment is left default.
+            self.read_raw_group();
+        }
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        let mut cell = Vec::new();
+        loop {
+            let (row, stop) = self.parse_row();
+            cell.extend(row);
+            match stop {
+                Stop::Cell => cells.push(mrow(std::mem::take(&mut cell))),
+                Stop::Row => {
+                    cells.push(mrow(std::mem::take(&mut cell)));
+                    rows.push(std::mem::take(&mut cells));
+                }
+                Stop::EndEnvironment | Stop::End => {
+                    cells.push(mrow(std::mem::take(&mut cell)));
+                    rows.push(std::mem::take(&mut cells));
+                    break;
+                }
+                Stop::Group | Stop::Right(_) => {}
+            }
+        }
+        // A trailing `\\` leaves an empty last row.
+        if rows
+            .last()
+            .is_some_and(|row| row.len() == 1 && row[0] == "<mrow></mrow>")
+        {
+            rows.pop();
+        }
+
+        let column_align = match environment {
+            "cases" => " columnalign=\"left left\"",
+            "aligned" | "align" | "align*" | "split" | "alignat" | "alignat*" => {
+                " columnalign=\"right left right left\""
+            }
+            _ => "",
+        };
+        let mut table = format!("<mtable{column_align}>");
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str(&format!("<mtd>{cell}</mtd>"));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        let (open, close) = match environment {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => return table,
+        };
+        format!("<mrow>{}{table}{}</mrow>", stretchy(open), stretchy(close))
+    }
+}
+
+fn mrow(items: Vec<String>) -> String {
+    match <[String; 1]>::try_from(items) {
+        Ok([item]) => item,
+        Err(items) => format!("<mrow>{}</mrow>", items.concat()),
+    }
+}
+
+fn mo(text: &str) -> String {
+    format!("<mo>{}</mo>", html_escape::encode_text(text))
+}
+
+fn stretchy(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<mo stretchy=\"true\">{}</mo>",
+            html_escape::encode_text(delimiter)
+        )
+    }
+}
+
+fn space(width: &str) -> String {
+    format!("<mspace width=\"{width}\"></mspace>")
+}
+
+fn delimiter(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "{" | "lbrace" => "{",
+        "}" | "rbrace" => "}",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "|" | "Vert" | "lVert" | "rVert" => "‖",
+        "vert" | "lvert" | "rvert" => "|",
+        "backslash" => "\\",
+        _ => return None,
+    })
+}
+
+fn accent(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "hat" | "widehat" => "^",
+        "bar" | "overline" => "‾",
+        "vec" | "overrightarrow" => "→",
+        "overleftarrow" => "←",
+        "dot" => "˙",
+        "ddot" => "¨",
+        "tilde" | "widetilde" => "~",
+        "check" => "ˇ",
+        "breve" => "˘",
+        "acute" => "´",
+        "grave" => "`",
+        _ => return None,
+    })
+}
+
+fn symbol(name: &str, variant: Option<&'static str>) -> Atom {
+    let identifier = |text: &str| match variant {
+        Some(variant) => format!("<mi mathvariant=\"{variant}\">{text}</mi>"),
+        None => format!("<mi>{text}</mi>"),
+    };
+    let upright = |text: &str| format!("<mi mathvariant=\"normal\">{text}</mi>");
+
+    if let Some(letter) = greek(name) {
+        return Atom::new(if letter.chars().next().is_some_and(char::is_uppercase) {
+            upright(letter)
+        } else {
+            identifier(letter)
+        });
+    }
+    if let Some(delimiter) = delimiter(name) {
+        return Atom::new(mo(delimiter));
+    }
+    let html = match name {
+        // Big operators take limits above and below in display mode.
+        "sum" => return Atom::with_limits(mo("∑")),
+        "prod" => return Atom::with_limits(mo("∏")),
+        "coprod" => return Atom::with_limits(mo("∐")),
+        "bigcup" => return Atom::with_limits(mo("⋃")),
+        "bigcap" => return Atom::with_limits(mo("⋂")),
+        "bigoplus" => return Atom::with_limits(mo("⨁")),
+        "bigotimes" => return Atom::with_limits(mo("⨂")),
+        "bigvee" => return Atom::with_limits(mo("⋁")),
+        "bigwedge" => return Atom::with_limits(mo("⋀")),
+        "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => {
+            return Atom::with_limits(format!("<mi>{name}</mi>"));
+        }
+        "limsup" => return Atom::with_limits("<mi>lim sup</mi>".to_string()),
+        "liminf" => return Atom::with_limits("<mi>lim inf</mi>".to_string()),
+        "int" => mo("∫"),
+        "iint" => mo("∬"),
+        "iiint" => mo("∭"),
+        "oint" => mo("∮"),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "deg" | "dim" | "ker"
+        | "arg" | "hom" => format!("<mi>{name}</mi>"),
+        "infty" => identifier("∞"),
+        "partial" => identifier("∂"),
+        "nabla" => upright("∇"),
+        "emptyset" | "varnothing" => upright("∅"),
+        "ell" => identifier("ℓ"),
+        "hbar" => identifier("ℏ"),
+        "aleph" => upright("ℵ"),
+        "Re" => upright("ℜ"),
+        "Im" => upright("ℑ"),
+        "wp" => identifier("℘"),
+        "top" => upright("⊤"),
+        "bot" => upright("⊥"),
+        "angle" => upright("∠"),
+        "triangle" => upright("△"),
+        "prime" => mo("′"),
+        "degree" | "circ" => mo("∘"),
+        "times" => mo("×"),
+        "cdot" => mo("⋅"),
+        "pm" => mo("±"),
+        "mp" => mo("∓"),
+        "div" => mo("÷"),
+        "ast" => mo("∗"),
+        "star" => mo("⋆"),
+        "bullet" => mo("∙"),
+        "oplus" => mo("⊕"),
+        "otimes" => mo("⊗"),
+        "wedge" | "land" => mo("∧"),
+        "vee" | "lor" => mo("∨"),
+        "neg" | "lnot" => mo("¬"),
+        "cup" => mo("∪"),
+        "cap" => mo("∩"),
+        "setminus" => mo("∖"),
+        "leq" | "le" => mo("≤"),
+        "geq" | "ge" => mo("≥"),
+        "neq" | "ne" => mo("≠"),
+        "ll" => mo("≪"),
+        "gg" => mo("≫"),
+        "approx" => mo("≈"),
+        "equiv" => mo("≡"),
+        "sim" => mo("∼"),
+        "simeq" => mo("≃"),
+        "cong" => mo("≅"),
+        "propto" => mo("∝"),
+        "in" => mo("∈"),
+        "notin" => mo("∉"),
+        "ni" => mo("∋"),
+        "subset" => mo("⊂"),
+        "subseteq" => mo("⊆"),
+        "supset" => mo("⊃"),
+        "supseteq" => mo("⊇"),
+        "perp" => mo("⊥"),
+        "parallel" => mo("∥"),
+        "mid" => mo("∣"),
+        "forall" => mo("∀"),
+        "exists" => mo("∃"),
+        "to" | "rightarrow" => mo("→"),
+        "leftarrow" | "gets" => mo("←"),
+        "leftrightarrow" => mo("↔"),
+        "Rightarrow" => mo("⇒"),
+        "Leftarrow" => mo("⇐"),
+        "Leftrightarrow" | "iff" => mo("⇔"),
+        "implies" => mo("⟹"),
+        "mapsto" => mo("↦"),
+        "uparrow" => mo("↑"),
+        "downarrow" => mo("↓"),
+        "ldots" | "dots" => mo("…"),
+        "cdots" => mo("⋯"),
+        "vdots" => mo("⋮"),
+        "ddots" => mo("⋱"),
+        "colon" => mo(":"),
+        "%" | "$" | "#" | "&" | "_" => mo(name),
+        "," | "thinspace" => space("0.167em"),
+        ":" | ">" | "medspace" => space("0.222em"),
+        ";" | "thickspace" => space("0.278em"),
+        " " => space("0.333em"),
+        "!" => space("-0.167em"),
+        "quad" => space("1em"),
+        "qquad" => space("2em"),
+        "displaystyle" | "textstyle" | "limits" | "nolimits" | "big" | "Big" | "bigg" | "Bigg" => {
+            String::new()
+        }
+        _ => format!(
+            "<merror><mtext>\\{}</mtext></merror>",
+            html_escape::encode_text(name)
+        ),
+    };
+    Atom::new(html)
+}
+
+fn greek(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(tex: &str, display: bool) -> String {
+        let mathml = latex_to_mathml(tex, display);
+        let start = mathml.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+        mathml[start..end].to_string()
+    }
+
+    #[test]
+    fn wraps_output_in_math_with_tex_annotation() {
+        assert_eq!(
+            latex_to_mathml("a<b", false),
+            "<math display=\"inline\"><semantics><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>\
+             <annotation encoding=\"application/x-tex\">a&lt;b</annotation></semantics></math>"
+        );
+        assert!(latex_to_mathml("x", true).starts_with("<math display=\"block\">"));
+    }
+
+    #[test]
+    fn converts_scripts_fractions_and_roots() {
+        assert_eq!(
+            body("x^2 + y_{i,j}", false),
+            "<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo>\
+             <msub><mi>y</mi><mrow><mi>i</mi><mo>,</mo><mi>j</mi></mrow></msub></mrow>"
+        );
+        assert_eq!(
+            body("\\frac{1}{2}", false),
+            "<mfrac><mn>1</mn><mn>2</mn></mfrac>"
+        );
+        assert_eq!(
+            body("\\sqrt[3]{8}", false),
+            "<mroot><mn>8</mn><mn>3</mn></mroot>"
+        );
+        assert_eq!(body("f'", false), "<msup><mi>f</mi><mo>′</mo></msup>");
+    }
+
+    #[test]
+    fn big_operators_take_limits_only_in_display_mode() {
+        let tex = "\\sum_{i=1}^n i";
+        assert!(body(tex, true).starts_with("<mrow><munderover><mo>∑</mo>"));
+        assert!(body(tex, false).starts_with("<mrow><msubsup><mo>∑</mo>"));
+    }
+
+    #[test]
+    fn converts_symbols_fonts_delimiters_and_environments() {
+        assert_eq!(
+            body("\\alpha \\Omega \\leq \\mathbb{R}", false),
+            "<mrow><mi>α</mi><mi mathvariant=\"normal\">Ω</mi><mo>≤</mo>\
+             <mi mathvariant=\"double-struck\">R</mi></mrow>"
+        );
+        assert_eq!(
+            body("\\left( x \\right.", false),
+            "<mrow><mo stretchy=\"true\">(</mo><mi>x</mi></mrow>"
+        );
+        assert_eq!(
+            body("\\begin{pmatrix} 1 & 0 \\\\ 0 & 1 \\\\ \\end{pmatrix}", false),
+            "<mrow><mo stretchy=\"true\">(</mo><mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr>\
+             <mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable><mo stretchy=\"true\">)</mo></mrow>"
+        );
+        assert_eq!(
+            body("\\text{if } x", false),
+            "<mrow><mtext>if </mtext><mi>x</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn unknown_commands_become_errors_and_unbalanced_input_does_not_panic() {
+        assert_eq!(
+            body("\\foo", false),
+            "<merror><mtext>\\foo</mtext></merror>"
+        );
+        for tex in [
+            "{",
+            "}",
+            "x^",
+            "\\frac{1",
+            "\\sqrt[",
+            "\\left(",
+            "\\begin{matrix} a &",
+            "\\",
+        ] {
+            latex_to_mathml(tex, true);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_input_becomes_an_error() {
+        for tex in [
+            "\\frac{".repeat(20_000),
+            "\\sqrt{".repeat(20_000),
+            "\\sqrt[".repeat(20_000),
+            format!("{}x{}", "{".repeat(5_000), "}".repeat(5_000)),
+        ] {
+            let body = body(&tex, true);
+            assert!(body.starts_with("<merror><mtext>"), "{}", &tex[..12]);
+        }
+        let nested = format!("{}x{}", "{".repeat(100), "}".repeat(100));
+        assert!(body(&nested, false).contains("<mi>x</mi>"));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    core::{self, AppState, LoadOptions},
+};
+
+/// What the viewer remembers between runs: the recent files, plus the open
+/// files, theme and scroll positions to restore on the next launch. Kept in
+/// `session.toml` in the state directory (see [`load_session`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Recently opened files, newest first.
+    pub recent: Vec<PathBuf>,
+    pub theme: Option<String>,
+    /// Index in `documents` of the active tab.
+    pub active: usize,
+    #[serde(rename = "document")]
+    pub documents: Vec<SessionDocument>,
+}
+
+/// An open tab of the session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionDocument {
+    pub path: PathBuf,
+    /// Vertical scroll offset, in CSS pixels.
+    #[serde(default)]
+    pub scroll: u32,
+}
+
+impl Session {
+    /// The session to save for `state`. Tabs that failed to open are left
+    /// out.
+    pub fn capture(state: &AppState) -> Self {
+        let mut session = Self {
+            recent: state.recent.clone(),
+            theme: Some(state.theme.name.clone()),
+            active: 0,
+            documents: Vec::new(),
+        };
+        for (index, document) in state.documents.iter().enumerate() {
+            let Some(path) = document.source_path().filter(|_| document.is_loaded()) else {
+                continue;
+            };
+            if index == state.active {
+                session.active = session.documents.len();
+            }
+            session.documents.push(SessionDocument {
+                path: path.to_path_buf(),
+                scroll: document.scroll_position.unwrap_or(0),
+            });
+        }
+        session
+    }
+
+    /// Brings back the recent files and, when `config.restore_session` is
+    /// set, the theme (unless the config names one). Returns the open files
+    /// to load again, with their scroll positions, for the frontend to pass
+    /// to [`AppState::start_load`]; files that no longer exist are skipped.
+    /// With `reopen_documents` unset there are none, for when files to show
+    /// were given on the command line.
+    pub fn restore(
+        self,
+        state: &mut AppState,
+        config: &Config,
+        reopen_documents: bool,
+    ) -> Vec<(PathBuf, LoadOptions)> {
+        let mut reopen = Vec::new();
+        if config.restore_session {
+            if config.theme.is_none()
+                && let Some(name) = &self.theme
+            {
+                state.select_theme_by_name(name);
+            }
+            if reopen_documents {
+                reopen = self.documents_to_reopen();
+            }
+        }
+        state.recent = self.recent;
+        reopen
+    }
+
+    fn documents_to_reopen(&self) -> Vec<(PathBuf, LoadOptions)> {
+        let existing: Vec<(usize, &SessionDocument)> = self
+            .documents
+            .iter()
+            .enumerate()
+            .filter(|(_, saved)| saved.path.is_file())
+            .collect();
+        // A missing active file hands over to the tab before it.
+        let active = existing
+            .iter()
+            .rposition(|(index, _)| *index <= self.active)
+            .or(existing.len().checked_sub(1));
+        existing
+            .iter()
+            .enumerate()
+            .map(|(position, (_, saved))| {
+                let options = LoadOptions {
+                    scroll_position: (saved.scroll > 0).then_some(saved.scroll),
+                    select: Some(position) == active,
+                    // Keep the saved order of the recent files.
+                    remember: false,
+                    ..LoadOptions::default()
+                };
+                (saved.path.clone(), options)
+            })
+            .collect()
+    }
+}
+
+/// `session.toml` in the state directory.
+pub fn session_path() -> Option<PathBuf> {
+    core::state_dir().map(|dir| dir.join("session.toml"))
+}
+
+/// Reads the saved session. The file is the viewer's own, so a missing or
+/// unreadable one just starts a fresh session.
+pub fn load_session() -> Session {
+    session_path()
+        .map(|path| load_session_from(&path))
+        .unwrap_or_default()
+}
+
+fn load_session_from(path: &Path) -> Session {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| toml::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_session(session: &Session) -> io::Result<()> {
+    let Some(path) = session_path() else {
+        return Ok(());
+    };
+    save_session_to(session, &path)
+}
+
+fn save_session_to(session: &Session, path: &Path) -> io::Result<()> {
+    let text = toml::to_string(session).map_err(io::Error::other)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Document, Renderer};
+    use crate::test_support::TempDir;
+    use crate::theme::Theme;
+
+    fn loaded(path: &Path, markdown: &str) -> Document {
+        Document::from_markdown(
+            path,
+            markdown.to_string(),
+            &Renderer::new(),
+            &Theme::light(),
+        )
+    }
+
+    #[test]
+    fn session_round_trips_through_the_state_file() {
+        let dir = TempDir::new("session");
+        let a = dir.join("a.md");
+        let b = dir.join("b.md");
+        let mut state = AppState::new(Theme::dark());
+        state.open_document(loaded(&a, "# A"));
+        state.open_document(Document::open_error(&dir.join("broken.md"), "boom"));
+        state.open_document(loaded(&b, "# B"));
+        state.documents[2].scroll_position = Some(480);
+
+        let session = Session::capture(&state);
+        let path = dir.join("state").join("session.toml");
+        save_session_to(&session, &path).unwrap();
+
+        assert_eq!(load_session_from(&path), session);
+        assert_eq!(session.recent, [b.clone(), a.clone()]);
+        assert_eq!(session.theme.as_deref(), Some("Dark"));
+        assert_eq!(session.active, 1);
+        assert_eq!(
+            session.documents,
+            [
+                SessionDocument { path: a, scroll: 0 },
+                SessionDocument {
+                    path: b,
+                    scroll: 480
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn load_session_from_missing_or_broken_file_starts_fresh() {
+        let dir = TempDir::new("no-session");
+        let broken = dir.join("session.toml");
+        fs::write(&broken, "active = \"x\"").unwrap();
+
+        assert_eq!(
+            load_session_from(&dir.join("missing.toml")),
+            Session::default()
+        );
+        assert_eq!(load_session_from(&broken), Session::default());
+    }
+
+    #[test]
+    fn restore_reopens_existing_files_with_theme_and_scroll() {
+        let dir = TempDir::new("restore");
+        let a = dir.join("a.md");
+        let c = dir.join("c.md");
+        fs::write(&a, "# A").unwrap();
+        fs::write(&c, "# C").unwrap();
+        let session = Session {
+            recent: vec![c.clone(), dir.join("gone.md"), a.clone()],
+            theme: Some("Dark".to_string()),
+            active: 2,
+            documents: vec![
+                SessionDocument {
+                    path: a.clone(),
+                    scroll: 0,
+                },
+                SessionDocument {
+                    path: dir.join("gone.md"),
+                    scroll: 10,
+                },
+                SessionDocument {
+                    path: c.clone(),
+                    scroll: 300,
+                },
+            ],
+        };
+        let renderer = Renderer::new();
+        let mut state = AppState::new(Theme::light());
+
+        let reopen = session
+            .clone()
+            .restore(&mut state, &Config::default(), true);
+        assert_eq!(reopen.len(), 2);
+        for (path, options) in reopen {
+            let load = state.start_load(&path, options);
+            let document = load.run(&renderer, &state.theme);
+            state.finish_load(load.id, document);
+        }
+
+        assert_eq!(state.theme.name, "Dark");
+        assert_eq!(state.documents.len(), 2);
+        assert_eq!(state.active, 1);
+        assert_eq!(state.documents[0].scroll_position, None);
+        assert_eq!(state.documents[1].scroll_position, Some(300));
+        assert_eq!(state.recent, session.recent);
+        assert_eq!(state.recent_files(), [c, a]);
+
+        let mut fresh = AppState::new(Theme::light());
+        let config = Config {
+            restore_session: false,
+            ..Config::default()
+        };
+        assert!(session.restore(&mut fresh, &config, true).is_empty());
+        assert_eq!(fresh.theme.name, "Light");
+        assert_eq!(fresh.recent.len(), 3);
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::config::ConfigError;
+
+const DEFAULT_BODY_FONT: &str =
+    "-apple-system, BlinkMacSystemFont, \"Segoe UI\", \"Noto Sans\", Helvetica, Arial, sans-serif";
+const DEFAULT_MAX_WIDTH: u32 = 980;
+/// Bounds for `max_width`, in CSS pixels.
+const MAX_WIDTH_RANGE: std::ops::RangeInclusive<u32> = 320..=4096;
+
+/// Colors, fonts and code highlighting of rendered documents. The built-in
+/// Light and Dark themes can be extended or overridden by TOML files in the
+/// themes directory (see [`load_themes`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub name: String,
+    pub dark: bool,
+    pub colors: Palette,
+    pub body_font: String,
+    /// `None` keeps the webview's default monospace font.
+    pub code_font: Option<String>,
+    pub max_width: u32,
+    /// Name of the syntect theme used for code blocks.
+    pub syntax_theme: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub background: String,
+    pub text: String,
+    pub muted: String,
+    pub border: String,
+    pub code_background: String,
+    pub quote: String,
+    pub link: String,
+    pub note: String,
+    pub tip: String,
+    pub important: String,
+    pub warning: String,
+    pub caution: String,
+}
+
+impl Theme {
+    pub fn light() -> Self {
+        Self {
+            name: "Light".to_string(),
+            dark: false,
+            colors: Palette {
+                background: "#ffffff".to_string(),
+                text: "#1f2328".to_string(),
+                muted: "#57606a".to_string(),
+                border: "#d0d7de".to_string(),
+                code_background: "#f6f8fa".to_string(),
+                quote: "#656d76".to_string(),
+                link: "#0969da".to_string(),
+                note: "#0969da".to_string(),
+                tip: "#1a7f37".to_string(),
+                important: "#8250df".to_string(),
+                warning: "#9a6700".to_string(),
+                caution: "#d1242f".to_string(),
+            },
+            body_font: DEFAULT_BODY_FONT.to_string(),
+            code_font: None,
+            max_width: DEFAULT_MAX_WIDTH,
+            syntax_theme: "InspiredGitHub".to_string(),
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            name: "Dark".to_string(),
+            dark: true,
+            colors: Palette {
+                background: "#0d1117".to_string(),
+                text: "#e6edf3".to_string(),
+                muted: "#9198a1".to_string(),
+                border: "#30363d".to_string(),
+                code_background: "#161b22".to_string(),
+                quote: "#8b949e".to_string(),
+                link: "#4493f8".to_string(),
+                note: "#4493f8".to_string(),
+                tip: "#3fb950".to_string(),
+                important: "#ab7df8".to_string(),
+                warning: "#d29922".to_string(),
+                caution: "#f85149".to_string(),
+            },
+            body_font: DEFAULT_BODY_FONT.to_string(),
+            code_font: None,
+            max_width: DEFAULT_MAX_WIDTH,
+            syntax_theme: "base16-ocean.dark".to_string(),
+        }
+    }
+}
+
+/// On-disk form of a theme; anything left out comes from `base`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    base: Option<String>,
+    syntax_theme: Option<String>,
+    max_width: Option<u32>,
+    #[serde(default)]
+    fonts: FontsFile,
+    #[serde(default)]
+    colors: ColorsFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FontsFile {
+    body: Option<String>,
+    code: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColorsFile {
+    background: Option<String>,
+    text: Option<String>,
+    muted: Option<String>,
+    border: Option<String>,
+    code_background: Option<String>,
+    quote: Option<String>,
+    link: Option<String>,
+    note: Option<String>,
+    tip: Option<String>,
+    important: Option<String>,
+    warning: Option<String>,
+    caution: Option<String>,
+}
+
+/// The built-in themes followed by every `*.toml` theme in `dir`, in file
+/// name order. A file whose theme has the name of an earlier one replaces
+/// it. Files that fail to parse or validate are reported and skipped.
+pub fn load_themes(
+    dir: Option<&Path>,
+    is_syntax_theme: impl Fn(&str) -> bool,
+) -> (Vec<Theme>, Vec<ConfigError>) {
+    let mut themes = vec![Theme::light(), Theme::dark()];
+    let mut errors = Vec::new();
+    let Some(entries) = dir.and_then(|dir| fs::read_dir(dir).ok()) else {
+        return (themes, errors);
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let parsed = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| parse_theme(&stem, &text, &is_syntax_theme));
+        match parsed {
+            Ok(theme) => match themes
+                .iter_mut()
+                .find(|known| known.name.eq_ignore_ascii_case(&theme.name))
+            {
+                Some(known) => *known = theme,
+                None => themes.push(theme),
+            },
+            Err(message) => errors.push(ConfigError { path, message }),
+        }
+    }
+    (themes, errors)
+}
+
+/// Parses and validates a theme file; `default_name` is used when the file
+/// does not name the theme.
+fn parse_theme(
+    default_name: &str,
+    text: &str,
+    is_syntax_theme: &impl Fn(&str) -> bool,
+) -> Result<Theme, String> {
+    let file: ThemeFile = toml::from_str(text).map_err(|err| err.message().to_string())?;
+    let mut theme = match file.base.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("light") => Theme::light(),
+        Some("dark") => Theme::dark(),
+        Some(other) => {
+            return Err(format!(
+                "base: unknown theme '{other}', expected light or dark"
+            ))
+        }
+    };
+    let mut problems = Vec::new();
+
+    theme.name = file
+        .name
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|| default_name.to_string());
+    if theme.name.is_empty() {
+        problems.push("name: must not be empty".to_string());
+    }
+    if let Some(syntax_theme) = file.syntax_theme {
+        if !is_syntax_theme(&syntax_theme) {
+            problems.push(format!(
+                "syntax_theme: unknown highlight theme '{syntax_theme}'"
+            ));
+        }
+        theme.syntax_theme = syntax_theme;
+    }
+    if let Some(max_width) = file.max_width {
+        if let Err(message) = check_max_width(max_width) {
+            problems.push(format!("max_width: {message}"));
+        }
+        theme.max_width = max_width;
+    }
+    for (key, font) in [("body", &file.fonts.body), ("code", &file.fonts.code)] {
+        if let Some(font) = font
+            && !is_font_family(font)
+        {
+            problems.push(format!(
+                "fonts.{key}: '{font}' is not a CSS font-family list"
+            ));
+        }
+    }
+    if let Some(font) = file.fonts.body {
+        theme.body_font = font;
+    }
+    theme.code_font = file.fonts.code.or(theme.code_font);
+
+    let colors = file.colors;
+    let palette = &mut theme.colors;
+    for (key, color, target) in [
+        ("background", colors.background, &mut palette.background),
+        ("text", colors.text, &mut palette.text),
+        ("muted", colors.muted, &mut palette.muted),
+        ("border", colors.border, &mut palette.border),
+        (
+            "code_background",
+            colors.code_background,
+            &mut palette.code_background,
+        ),
+        ("quote", colors.quote, &mut palette.quote),
+        ("link", colors.link, &mut palette.link),
+        ("note", colors.note, &mut palette.note),
+        ("tip", colors.tip, &mut palette.tip),
+        ("important", colors.important, &mut palette.important),
+        ("warning", colors.warning, &mut palette.warning),
+        ("caution", colors.caution, &mut palette.caution),
+    ] {
+        let Some(color) = color else {
+            continue;
+        };
+        if !is_css_color(&color) {
+            problems.push(format!("colors.{key}: '{color}' is not a CSS color"));
+        }
+        *target = color;
+    }
+
+    if problems.is_empty() {
+        Ok(theme)
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+/// Theme values end up inside the page's `<style>`, so only plain color
+/// syntax is accepted: `#hex`, a color name, or an `rgb()`/`hsl()` call.
+fn is_css_color(value: &str) -> bool {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphabetic()) {
+        return true;
+    }
+    ["rgb(", "rgba(", "hsl(", "hsla("].iter().any(|function| {
+        value
+            .strip_prefix(function)
+            .and_then(|args| args.strip_suffix(')'))
+            .is_some_and(|args| {
+                args.chars()
+                    .all(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '%' | '/' | ' '))
+            })
+    })
+}
+
+pub(crate) fn check_max_width(max_width: u32) -> Result<(), String> {
+    if MAX_WIDTH_RANGE.contains(&max_width) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{max_width} is outside {}..={}",
+            MAX_WIDTH_RANGE.start(),
+            MAX_WIDTH_RANGE.end()
+        ))
+    }
+}
+
+pub(crate) fn is_font_family(value: &str) -> bool {
+    !value.trim().is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_control() || matches!(c, ';' | '{' | '}' | '<' | '>' | '\\'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn known(name: &str) -> bool {
+        name == "Solarized (dark)"
+    }
+
+    #[test]
+    fn parse_theme_fills_unset_values_from_base() {
+        let theme = parse_theme(
+            "solarized",
+            "base = \"dark\"\nsyntax_theme = \"Solarized (dark)\"\nmax_width = 720\n\n\
+             [fonts]\ncode = \"'Fira Code', monospace\"\n\n\
+             [colors]\nbackground = \"#002b36\"\nlink = \"rgb(38, 139, 210)\"\n",
+            &known,
+        )
+        .unwrap();
+
+        assert_eq!(theme.name, "solarized");
+        assert!(theme.dark);
+        assert_eq!(theme.colors.background, "#002b36");
+        assert_eq!(theme.colors.link, "rgb(38, 139, 210)");
+        assert_eq!(theme.colors.text, Theme::dark().colors.text);
+        assert_eq!(theme.body_font, Theme::dark().body_font);
+        assert_eq!(theme.code_font.as_deref(), Some("'Fira Code', monospace"));
+        assert_eq!(theme.max_width, 720);
+        assert_eq!(theme.syntax_theme, "Solarized (dark)");
+    }
+
+    #[test]
+    fn parse_theme_reports_every_invalid_value() {
+        let err = parse_theme(
+            "bad",
+            "syntax_theme = \"Nope\"\nmax_width = 10\n[fonts]\nbody = \"x; } body { color: red\"\n\
+             [colors]\ntext = \"red;}\"\nlink = \"#12345\"\n",
+            &known,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err,
+            "syntax_theme: unknown highlight theme 'Nope'; max_width: 10 is outside 320..=4096; \
+             fonts.body: 'x; } body { color: red' is not a CSS font-family list; \
+             colors.text: 'red;}' is not a CSS color; colors.link: '#12345' is not a CSS color"
+        );
+        assert!(parse_theme("typo", "[colours]\ntext = \"red\"", &known)
+            .unwrap_err()
+            .contains("unknown field `colours`"));
+        assert!(parse_theme("base", "base = \"sepia\"", &known).is_err());
+    }
+
+    #[test]
+    fn load_themes_adds_and_overrides_built_ins_and_collects_errors() {
+        let dir = TempDir::new("themes");
+        fs::write(
+            dir.join("a-dark.toml"),
+            "name = \"Dark\"\nbase = \"dark\"\nmax_width = 700",
+        )
+        .unwrap();
+        fs::write(dir.join("b-paper.toml"), "[colors]\nbackground = \"ivory\"").unwrap();
+        fs::write(dir.join("c-broken.toml"), "colors = 3").unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let (themes, errors) = load_themes(Some(&dir), known);
+
+        let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+        assert_eq!(names, ["Light", "Dark", "b-paper"]);
+        assert_eq!(themes[1].max_width, 700);
+        assert_eq!(themes[2].colors.background, "ivory");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, dir.join("c-broken.toml"));
+
+        let (themes, errors) = load_themes(None, known);
+        assert_eq!(themes, vec![Theme::light(), Theme::dark()]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn css_values_are_validated_against_injection() {
+        assert!(is_css_color("#fff"));
+        assert!(is_css_color("rebeccapurple"));
+        assert!(is_css_color("hsla(120, 50%, 40%, 0.5)"));
+        assert!(!is_css_color("url(x)"));
+        assert!(!is_css_color("red</style>"));
+        assert!(is_font_family("\"Iosevka Term\", monospace"));
+        assert!(!is_font_family("a</style>"));
+    }
+}
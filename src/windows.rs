@@ -1,32 +1,176 @@
-use std::{env, fs, path::Path, path::PathBuf};
+use std::{
+    borrow::Cow,
+    fs, mem,
+    path::Path,
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Instant,
+};
 
-use rfd::FileDialog;
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
 use tao::{
     dpi::LogicalSize,
     event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoopBuilder},
+    event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy},
     window::WindowBuilder,
 };
-use wry::WebViewBuilder;
+use wry::{
+    http::{header::CONTENT_TYPE, Request, Response, StatusCode},
+    DragDropEvent, NewWindowResponse, WebViewBuilder,
+};
+
+use crate::cli::{self, ViewerArgs};
+use crate::config::{self, ConfigError};
+use crate::core::{
+    self, AppState, Document, FinishedRender, LinkTarget, LoadOptions, MarkdownLink, PendingLoad,
+    ReloadWatch, RenderJob, RenderReason, Renderer,
+};
+use crate::encoding;
+use crate::keymap::AppEvent;
+use crate::session::{self, Session};
+use crate::windows_shared;
+
+/// In-page find for WebView2, which has no native find API exposed through
+/// wry: matches inside the article are wrapped in `<mark>` elements.
+const FIND_SCRIPT: &str = r#"
+      const dustrownFind = (() => {
+        const bar = document.querySelector('.findbar');
+        const input = document.getElementById('find-input');
+        const matchCase = document.getElementById('find-case');
+        const status = document.getElementById('find-status');
+        let matches = [];
+        let current = -1;
+
+        function clear() {
+          for (const mark of document.querySelectorAll('mark.find-match')) {
+            mark.replaceWith(document.createTextNode(mark.textContent));
+          }
+          document.querySelector('article.markdown-body')?.normalize();
+          matches = [];
+          current = -1;
+        }
+
+        function select(index) {
+          matches[current]?.classList.remove('current');
+          current = index;
+          const mark = matches[current];
+          if (mark) {
+            mark.classList.add('current');
+            mark.scrollIntoView({ block: 'center' });
+          }
+          status.textContent = !input.value ? ''
+            : matches.length ? `${current + 1} of ${matches.length}` : 'No matches';
+        }
 
-use crate::core::{self, AppState};
-use crate::windows_shared::{self, AppEvent};
+        // Lowercases each character only where that keeps its length ('İ'
+        // would grow to two code units), so indices in the folded text
+        // are indices in the text node.
+        function fold(text) {
+          if (matchCase.checked) return text;
+          let folded = '';
+          for (const ch of text) {
+            const lower = ch.toLowerCase();
+            folded += lower.length === ch.length ? lower : ch;
+          }
+          return folded;
+        }
 
-fn render_app_shell(content_html: &str, state: &AppState) -> String {
-    let body = if content_html.is_empty() {
-        core::default_body()
+        function search() {
+          clear();
+          const article = document.querySelector('article.markdown-body');
+          const query = fold(input.value);
+          if (article && query) {
+            const walker = document.createTreeWalker(article, NodeFilter.SHOW_TEXT);
+            const nodes = [];
+            while (walker.nextNode()) nodes.push(walker.currentNode);
+            for (let node of nodes) {
+              let index;
+              while ((index = fold(node.data).indexOf(query)) !== -1) {
+                const match = node.splitText(index);
+                node = match.splitText(query.length);
+                const mark = document.createElement('mark');
+                mark.className = 'find-match';
+                match.replaceWith(mark);
+                mark.appendChild(match);
+                matches.push(mark);
+              }
+            }
+          }
+          select(matches.length ? 0 : -1);
+        }
+
+        function step(delta) {
+          if (bar.hidden || !input.value) { open(); return; }
+          if (matches.length) select((current + delta + matches.length) % matches.length);
+        }
+
+        function open() { bar.hidden = false; input.focus(); input.select(); }
+        function close() { bar.hidden = true; clear(); status.textContent = ''; }
+
+        input.addEventListener('input', search);
+        matchCase.addEventListener('change', search);
+        input.addEventListener('keydown', (event) => {
+          if (event.key === 'Enter') { event.preventDefault(); step(event.shiftKey ? -1 : 1); }
+          else if (event.key === 'Escape') { event.preventDefault(); close(); }
+        });
+        return { open, close, next: () => step(1), previous: () => step(-1) };
+      })();"#;
+
+fn render_app_shell(state: &AppState, outline_visible: bool) -> String {
+    let document = state.active_document();
+    let body = document.map_or_else(core::default_body, |d| d.rendered_html.clone());
+    let markdown_doc = core::render_document(&body, &state.theme, None);
+    let content = windows_shared::extract_document_body(&markdown_doc);
+    let outline = windows_shared::render_outline(document.map_or(&[][..], |d| &d.outline));
+    let tabs = windows_shared::render_tabs(state);
+    let theme_picker = windows_shared::render_theme_picker(state);
+    let recent_picker = windows_shared::render_recent_picker(state);
+    let remote_banner = windows_shared::render_remote_banner(document);
+    let encoding_picker = windows_shared::render_encoding_picker(document);
+    let encoding_banner = windows_shared::render_encoding_banner(document);
+    let loading_banner = windows_shared::render_loading_banner(state);
+    let scroll = match document {
+        Some(Document {
+            scroll_position: Some(offset),
+            ..
+        }) => format!("\n      {}", core::scroll_to_offset_script(*offset)),
+        Some(Document {
+            fragment: Some(id), ..
+        }) => format!("\n      {}", core::scroll_to_heading_script(id)),
+        _ => String::new(),
+    };
+    let back_disabled = if document.is_some_and(|d| d.history.can_go_back()) {
+        ""
     } else {
-        content_html.to_string()
+        " disabled"
     };
-    let markdown_doc = core::render_document(&body, state.theme);
-    let content = windows_shared::extract_document_body(&markdown_doc);
+    let forward_disabled = if document.is_some_and(|d| d.history.can_go_forward()) {
+        ""
+    } else {
+        " disabled"
+    };
+    let body_class = if outline_visible {
+        ""
+    } else {
+        "outline-hidden"
+    };
+    let base = document
+        .and_then(Document::source_path)
+        .and_then(windows_shared::asset_base_uri)
+        .map_or_else(String::new, |uri| {
+            format!(
+                "\n    <base href=\"{}\" />",
+                html_escape::encode_double_quoted_attribute(&uri)
+            )
+        });
 
     format!(
         r#"<!doctype html>
 <html lang="en">
   <head>
     <meta charset="utf-8" />
-    <meta name="viewport" content="width=device-width, initial-scale=1" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />{base}
     <style>
       html, body {{ margin: 0; padding: 0; }}
       .topbar {{
@@ -46,95 +190,442 @@ fn render_app_shell(content_html: &str, state: &AppState) -> String {
         cursor: pointer;
       }}
       .topbar button:hover {{ background: #e2e8f0; }}
+      .topbar button:disabled {{ opacity: 0.5; cursor: default; }}
+      .topbar select {{ border: 1px solid #6e7681; background: #f6f8fa; }}
+      .tabs {{ display: flex; gap: 4px; margin-left: 12px; min-width: 0; overflow-x: auto; }}
+      .tab {{ display: flex; flex: none; }}
+      .topbar .tab button {{ border-color: #9ea7b3; }}
+      .topbar .tab.active button {{ background: #ffffff; border-color: #6e7681; font-weight: 600; }}
+      .topbar .tab-close {{ border-left: none; padding: 5px 6px; }}
+      .layout {{ display: flex; align-items: flex-start; }}
+      .layout > .content {{ flex: 1; min-width: 0; }}
+      .outline {{
+        box-sizing: border-box;
+        width: 240px;
+        flex: none;
+        max-height: calc(100vh - 48px);
+        overflow: auto;
+        position: sticky;
+        top: 48px;
+        padding: 16px 8px;
+        border-right: 1px solid #9ea7b3;
+        font: 13px/1.5 -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif;
+      }}
+      .outline:empty, .outline-hidden .outline {{ display: none; }}
+      .outline ul {{ list-style: none; margin: 0; padding-left: 12px; }}
+      .outline > ul {{ padding-left: 0; }}
+      .outline summary {{ cursor: pointer; }}
+      .outline a {{ color: inherit; text-decoration: none; }}
+      .outline a:hover {{ text-decoration: underline; }}
+      .findbar {{
+        display: flex;
+        align-items: center;
+        gap: 8px;
+        padding: 6px 12px;
+        border-bottom: 1px solid #9ea7b3;
+        background: #f6f8fa;
+        position: sticky;
+        top: 48px;
+        z-index: 2;
+        font: 13px -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif;
+      }}
+      .findbar[hidden] {{ display: none; }}
+      .remote-banner, .encoding-banner, .loading-banner {{
+        display: flex;
+        align-items: center;
+        gap: 8px;
+        padding: 6px 12px;
+        border-bottom: 1px solid #d4a72c;
+        background: #fff8c5;
+        color: #24292f;
+        font: 13px -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif;
+      }}
+      mark.find-match {{ background: #fff8c5; color: inherit; }}
+      mark.find-match.current {{ background: #ffd33d; }}
     </style>
   </head>
-  <body>
+  <body class="{body_class}">
     <nav class="topbar">
+      <button id="back-button" onclick="appCmd('back')" title="Back (Alt+Left)"{back_disabled}>&larr;</button>
+      <button id="forward-button" onclick="appCmd('forward')" title="Forward (Alt+Right)"{forward_disabled}>&rarr;</button>
       <button onclick="appCmd('open')">Open...</button>
-      <button onclick="appCmd('close')">Close File</button>
+      {recent_picker}
+      <button onclick="appCmd('close')">Close Tab</button>
       <button onclick="appCmd('theme')">Toggle Light/Dark</button>
+      {theme_picker}
+      {encoding_picker}
+      <button onclick="appCmd('outline')">Outline</button>
+      <button onclick="appCmd('find')">Find</button>
       <button onclick="appCmd('quit')">Quit</button>
+      <div class="tabs">{tabs}</div>
     </nav>
-    {content}
+    <div class="findbar" hidden>
+      <input id="find-input" type="search" placeholder="Find in document" />
+      <button onclick="appCmd('find-previous')" title="Previous match (Shift+F3)">&uarr;</button>
+      <button onclick="appCmd('find-next')" title="Next match (F3)">&darr;</button>
+      <label><input id="find-case" type="checkbox" /> Match case</label>
+      <span id="find-status"></span>
+      <button onclick="dustrownFind.close()" title="Close (Esc)">&times;</button>
+    </div>
+    <div class="remote-slot">{remote_banner}</div>
+    <div class="encoding-slot">{encoding_banner}</div>
+    <div class="loading-slot">{loading_banner}</div>
+    <div class="layout">
+      <nav class="outline">{outline}</nav>
+      <div class="content">{content}</div>
+    </div>
     <script>
-      function appCmd(action) {{ window.ipc.postMessage(action); }}
-      window.addEventListener('keydown', (event) => {{
-        if (!event.ctrlKey) return;
-        const key = event.key.toLowerCase();
-        if (key === 'o') {{ event.preventDefault(); appCmd('open'); }}
-        else if (key === 'w') {{ event.preventDefault(); appCmd('close'); }}
-        else if (key === 'd') {{ event.preventDefault(); appCmd('theme'); }}
-        else if (key === 'q') {{ event.preventDefault(); appCmd('quit'); }}
+      function appCmd(action) {{ window.ipc.postMessage(action); }}{FIND_SCRIPT}
+      // The page <base> points at the document directory, so resolve
+      // in-page anchors here instead of navigating away.
+      document.addEventListener('click', (event) => {{
+        const link = event.target.closest('a[href^="#"]');
+        if (!link) return;
+        const target = document.getElementById(decodeURIComponent(link.getAttribute('href').slice(1)));
+        if (target) {{ event.preventDefault(); target.scrollIntoView(); }}
       }});
+      // Report where the page rests, so tab switches and the next session
+      // come back to it.
+      let scrollReport;
+      window.addEventListener('scroll', () => {{
+        clearTimeout(scrollReport);
+        scrollReport = setTimeout(() => appCmd('scrolled:' + Math.round(window.scrollY)), 200);
+      }});{scroll}
     </script>
   </body>
 </html>"#
     )
 }
 
-fn refresh_view(webview: &wry::WebView, state: &AppState) {
-    let content = state
-        .rendered_html
-        .clone()
-        .unwrap_or_else(core::default_body);
-    let page = render_app_shell(&content, state);
+fn refresh_view(
+    webview: &wry::WebView,
+    window: &tao::window::Window,
+    state: &mut AppState,
+    outline_visible: bool,
+) {
+    if let Ok(mut root) = ASSET_ROOT.lock() {
+        *root = state
+            .active_document()
+            .and_then(Document::source_path)
+            .and_then(Path::parent)
+            .map(Path::to_path_buf);
+    }
+    let page = render_app_shell(state, outline_visible);
     let _ = webview.load_html(&page);
+    window.set_title(&state.window_title());
+    // The page scrolls to the fragment once; after that the offsets from
+    // `AppEvent::Scrolled` keep the reader's place.
+    if let Some(document) = state.active_document_mut() {
+        document.fragment = None;
+    }
+}
+
+/// Reads and renders files for new tabs on worker threads. A finished load
+/// posts its document to `results` and wakes the event loop with
+/// `AppEvent::Loaded`; a finished render of an open tab goes to `renders`,
+/// followed by `AppEvent::Rendered`.
+struct Loader {
+    renderer: Arc<Renderer>,
+    proxy: EventLoopProxy<AppEvent>,
+    results: mpsc::Sender<(u64, Option<Document>)>,
+    renders: mpsc::Sender<FinishedRender>,
+}
+
+/// Starts reading the file at `path` for a new tab, asking first when it is
+/// over the size limit.
+fn open_path(path: &Path, state: &mut AppState, loader: &Loader) {
+    open_path_at(path, LoadOptions::default(), state, loader);
 }
 
-fn open_path(
-    path: &Path,
+/// Like [`open_path`], showing the tab as `options` say.
+fn open_path_at(path: &Path, options: LoadOptions, state: &mut AppState, loader: &Loader) {
+    let limit_mb = state.max_file_size_mb;
+    if let Some(size) = core::large_text_file(path, limit_mb)
+        && !confirm_large_file(path, size, limit_mb)
+    {
+        return;
+    }
+    let load = state.start_load(path, options);
+    run_load(load, state, loader);
+}
+
+/// Reads and renders `load` on a worker thread, which posts the document
+/// back to the event loop.
+fn run_load(load: PendingLoad, state: &AppState, loader: &Loader) {
+    let theme = state.theme.clone();
+    let renderer = loader.renderer.clone();
+    let proxy = loader.proxy.clone();
+    let results = loader.results.clone();
+    thread::spawn(move || {
+        let document = load.run(&renderer, &theme);
+        if results.send((load.id, document)).is_ok() {
+            let _ = proxy.send_event(AppEvent::Loaded);
+        }
+    });
+}
+
+fn render_in_background(jobs: Vec<RenderJob>, loader: &Loader) {
+    for job in jobs {
+        let renderer = loader.renderer.clone();
+        let proxy = loader.proxy.clone();
+        let renders = loader.renders.clone();
+        thread::spawn(move || {
+            if renders.send(job.run(&renderer)).is_ok() {
+                let _ = proxy.send_event(AppEvent::Rendered);
+            }
+        });
+    }
+}
+
+/// Renders the open tabs again with the theme just selected. The page is
+/// rebuilt right away only when the active tab has nothing to render;
+/// otherwise `AppEvent::Rendered` rebuilds it once that render finishes.
+fn change_theme(
     webview: &wry::WebView,
     window: &tao::window::Window,
     state: &mut AppState,
+    loader: &Loader,
+    outline_visible: bool,
 ) {
-    match fs::read_to_string(path) {
-        Ok(markdown) => {
-            state.source_markdown = Some(markdown.clone());
-            state.rendered_html = Some(core::render_markdown(&markdown, state.theme));
-            refresh_view(webview, state);
-            window.set_title(&format!(
-                "{} - {}",
-                core::APP_TITLE,
-                core::filename_or_path(path)
-            ));
+    render_in_background(state.render_jobs(), loader);
+    if !state.active_document().is_some_and(Document::is_rendering) {
+        refresh_view(webview, window, state, outline_visible);
+    }
+}
+
+fn refresh_loading(webview: &wry::WebView, state: &AppState) {
+    let _ = webview.evaluate_script(&core::replace_inner_html_script(
+        ".loading-slot",
+        &windows_shared::render_loading_banner(state),
+    ));
+}
+
+/// Back and forward follow the tab's history, which moves as soon as a
+/// navigation starts and moves back if it fails.
+fn refresh_history_buttons(webview: &wry::WebView, state: &AppState) {
+    let document = state.active_document();
+    let _ = webview.evaluate_script(&format!(
+        "document.getElementById('back-button').disabled = {}; \
+         document.getElementById('forward-button').disabled = {};",
+        !document.is_some_and(|d| d.history.can_go_back()),
+        !document.is_some_and(|d| d.history.can_go_forward()),
+    ));
+}
+
+/// Starts loading a linked document into the active tab, recording it in
+/// the history, asking first when it is over the size limit.
+fn follow_link(link: MarkdownLink, state: &mut AppState, loader: &Loader) {
+    let limit_mb = state.max_file_size_mb;
+    if let Some(size) = core::large_text_file(&link.path, limit_mb)
+        && !confirm_large_file(&link.path, size, limit_mb)
+    {
+        return;
+    }
+    let load = state.follow_link(link);
+    run_load(load, state, loader);
+}
+
+fn reload_path(path: &Path, state: &mut AppState, loader: &Loader) {
+    // Keep the last good render while an editor is midway through saving.
+    let Ok(bytes) = fs::read(path) else {
+        return;
+    };
+    render_in_background(state.reload_path(path, &bytes), loader);
+}
+
+/// Swaps in the new render of the active tab, keeping the scroll position.
+fn refresh_body(webview: &wry::WebView, state: &AppState) {
+    let Some(document) = state.active_document() else {
+        return;
+    };
+    let _ = webview.evaluate_script(&core::replace_body_script(&document.rendered_html));
+    let _ = webview.evaluate_script(&core::replace_inner_html_script(
+        "nav.outline",
+        &windows_shared::render_outline(&document.outline),
+    ));
+    let _ = webview.evaluate_script(&core::replace_inner_html_script(
+        ".remote-slot",
+        &windows_shared::render_remote_banner(Some(document)),
+    ));
+    let _ = webview.evaluate_script(&core::replace_inner_html_script(
+        ".encoding-slot",
+        &windows_shared::render_encoding_banner(Some(document)),
+    ));
+}
+
+fn open_external(url: &str, confirm: bool) {
+    if confirm {
+        let answer = MessageDialog::new()
+            .set_level(MessageLevel::Info)
+            .set_title("Open this link in your browser?")
+            .set_description(url)
+            .set_buttons(MessageButtons::OkCancel)
+            .show();
+        if answer != MessageDialogResult::Ok {
+            return;
         }
-        Err(err) => {
-            let error_text = err.to_string();
-            let escaped = html_escape::encode_text(&error_text);
-            state.source_markdown = None;
-            state.rendered_html = Some(format!("<h2>Could not open file</h2><p>{escaped}</p>"));
-            refresh_view(webview, state);
-            window.set_title(core::APP_TITLE);
+    }
+    if let Err(err) = open::that_detached(url) {
+        eprintln!("failed to open {url}: {err}");
+    }
+}
+
+fn confirm_large_file(path: &Path, size: u64, limit_mb: u64) -> bool {
+    MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("Render this large file?")
+        .set_description(core::large_file_prompt(path, size, limit_mb))
+        .set_buttons(MessageButtons::OkCancel)
+        .show()
+        == MessageDialogResult::Ok
+}
+
+fn report_blocked_link(url: &str) {
+    MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("This link was blocked")
+        .set_description(format!(
+            "Only web links and local Markdown files can be opened:\n{url}"
+        ))
+        .set_buttons(MessageButtons::Ok)
+        .show();
+}
+
+fn report_config_errors(errors: &[ConfigError]) {
+    if errors.is_empty() {
+        return;
+    }
+    let details: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("Some settings could not be loaded")
+        .set_description(details.join("\n"))
+        .set_buttons(MessageButtons::Ok)
+        .show();
+}
+
+fn save_session(state: &AppState) {
+    if let Err(err) = session::save_session(&Session::capture(state)) {
+        eprintln!("failed to save the session: {err}");
+    }
+}
+
+/// Keeps one watch per open file, preserving the state of existing watches.
+fn sync_watches(watches: &mut Vec<ReloadWatch>, paths: &[PathBuf]) {
+    watches.retain(|watch| paths.iter().any(|path| path == watch.path()));
+    for path in paths {
+        if !watches.iter().any(|watch| watch.path() == path) {
+            watches.push(ReloadWatch::new(path));
         }
     }
 }
 
-fn open_file_dialog() -> Option<PathBuf> {
+/// Directory of the active document, kept by [`refresh_view`]; the asset
+/// protocol serves only images inside it.
+static ASSET_ROOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+fn serve_asset(request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    let path = windows_shared::asset_path_from_url_path(request.uri().path());
+    let root = ASSET_ROOT.lock().ok().and_then(|root| root.clone());
+    let allowed = root.is_some_and(|root| windows_shared::asset_within(&path, &root));
+    let response = match windows_shared::asset_content_type(&path) {
+        Some(content_type) if allowed => match fs::read(&path) {
+            Ok(bytes) => Response::builder()
+                .header(CONTENT_TYPE, content_type)
+                .body(Cow::Owned(bytes)),
+            Err(_) => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Cow::Borrowed(&[][..])),
+        },
+        _ => Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Cow::Borrowed(&[][..])),
+    };
+    response.expect("valid asset response")
+}
+
+fn open_file_dialog(extensions: &[String]) -> Vec<PathBuf> {
     FileDialog::new()
-        .add_filter("Markdown", &["md", "markdown", "mdown", "mkd", "txt"])
-        .pick_file()
+        .add_filter("Markdown", extensions)
+        .pick_files()
+        .unwrap_or_default()
 }
 
-pub fn run() {
-    let initial_path = env::args().nth(1).map(PathBuf::from);
-    let mut state = AppState {
-        theme: core::detect_theme(),
-        source_markdown: None,
-        rendered_html: None,
-    };
+pub fn run(args: ViewerArgs) {
+    let (config, mut load_errors) = config::load_config();
+    let mut state = AppState::new(core::detect_theme());
+    state.max_file_size_mb = config.max_file_size_mb;
+    let (renderer, renderer_errors) = Renderer::with_config(&config);
+    load_errors.extend(renderer_errors);
+    load_errors.extend(state.load_themes(&config, &renderer));
+    // The theme is settled before any file loads, so nothing renders twice.
+    let mut startup_files =
+        session::load_session().restore(&mut state, &config, args.targets.is_empty());
+    if let Some(name) = &args.theme
+        && !state.select_theme_by_name(name)
+    {
+        eprintln!("dustrown: unknown theme '{name}'");
+    }
+    if let Some(document) = cli::stdin_document(&args.targets, &renderer, &state.theme) {
+        state.open_document(document);
+    }
+    startup_files.extend(cli::target_files(&args.targets, &config.file_extensions));
+    let mut outline_visible = true;
+    let mut reload_watches: Vec<ReloadWatch> = Vec::new();
 
     let event_loop = EventLoopBuilder::<AppEvent>::with_user_event().build();
     let proxy = event_loop.create_proxy();
+    let link_proxy = event_loop.create_proxy();
+    let drop_proxy = event_loop.create_proxy();
+    let (load_sender, load_results) = mpsc::channel();
+    let (render_sender, render_results) = mpsc::channel();
+    let loader = Loader {
+        renderer: Arc::new(renderer),
+        proxy: event_loop.create_proxy(),
+        results: load_sender,
+        renders: render_sender,
+    };
+    // The new-window handler runs on another thread.
+    let new_window_proxy = Mutex::new(event_loop.create_proxy());
 
     let window = WindowBuilder::new()
         .with_title(core::APP_TITLE)
-        .with_inner_size(LogicalSize::new(980.0, 760.0))
+        .with_inner_size(LogicalSize::new(config.window_width, config.window_height))
         .build(&event_loop)
         .expect("create window");
 
     let webview = WebViewBuilder::new()
+        // Runs on every page load, so the shortcuts survive `load_html`.
+        .with_initialization_script(&windows_shared::shortcut_script(&config.keymap))
+        .with_custom_protocol(windows_shared::ASSET_PROTOCOL.to_string(), |_, request| {
+            serve_asset(request)
+        })
+        .with_navigation_handler(
+            move |url| match windows_shared::intercepted_navigation(&url) {
+                Some(target) => {
+                    let _ = link_proxy.send_event(AppEvent::Link(target));
+                    false
+                }
+                None => true,
+            },
+        )
+        .with_new_window_req_handler(move |url, _| {
+            if let Some(target) = windows_shared::intercepted_navigation(&url)
+                && let Ok(proxy) = new_window_proxy.lock()
+            {
+                let _ = proxy.send_event(AppEvent::Link(target));
+            }
+            NewWindowResponse::Deny
+        })
+        // Open dropped files in tabs instead of letting WebView2 show them.
+        .with_drag_drop_handler(move |event| {
+            if let DragDropEvent::Drop { paths, .. } = event {
+                let _ = drop_proxy.send_event(AppEvent::Dropped(paths));
+            }
+            true
+        })
         .with_ipc_handler(move |request| {
-            if let Some(event) = windows_shared::parse_app_event(request.body()) {
+            if let Some(event) = windows_shared::parse_page_message(request.body()) {
                 let _ = proxy.send_event(event);
             }
         })
@@ -142,7 +633,6 @@ pub fn run() {
         .expect("build webview");
 
     let mut initialized = false;
-    let mut pending_initial_path = initial_path;
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -150,39 +640,203 @@ pub fn run() {
         match event {
             Event::MainEventsCleared if !initialized => {
                 initialized = true;
-                refresh_view(&webview, &state);
-                if let Some(path) = pending_initial_path.take() {
-                    open_path(&path, &webview, &window, &mut state);
+                sync_watches(&mut reload_watches, &state.watched_paths());
+                refresh_view(&webview, &window, &mut state, outline_visible);
+                report_config_errors(&load_errors);
+                // Opened once the window is up, so a size prompt has a
+                // parent and the loading bar can cancel them.
+                for (path, options) in mem::take(&mut startup_files) {
+                    open_path_at(&path, options, &mut state, &loader);
                 }
+                refresh_loading(&webview, &state);
             }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
+                save_session(&state);
                 *control_flow = ControlFlow::Exit;
             }
             Event::UserEvent(AppEvent::Open) => {
-                if let Some(path) = open_file_dialog() {
-                    open_path(&path, &webview, &window, &mut state);
+                for path in &open_file_dialog(&config.file_extensions) {
+                    open_path(path, &mut state, &loader);
+                }
+                refresh_loading(&webview, &state);
+            }
+            Event::UserEvent(AppEvent::Dropped(paths)) => {
+                for path in &paths {
+                    open_path(path, &mut state, &loader);
+                }
+                refresh_loading(&webview, &state);
+            }
+            Event::UserEvent(AppEvent::Loaded) => {
+                let mut opened = false;
+                for (id, document) in load_results.try_iter() {
+                    opened |= !state.finish_load(id, document).is_empty();
+                }
+                if opened {
+                    sync_watches(&mut reload_watches, &state.watched_paths());
+                    refresh_view(&webview, &window, &mut state, outline_visible);
+                } else {
+                    refresh_loading(&webview, &state);
+                    refresh_history_buttons(&webview, &state);
+                }
+            }
+            Event::UserEvent(AppEvent::Rendered) => {
+                let (mut page, mut body, mut tabs) = (false, false, false);
+                for render in render_results.try_iter() {
+                    let reason = render.reason;
+                    let Some(index) = state.finish_render(render) else {
+                        continue;
+                    };
+                    // A changed front matter title renames the tab.
+                    tabs = true;
+                    if index == state.active {
+                        match reason {
+                            RenderReason::Theme => page = true,
+                            RenderReason::Content => body = true,
+                        }
+                    }
+                }
+                if page {
+                    refresh_view(&webview, &window, &mut state, outline_visible);
+                } else if tabs {
+                    if body {
+                        refresh_body(&webview, &state);
+                    }
+                    let _ = webview.evaluate_script(&core::replace_inner_html_script(
+                        ".tabs",
+                        &windows_shared::render_tabs(&state),
+                    ));
+                    window.set_title(&state.window_title());
+                }
+            }
+            Event::UserEvent(AppEvent::CancelLoad) => {
+                if state.cancel_loads() {
+                    refresh_loading(&webview, &state);
                 }
             }
             Event::UserEvent(AppEvent::Close) => {
-                state.source_markdown = None;
-                state.rendered_html = None;
-                refresh_view(&webview, &state);
-                window.set_title(core::APP_TITLE);
+                if state.close_active().is_some() {
+                    sync_watches(&mut reload_watches, &state.watched_paths());
+                    refresh_view(&webview, &window, &mut state, outline_visible);
+                }
+            }
+            Event::UserEvent(AppEvent::ReopenClosed) => {
+                if let Some(path) = state.take_closed() {
+                    open_path(&path, &mut state, &loader);
+                    refresh_loading(&webview, &state);
+                }
+            }
+            Event::UserEvent(AppEvent::OpenRecent(index)) => {
+                if let Some(path) = state.recent_files().get(index) {
+                    open_path(path, &mut state, &loader);
+                    refresh_loading(&webview, &state);
+                }
+            }
+            Event::UserEvent(AppEvent::CloseTab(index)) => {
+                if state.select(index) && state.close_active().is_some() {
+                    sync_watches(&mut reload_watches, &state.watched_paths());
+                    refresh_view(&webview, &window, &mut state, outline_visible);
+                }
+            }
+            Event::UserEvent(AppEvent::SelectTab(index)) => {
+                if state.select(index) {
+                    refresh_view(&webview, &window, &mut state, outline_visible);
+                }
+            }
+            Event::UserEvent(AppEvent::NextTab) => {
+                state.select_next();
+                refresh_view(&webview, &window, &mut state, outline_visible);
+            }
+            Event::UserEvent(AppEvent::PreviousTab) => {
+                state.select_previous();
+                refresh_view(&webview, &window, &mut state, outline_visible);
+            }
+            Event::UserEvent(AppEvent::Link(target)) => match target {
+                LinkTarget::Markdown(link) => {
+                    follow_link(link, &mut state, &loader);
+                    refresh_loading(&webview, &state);
+                    refresh_history_buttons(&webview, &state);
+                }
+                LinkTarget::External(url) => open_external(&url, state.confirm_external_links),
+                LinkTarget::Blocked(url) => report_blocked_link(&url),
+                LinkTarget::SameDocument => {}
+            },
+            Event::UserEvent(AppEvent::Back) => {
+                if let Some(load) = state.go_back() {
+                    run_load(load, &state, &loader);
+                    refresh_loading(&webview, &state);
+                    refresh_history_buttons(&webview, &state);
+                }
+            }
+            Event::UserEvent(AppEvent::Forward) => {
+                if let Some(load) = state.go_forward() {
+                    run_load(load, &state, &loader);
+                    refresh_loading(&webview, &state);
+                    refresh_history_buttons(&webview, &state);
+                }
+            }
+            Event::UserEvent(AppEvent::ReopenWithEncoding(index)) => {
+                if let Some(&encoding) = encoding::reopen_encodings().get(index) {
+                    let job = state.reopen_with_encoding(encoding);
+                    render_in_background(job.into_iter().collect(), &loader);
+                }
+            }
+            Event::UserEvent(AppEvent::AllowRemote) => {
+                let job = state.allow_remote_content();
+                render_in_background(job.into_iter().collect(), &loader);
             }
             Event::UserEvent(AppEvent::ToggleTheme) => {
-                state.theme = state.theme.toggled();
-                if let Some(markdown) = state.source_markdown.clone() {
-                    state.rendered_html = Some(core::render_markdown(&markdown, state.theme));
+                state.toggle_theme();
+                change_theme(&webview, &window, &mut state, &loader, outline_visible);
+            }
+            Event::UserEvent(AppEvent::SelectTheme(index)) => {
+                if state.set_theme(index) {
+                    change_theme(&webview, &window, &mut state, &loader, outline_visible);
                 }
-                refresh_view(&webview, &state);
+            }
+            Event::UserEvent(AppEvent::ToggleOutline) => {
+                outline_visible = !outline_visible;
+                let _ = webview.evaluate_script(&format!(
+                    "document.body.classList.toggle('outline-hidden', {});",
+                    !outline_visible
+                ));
+            }
+            Event::UserEvent(AppEvent::Navigate(navigation)) => {
+                let _ = webview.evaluate_script(&core::navigation_script(navigation));
+            }
+            Event::UserEvent(AppEvent::Scrolled(offset)) => {
+                if let Some(document) = state.active_document_mut() {
+                    document.scroll_position = Some(offset);
+                }
+            }
+            Event::UserEvent(AppEvent::Find) => {
+                let _ = webview.evaluate_script("dustrownFind.open();");
+            }
+            Event::UserEvent(AppEvent::FindNext) => {
+                let _ = webview.evaluate_script("dustrownFind.next();");
+            }
+            Event::UserEvent(AppEvent::FindPrevious) => {
+                let _ = webview.evaluate_script("dustrownFind.previous();");
             }
             Event::UserEvent(AppEvent::Quit) => {
+                save_session(&state);
                 *control_flow = ControlFlow::Exit;
             }
             _ => {}
         }
+
+        // No native watcher on this backend: poll the open files' mtimes.
+        if *control_flow == ControlFlow::Wait && !reload_watches.is_empty() {
+            let now = Instant::now();
+            for watch in &mut reload_watches {
+                watch.poll(now);
+                if watch.take_due(now) {
+                    reload_path(watch.path(), &mut state, &loader);
+                }
+            }
+            *control_flow = ControlFlow::WaitUntil(now + core::RELOAD_TICK);
+        }
     });
 }
@@ -1,19 +1,74 @@
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum AppEvent {
-    Open,
-    Close,
-    ToggleTheme,
-    Quit,
-}
-
-pub fn parse_app_event(raw: &str) -> Option<AppEvent> {
-    match raw {
-        "open" => Some(AppEvent::Open),
-        "close" => Some(AppEvent::Close),
-        "theme" => Some(AppEvent::ToggleTheme),
-        "quit" => Some(AppEvent::Quit),
-        _ => None,
+use std::path::{Path, PathBuf};
+
+use crate::{
+    core::{self, AppState, Document, Heading, LinkTarget, MarkdownLink},
+    encoding,
+    keymap::{self, AppEvent, Keymap},
+};
+
+/// Custom protocol serving local files referenced by the open document.
+/// WebView2 exposes it as `http://dustrown.localhost/<absolute path>`.
+pub const ASSET_PROTOCOL: &str = "dustrown";
+
+/// Page script that runs [`Keymap`] on key presses and posts the bound
+/// commands. Chords are matched in the lowercase form of their display,
+/// sequences joined by spaces.
+pub fn shortcut_script(keymap: &Keymap) -> String {
+    let mut sequences = Vec::new();
+    let mut prefixes = Vec::new();
+    for binding in keymap.bindings() {
+        let chords: Vec<String> = binding
+            .keys
+            .iter()
+            .map(|chord| chord.to_string().to_lowercase())
+            .collect();
+        sequences.push(format!(
+            "{}: {}",
+            core::js_string_literal(&chords.join(" ")),
+            core::js_string_literal(&binding.command)
+        ));
+        for end in 1..chords.len() {
+            prefixes.push(core::js_string_literal(&chords[..end].join(" ")));
+        }
     }
+    format!(
+        r#"(() => {{
+  const bindings = {{ {} }};
+  const prefixes = new Set([{}]);
+  const keyNames = {{ ArrowLeft: 'left', ArrowRight: 'right', ArrowUp: 'up', ArrowDown: 'down', ' ': 'space' }};
+  let pending = [];
+  let lastPress = 0;
+  window.addEventListener('keydown', (event) => {{
+    if (['Control', 'Shift', 'Alt', 'Meta'].includes(event.key)) return;
+    const key = keyNames[event.key] || event.key.toLowerCase();
+    // Leave plain typing in text fields alone.
+    if (!event.ctrlKey && !event.altKey && key.length === 1 && event.target.closest('input, textarea')) return;
+    // Shift only counts for letters and named keys; it already picked other characters.
+    const shift = event.shiftKey && (key.length > 1 || key !== key.toUpperCase());
+    const chord = (event.ctrlKey ? 'ctrl+' : '') + (event.altKey ? 'alt+' : '') + (shift ? 'shift+' : '') + key;
+    if (event.timeStamp - lastPress > {}) pending = [];
+    lastPress = event.timeStamp;
+    for (const keys of pending.length ? [pending.concat(chord), [chord]] : [[chord]]) {{
+      const sequence = keys.join(' ');
+      if (bindings[sequence]) {{
+        event.preventDefault();
+        pending = [];
+        window.ipc.postMessage(bindings[sequence]);
+        return;
+      }}
+      if (prefixes.has(sequence)) {{
+        event.preventDefault();
+        pending = keys;
+        return;
+      }}
+    }}
+    pending = [];
+  }});
+}})();"#,
+        sequences.join(", "),
+        prefixes.join(", "),
+        keymap::SEQUENCE_TIMEOUT.as_millis()
+    )
 }
 
 pub fn extract_document_body(markdown_doc: &str) -> &str {
@@ -27,17 +82,256 @@ pub fn extract_document_body(markdown_doc: &str) -> &str {
     }
 }
 
+/// Renders the outline as nested lists; headings with children collapse via
+/// `<details>`, and links point at the heading ids in the article.
+pub fn render_outline(headings: &[Heading]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<ul>");
+    for heading in headings {
+        let link = format!(
+            "<a href=\"#{}\">{}</a>",
+            html_escape::encode_double_quoted_attribute(&heading.id),
+            html_escape::encode_text(&heading.title)
+        );
+        if heading.children.is_empty() {
+            html.push_str(&format!("<li>{link}</li>"));
+        } else {
+            html.push_str(&format!(
+                "<li><details open><summary>{link}</summary>{}</details></li>",
+                render_outline(&heading.children)
+            ));
+        }
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// Renders one button per open document, plus a close button for each.
+pub fn render_tabs(state: &AppState) -> String {
+    let mut html = String::new();
+    for (index, document) in state.documents.iter().enumerate() {
+        let class = if index == state.active {
+            "tab active"
+        } else {
+            "tab"
+        };
+        let title = html_escape::encode_text(&document.title);
+        html.push_str(&format!(
+            "<span class=\"{class}\"><button onclick=\"appCmd('tab:{index}')\">{title}</button>\
+             <button class=\"tab-close\" onclick=\"appCmd('close-tab:{index}')\" \
+             title=\"Close tab (Ctrl+W)\">&times;</button></span>"
+        ));
+    }
+    html
+}
+
+/// Theme picker for the top bar; option `i` selects `AppState::themes[i]`.
+pub fn render_theme_picker(state: &AppState) -> String {
+    let active = state.theme_index();
+    let mut html =
+        String::from("<select title=\"Theme\" onchange=\"appCmd('theme:' + this.value)\">");
+    for (index, theme) in state.themes.iter().enumerate() {
+        let selected = if active == Some(index) {
+            " selected"
+        } else {
+            ""
+        };
+        html.push_str(&format!(
+            "<option value=\"{index}\"{selected}>{}</option>",
+            html_escape::encode_text(&theme.name)
+        ));
+    }
+    html.push_str("</select>");
+    html
+}
+
+/// Open Recent picker for the top bar; option `i` opens
+/// `AppState::recent_files()[i]`. Empty when there are no recent files.
+pub fn render_recent_picker(state: &AppState) -> String {
+    let recent = state.recent_files();
+    if recent.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from(
+        "<select title=\"Open Recent\" \
+         onchange=\"appCmd('recent:' + this.value); this.selectedIndex = 0\">\
+         <option value=\"\" selected disabled>Open Recent</option>",
+    );
+    for (index, path) in recent.iter().enumerate() {
+        html.push_str(&format!(
+            "<option value=\"{index}\" title=\"{}\">{}</option>",
+            html_escape::encode_double_quoted_attribute(&path.to_string_lossy()),
+            html_escape::encode_text(&core::filename_or_path(path))
+        ));
+    }
+    html.push_str("</select>");
+    html
+}
+
+/// Reopen with Encoding picker for the top bar; option `i` rereads the
+/// active file as `encoding::reopen_encodings()[i]`. Empty unless the
+/// active document is a file that loaded.
+pub fn render_encoding_picker(document: Option<&Document>) -> String {
+    if !document.is_some_and(|d| d.is_loaded() && d.source_path().is_some()) {
+        return String::new();
+    }
+    let mut html = String::from(
+        "<select title=\"Reopen with Encoding\" \
+         onchange=\"appCmd('encoding:' + this.value); this.selectedIndex = 0\">\
+         <option value=\"\" selected disabled>Encoding</option>",
+    );
+    for (index, encoding) in encoding::reopen_encodings().iter().enumerate() {
+        html.push_str(&format!(
+            "<option value=\"{index}\">{}</option>",
+            encoding.name()
+        ));
+    }
+    html.push_str("</select>");
+    html
+}
+
+/// Banner naming the encoding `document` was decoded with; empty for
+/// clean UTF-8.
+pub fn render_encoding_banner(document: Option<&Document>) -> String {
+    document
+        .and_then(|d| d.encoding.notice())
+        .map_or_else(String::new, |notice| {
+            format!(
+                "<div class=\"encoding-banner\">{}</div>",
+                html_escape::encode_text(&notice)
+            )
+        })
+}
+
+/// Loading indicator with a Cancel button; empty when nothing is loading.
+pub fn render_loading_banner(state: &AppState) -> String {
+    state.loading_message().map_or_else(String::new, |message| {
+        format!(
+            "<div class=\"loading-banner\">{} \
+                 <button onclick=\"appCmd('cancel-load')\">Cancel</button></div>",
+            html_escape::encode_text(&message)
+        )
+    })
+}
+
+/// Event for a message the page posts: a command, or `scrolled:N` with
+/// the page's scroll offset.
+pub fn parse_page_message(message: &str) -> Option<AppEvent> {
+    match message.strip_prefix("scrolled:") {
+        Some(offset) => offset.parse().ok().map(AppEvent::Scrolled),
+        None => keymap::parse_app_event(message),
+    }
+}
+
+/// Banner offering to load the remote images blocked in `document`; empty
+/// when nothing was blocked.
+pub fn render_remote_banner(document: Option<&Document>) -> String {
+    document
+        .and_then(Document::blocked_remote_message)
+        .map_or_else(String::new, |message| {
+            format!(
+                "<div class=\"remote-banner\">{} \
+                 <button onclick=\"appCmd('allow-remote')\">Load remote images</button></div>",
+                html_escape::encode_text(&message)
+            )
+        })
+}
+
+pub fn asset_base_uri(document_path: &Path) -> Option<String> {
+    core::document_directory_url_path(document_path)
+        .map(|url_path| format!("http://{ASSET_PROTOCOL}.localhost{url_path}"))
+}
+
+/// Markdown link behind a navigation to `url`, when it targets a local
+/// Markdown file served through the asset protocol.
+pub fn asset_markdown_link(url: &str) -> Option<MarkdownLink> {
+    let url_path = url
+        .strip_prefix("http://")
+        .and_then(|rest| rest.strip_prefix(ASSET_PROTOCOL))
+        .and_then(|rest| rest.strip_prefix(".localhost"))
+        .filter(|url_path| url_path.starts_with('/'))?;
+    core::markdown_link_from_url_path(url_path)
+}
+
+/// Navigation the viewer handles itself instead of letting WebView2 leave
+/// the shell page; `None` lets it proceed.
+pub fn intercepted_navigation(url: &str) -> Option<LinkTarget> {
+    if let Some(link) = asset_markdown_link(url) {
+        return Some(LinkTarget::Markdown(link));
+    }
+    // `load_html` navigates to the shell page itself.
+    if url.starts_with("about:") || url.starts_with("data:text/html") {
+        return None;
+    }
+    // Other local files served for the document are never shown on their own.
+    if url.starts_with(&format!("http://{ASSET_PROTOCOL}.localhost/")) {
+        return Some(LinkTarget::Blocked(url.to_string()));
+    }
+    match core::classify_link(url, None) {
+        LinkTarget::SameDocument => None,
+        target => Some(target),
+    }
+}
+
+pub fn asset_path_from_url_path(url_path: &str) -> PathBuf {
+    core::path_from_url_path(url_path)
+}
+
+/// Content type of an image the asset protocol may serve; `None` for any
+/// other file, which it refuses.
+pub fn asset_content_type(path: &Path) -> Option<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => Some("image/png"),
+        Some("jpg" | "jpeg") => Some("image/jpeg"),
+        Some("gif") => Some("image/gif"),
+        Some("svg") => Some("image/svg+xml"),
+        Some("webp") => Some("image/webp"),
+        Some("bmp") => Some("image/bmp"),
+        Some("ico") => Some("image/x-icon"),
+        _ => None,
+    }
+}
+
+/// Whether `path` is inside `root`, the active document's directory, once
+/// `..` and links are resolved. A missing file is not. The renderer already
+/// drops image sources that climb out of the document's folder on both
+/// frontends; this also catches links pointing outside it.
+pub fn asset_within(path: &Path, root: &Path) -> bool {
+    match (path.canonicalize(), root.canonicalize()) {
+        (Ok(path), Ok(root)) => path.starts_with(root),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::LoadOptions;
+    use crate::test_support::TempDir;
+    use crate::theme::Theme;
 
     #[test]
-    fn parse_app_event_maps_known_actions() {
-        assert_eq!(parse_app_event("open"), Some(AppEvent::Open));
-        assert_eq!(parse_app_event("close"), Some(AppEvent::Close));
-        assert_eq!(parse_app_event("theme"), Some(AppEvent::ToggleTheme));
-        assert_eq!(parse_app_event("quit"), Some(AppEvent::Quit));
-        assert_eq!(parse_app_event("unknown"), None);
+    fn shortcut_script_lists_sequences_and_their_prefixes() {
+        let mut keymap = Keymap::default();
+        keymap.rebind(
+            "theme",
+            vec![keymap::parse_sequence("Ctrl+K Ctrl+T").unwrap()],
+        );
+        let script = shortcut_script(&keymap);
+
+        assert!(script.contains(r#""ctrl+shift+tab": "previous-tab""#));
+        assert!(script.contains(r#""alt+left": "back""#));
+        assert!(script.contains(r#""ctrl+k ctrl+t": "theme""#));
+        assert!(script.contains(r#""g g": "top""#));
+        assert!(script.contains(r#"new Set(["g", "]", "[", "ctrl+k"])"#));
+        assert!(!script.contains(r#""ctrl+d""#));
     }
 
     #[test]
@@ -53,4 +347,255 @@ mod tests {
 
         assert_eq!(extract_document_body(malformed), "missing close");
     }
+
+    #[test]
+    fn render_outline_nests_children_in_collapsible_details() {
+        let heading = |title: &str, id: &str, level, children| Heading {
+            level,
+            title: title.to_string(),
+            id: id.to_string(),
+            line: 1,
+            children,
+        };
+        let outline = vec![
+            heading("Intro <1>", "intro-1", 1, Vec::new()),
+            heading(
+                "Setup",
+                "setup",
+                1,
+                vec![heading("Linux", "linux", 2, Vec::new())],
+            ),
+        ];
+
+        assert_eq!(
+            render_outline(&outline),
+            "<ul><li><a href=\"#intro-1\">Intro &lt;1&gt;</a></li>\
+             <li><details open><summary><a href=\"#setup\">Setup</a></summary>\
+             <ul><li><a href=\"#linux\">Linux</a></li></ul></details></li></ul>"
+        );
+        assert_eq!(render_outline(&[]), "");
+    }
+
+    #[test]
+    fn render_tabs_marks_active_tab_and_escapes_titles() {
+        let renderer = core::Renderer::new();
+        let mut state = AppState::new(Theme::light());
+        state.open_document(core::Document::from_markdown(
+            Path::new("/tmp/a<b>.md"),
+            "# A".to_string(),
+            &renderer,
+            &Theme::light(),
+        ));
+        state.open_document(core::Document::open_error(
+            Path::new("/tmp/b.md"),
+            "missing",
+        ));
+
+        let html = render_tabs(&state);
+
+        assert!(html.starts_with(
+            "<span class=\"tab\"><button onclick=\"appCmd('tab:0')\">a&lt;b&gt;.md</button>"
+        ));
+        assert!(html.contains(
+            "<span class=\"tab active\"><button onclick=\"appCmd('tab:1')\">b.md</button>"
+        ));
+        assert!(html.contains("appCmd('close-tab:1')"));
+        assert_eq!(render_tabs(&AppState::new(Theme::light())), "");
+    }
+
+    #[test]
+    fn asset_path_from_url_path_decodes_unix_and_drive_paths() {
+        assert_eq!(
+            asset_path_from_url_path("/home/me/my%20docs/img/a.png"),
+            PathBuf::from("/home/me/my docs/img/a.png")
+        );
+        assert_eq!(
+            asset_path_from_url_path("/C:/Users/me/docs/img/a.png"),
+            PathBuf::from("C:/Users/me/docs/img/a.png")
+        );
+        assert_eq!(
+            asset_path_from_url_path("/bad%zz"),
+            PathBuf::from("/bad%zz")
+        );
+    }
+
+    #[test]
+    fn render_theme_picker_lists_themes_and_selects_the_current_one() {
+        let mut state = AppState::new(Theme::dark());
+        let mut custom = Theme::light();
+        custom.name = "<Paper>".to_string();
+        state.themes.push(custom);
+
+        assert_eq!(
+            render_theme_picker(&state),
+            "<select title=\"Theme\" onchange=\"appCmd('theme:' + this.value)\">\
+             <option value=\"0\">Light</option><option value=\"1\" selected>Dark</option>\
+             <option value=\"2\">&lt;Paper&gt;</option></select>"
+        );
+    }
+
+    #[test]
+    fn render_recent_picker_lists_existing_recent_files() {
+        let dir = TempDir::new("recent");
+        let notes = dir.join("a&b.md");
+        std::fs::write(&notes, "# Notes").unwrap();
+        let mut state = AppState::new(Theme::light());
+        assert_eq!(render_recent_picker(&state), "");
+
+        state.recent = vec![dir.join("gone.md"), notes.clone()];
+        let picker = render_recent_picker(&state);
+
+        assert!(picker.contains("appCmd('recent:' + this.value)"));
+        assert!(picker.contains(&format!(
+            "<option value=\"0\" title=\"{}\">a&amp;b.md</option>",
+            html_escape::encode_double_quoted_attribute(&notes.to_string_lossy())
+        )));
+        assert!(!picker.contains("gone.md"));
+    }
+
+    #[test]
+    fn parse_page_message_reads_scroll_reports_and_commands() {
+        assert_eq!(
+            parse_page_message("scrolled:640"),
+            Some(AppEvent::Scrolled(640))
+        );
+        assert_eq!(parse_page_message("scrolled:-1"), None);
+        assert_eq!(
+            parse_page_message("recent:0"),
+            Some(AppEvent::OpenRecent(0))
+        );
+        assert_eq!(parse_page_message("open"), Some(AppEvent::Open));
+    }
+
+    #[test]
+    fn render_remote_banner_appears_only_when_images_were_blocked() {
+        let renderer = core::Renderer::new();
+        let document = |markdown: &str| {
+            Document::from_markdown(
+                Path::new("/tmp/a.md"),
+                markdown.to_string(),
+                &renderer,
+                &Theme::light(),
+            )
+        };
+
+        let banner = render_remote_banner(Some(&document("![a](https://x.test/a.png)")));
+        assert!(banner.contains("1 remote image was blocked"));
+        assert!(banner.contains("appCmd('allow-remote')"));
+        assert_eq!(render_remote_banner(Some(&document("![a](a.png)"))), "");
+        assert_eq!(render_remote_banner(None), "");
+    }
+
+    #[test]
+    fn encoding_picker_and_banner_follow_the_active_document() {
+        let dir = TempDir::new("picker");
+        let path = dir.join("legacy.md");
+        std::fs::write(&path, b"# Caf\xE9 cr\xE8me, na\xEFve fa\xE7ade").unwrap();
+        let legacy = Document::load(&path, &core::Renderer::new(), &Theme::light());
+        let piped = Document::from_source(
+            core::DocumentSource::Stdin,
+            "# Piped".to_string(),
+            &core::Renderer::new(),
+            &Theme::light(),
+        );
+
+        let picker = render_encoding_picker(Some(&legacy));
+        assert!(picker.contains("appCmd('encoding:' + this.value)"));
+        assert!(picker.contains("<option value=\"3\">windows-1252</option>"));
+        assert!(render_encoding_banner(Some(&legacy)).contains("Shown as windows-1252"));
+        assert_eq!(render_encoding_picker(Some(&piped)), "");
+        assert_eq!(render_encoding_banner(Some(&piped)), "");
+        assert_eq!(render_encoding_picker(None), "");
+    }
+
+    #[test]
+    fn render_loading_banner_offers_to_cancel_while_loading() {
+        let mut state = AppState::new(Theme::light());
+        assert_eq!(render_loading_banner(&state), "");
+
+        state.start_load(Path::new("/tmp/<big>.log"), LoadOptions::default());
+        let banner = render_loading_banner(&state);
+        assert!(banner.contains("Loading &lt;big&gt;.log…"));
+        assert!(banner.contains("appCmd('cancel-load')"));
+    }
+
+    #[test]
+    fn asset_markdown_link_accepts_only_local_markdown_assets() {
+        assert_eq!(
+            asset_markdown_link("http://dustrown.localhost/C:/docs/design.md#usage"),
+            Some(MarkdownLink {
+                path: PathBuf::from("C:/docs/design.md"),
+                fragment: Some("usage".to_string()),
+            })
+        );
+        assert_eq!(
+            asset_markdown_link("http://dustrown.localhost/C:/docs/a.png"),
+            None
+        );
+        assert_eq!(asset_markdown_link("http://example.com/design.md"), None);
+        assert_eq!(
+            asset_markdown_link("http://dustrown.localhost.evil.com/design.md"),
+            None
+        );
+    }
+
+    #[test]
+    fn intercepted_navigation_lets_only_the_shell_load() {
+        assert_eq!(intercepted_navigation("about:blank"), None);
+        assert_eq!(
+            intercepted_navigation("http://dustrown.localhost/C:/docs/a.md"),
+            Some(LinkTarget::Markdown(MarkdownLink {
+                path: PathBuf::from("C:/docs/a.md"),
+                fragment: None,
+            }))
+        );
+        assert_eq!(
+            intercepted_navigation("http://dustrown.localhost/C:/docs/run.bat"),
+            Some(LinkTarget::Blocked(
+                "http://dustrown.localhost/C:/docs/run.bat".to_string()
+            ))
+        );
+        assert_eq!(
+            intercepted_navigation("https://example.com/"),
+            Some(LinkTarget::External("https://example.com/".to_string()))
+        );
+        assert_eq!(
+            intercepted_navigation("ms-settings:privacy"),
+            Some(LinkTarget::Blocked("ms-settings:privacy".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn asset_base_uri_uses_protocol_host_and_document_directory() {
+        assert_eq!(
+            asset_base_uri(Path::new("/home/me/README.md")).as_deref(),
+            Some("http://dustrown.localhost/home/me/")
+        );
+    }
+
+    #[test]
+    fn asset_content_type_maps_common_image_extensions() {
+        assert_eq!(asset_content_type(Path::new("a.PNG")), Some("image/png"));
+        assert_eq!(
+            asset_content_type(Path::new("a.svg")),
+            Some("image/svg+xml")
+        );
+        assert_eq!(asset_content_type(Path::new("a")), None);
+        assert_eq!(asset_content_type(Path::new("notes.md")), None);
+    }
+
+    #[test]
+    fn asset_within_keeps_to_the_document_directory() {
+        let dir = TempDir::new("assets");
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(docs.join("img")).unwrap();
+        std::fs::write(docs.join("img/a.png"), "png").unwrap();
+        std::fs::write(dir.join("secret.png"), "png").unwrap();
+
+        assert!(asset_within(&docs.join("img/a.png"), &docs));
+        assert!(!asset_within(&docs.join("../secret.png"), &docs));
+        assert!(!asset_within(&dir.join("secret.png"), &docs));
+        assert!(!asset_within(&docs.join("img/missing.png"), &docs));
+    }
 }